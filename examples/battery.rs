@@ -12,10 +12,13 @@ fn main() {
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
 
     let mut device = Device::new(0x046d, 0xc547).unwrap();
-    device.init();
 
-    let (percentage, level, status) = device.get_battery().unwrap();
-    println!("Battery: {}%", percentage);
-    println!("Level: {:?}", level);
-    println!("Status: {:?}", status);
+    let battery = device.get_battery().unwrap();
+    if battery.estimated {
+        println!("Battery: ~{}%", battery.percentage);
+    } else {
+        println!("Battery: {}%", battery.percentage);
+    }
+    println!("Level: {:?}", battery.level);
+    println!("Status: {:?}", battery.status);
 }
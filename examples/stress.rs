@@ -0,0 +1,22 @@
+use std::sync::{Arc, Mutex};
+
+use hidpp::{
+    stress::{self, StressConfig},
+    Device,
+};
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
+
+fn main() {
+    let subscriber = FmtSubscriber::builder()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .or_else(|_| EnvFilter::try_new("info"))
+                .unwrap(),
+        )
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let device = Device::new(0x046d, 0xc547).unwrap();
+    let report = stress::run(Arc::new(Mutex::new(device)), StressConfig::default());
+    println!("{:#?}", report);
+}
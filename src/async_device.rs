@@ -0,0 +1,105 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use tokio::{sync::mpsc, task};
+
+use crate::{BatteryInfo, Device, Event, Feature, Function, Message};
+
+// how long the event reader holds the device before letting requests in
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// A device for async code. hidapi only has blocking reads, so every request
+// runs on tokio's blocking pool instead of the async workers, and clones
+// share the device, taking turns between requests.
+//
+//   let device = AsyncDevice::open(0x046d, 0xc547).await?;
+//   let battery = device.get_battery().await?;
+#[derive(Clone)]
+pub struct AsyncDevice {
+    device: Arc<Mutex<Device>>,
+}
+
+impl AsyncDevice {
+    pub fn new(device: Device) -> Self {
+        Self {
+            device: Arc::new(Mutex::new(device)),
+        }
+    }
+
+    pub async fn open(vendor_id: u16, product_id: u16) -> anyhow::Result<Self> {
+        let device = task::spawn_blocking(move || Device::new(vendor_id, product_id)).await??;
+        Ok(Self::new(device))
+    }
+
+    // Runs `f` with the device on the blocking pool, for anything without
+    // an async wrapper
+    pub async fn run<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Device) -> anyhow::Result<T> + Send + 'static,
+    {
+        let device = self.device.clone();
+        task::spawn_blocking(move || {
+            let mut device = device
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Device lock poisoned"))?;
+            f(&mut device)
+        })
+        .await?
+    }
+
+    pub async fn init(&self) -> anyhow::Result<()> {
+        self.run(|device| device.init()).await
+    }
+
+    pub async fn send_feature(
+        &self,
+        feature: Feature,
+        function: Function,
+        payload: &[u8],
+    ) -> anyhow::Result<Message> {
+        let payload = payload.to_vec();
+        self.run(move |device| device.send_feature(feature, function, &payload))
+            .await
+    }
+
+    pub async fn get_battery(&self) -> anyhow::Result<BatteryInfo> {
+        self.run(|device| device.get_battery()).await
+    }
+
+    pub async fn next_event(&self, timeout: Duration) -> anyhow::Result<Option<Event>> {
+        self.run(move |device| device.next_event(timeout)).await
+    }
+
+    // Notifications as they arrive, until the receiver is dropped or a read
+    // fails. Wrap it in tokio-stream's `ReceiverStream` for a `Stream`. The
+    // reader lets go of the device after every read, so requests sent in the
+    // meantime wait at most one poll interval even while events keep coming.
+    pub async fn events(&self) -> mpsc::Receiver<anyhow::Result<Event>> {
+        let (sender, events) = mpsc::channel(64);
+        let device = self.device.clone();
+        task::spawn_blocking(move || {
+            while !sender.is_closed() {
+                let event = match device.lock() {
+                    Ok(mut device) => device.next_event(EVENT_POLL_INTERVAL),
+                    Err(_) => Err(anyhow::anyhow!("Device lock poisoned")),
+                };
+                // the lock isn't fair, give waiting requests a chance
+                thread::sleep(Duration::from_millis(1));
+                let event = match event {
+                    Ok(Some(event)) => Ok(event),
+                    Ok(None) => continue,
+                    Err(err) => Err(err),
+                };
+                let failed = event.is_err();
+                if sender.blocking_send(event).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        events
+    }
+}
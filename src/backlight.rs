@@ -0,0 +1,109 @@
+use anyhow::bail;
+
+use crate::{Device, Feature, Function};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum BacklightMode {
+    // on/off as decided by the device
+    Basic,
+    // driven by the ambient light sensor
+    Automatic,
+    // fixed level set by software
+    Manual,
+    Unknown(u8),
+}
+
+impl From<u8> for BacklightMode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => BacklightMode::Basic,
+            0x01 => BacklightMode::Automatic,
+            0x03 => BacklightMode::Manual,
+            value => BacklightMode::Unknown(value),
+        }
+    }
+}
+
+impl BacklightMode {
+    fn value(&self) -> u8 {
+        match self {
+            BacklightMode::Basic => 0x00,
+            BacklightMode::Automatic => 0x01,
+            BacklightMode::Manual => 0x03,
+            BacklightMode::Unknown(value) => *value,
+        }
+    }
+}
+
+// Backlight2 (0x1982) configuration, as returned by getBacklightConfig and
+// carried by backlight change events
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BacklightConfig {
+    pub enabled: bool,
+    pub mode: BacklightMode,
+    pub level: u8,
+    // raw option bits, mode excluded
+    pub options: u8,
+    // capability bits: 0x08 automatic, 0x10 temporary, 0x20 permanent
+    pub supported: u8,
+    pub effects: u16,
+    // fade out durations, in 5 second steps: hands out, hands in, powered
+    pub duration_hands_out: u16,
+    pub duration_hands_in: u16,
+    pub duration_powered: u16,
+}
+
+impl TryFrom<&[u8]> for BacklightConfig {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < 12 {
+            bail!("Backlight config too short: {} bytes", data.len());
+        }
+
+        Ok(Self {
+            enabled: data[0] != 0,
+            mode: BacklightMode::from((data[1] >> 3) & 0x03),
+            level: data[5],
+            options: data[1] & 0x07,
+            supported: data[2],
+            effects: u16::from_le_bytes([data[3], data[4]]),
+            duration_hands_out: u16::from_le_bytes([data[6], data[7]]),
+            duration_hands_in: u16::from_le_bytes([data[8], data[9]]),
+            duration_powered: u16::from_le_bytes([data[10], data[11]]),
+        })
+    }
+}
+
+impl Device {
+    pub fn get_backlight(&mut self) -> anyhow::Result<BacklightConfig> {
+        let result = self.send_feature(Feature::Backlight2, Function::Backlight2GetConfig, &[])?;
+        BacklightConfig::try_from(result.data.as_slice())
+    }
+
+    pub fn set_backlight(&mut self, config: &BacklightConfig) -> anyhow::Result<()> {
+        // the level only applies in manual mode
+        let level = match config.mode {
+            BacklightMode::Manual => config.level,
+            _ => 0,
+        };
+        let mut payload = vec![
+            config.enabled as u8,
+            config.options & 0x07 | config.mode.value() << 3,
+            0xFF,
+            level,
+        ];
+        payload.extend_from_slice(&config.duration_hands_out.to_le_bytes());
+        payload.extend_from_slice(&config.duration_hands_in.to_le_bytes());
+        payload.extend_from_slice(&config.duration_powered.to_le_bytes());
+
+        self.send_feature(Feature::Backlight2, Function::Backlight2SetConfig, &payload)?;
+        Ok(())
+    }
+
+    // number of brightness levels available in manual mode
+    pub fn backlight_levels(&mut self) -> anyhow::Result<u8> {
+        let result = self.send_feature(Feature::Backlight2, Function::Backlight2GetInfo, &[])?;
+        Ok(result.data[0])
+    }
+}
@@ -0,0 +1,86 @@
+use std::{fmt, iter, time::Duration};
+
+use crate::Clock;
+
+// Retry policy for opening a device. Delays grow exponentially up to
+// `max_delay` and attempts stop once `budget` has elapsed, however many
+// there were. Retrying never sleeps past the budget.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub factor: f64,
+    pub max_delay: Duration,
+    pub budget: Duration,
+    // randomize each delay between zero and its nominal value, so processes
+    // reopening the same device don't retry in lockstep
+    pub jitter: bool,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(10),
+            factor: 2.0,
+            max_delay: Duration::from_millis(250),
+            budget: Duration::from_millis(500),
+            jitter: true,
+        }
+    }
+}
+
+impl Backoff {
+    // Calls `attempt` with the attempt number, from 1, until it succeeds or
+    // the budget has elapsed on `clock`, sleeping on it in between. Returns
+    // the last error.
+    pub fn retry<T, E: fmt::Display>(
+        &self,
+        clock: &dyn Clock,
+        mut attempt: impl FnMut(usize) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let start = clock.now();
+        let mut delays = self.delays();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let err = match attempt(attempts) {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            let elapsed = clock.now().duration_since(start);
+            if elapsed >= self.budget {
+                crate::tracing::debug!("Giving up after {} attempts: {}", attempts, err);
+                return Err(err);
+            }
+            crate::tracing::debug!("Attempt {} failed: {}", attempts, err);
+            // the last attempt is made when the budget runs out, not after
+            let delay = delays.next().unwrap_or(self.max_delay);
+            clock.sleep(delay.min(self.budget - elapsed));
+        }
+    }
+
+    pub(crate) fn delays(&self) -> impl Iterator<Item = Duration> {
+        let (factor, max_delay) = (self.factor, self.max_delay);
+        let with_jitter = self.jitter;
+        iter::successors(Some(self.initial.min(max_delay)), move |delay| {
+            Some(delay.mul_f64(factor).min(max_delay))
+        })
+        .map(move |delay| if with_jitter { jitter(delay) } else { delay })
+    }
+}
+
+#[cfg(feature = "retry")]
+fn jitter(delay: Duration) -> Duration {
+    retry::delay::jitter(delay)
+}
+
+// a uniformly random duration up to `delay`, seeded by the random keys std
+// gives each hasher
+#[cfg(not(feature = "retry"))]
+fn jitter(delay: Duration) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    delay.mul_f64(random as f64 / u64::MAX as f64)
+}
@@ -0,0 +1,62 @@
+use std::{thread, time::Duration};
+
+use clap::Parser;
+use hidpp::{sim::Simulator, uhid::VirtualDevice};
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
+
+#[derive(Parser)]
+#[command(about = "Emulate a HID++ 2.0 mouse through /dev/uhid")]
+struct Cli {
+    #[arg(long, value_parser = parse_hex, default_value = "046d")]
+    vid: u16,
+
+    #[arg(long, value_parser = parse_hex, default_value = "c5ff")]
+    pid: u16,
+
+    /// Initial battery percentage
+    #[arg(long, default_value_t = 80)]
+    battery: u8,
+
+    /// Drain the battery by 1% at this interval, in seconds, sending a battery event each time
+    #[arg(long)]
+    drain: Option<u64>,
+}
+
+fn parse_hex(s: &str) -> Result<u16, String> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|e| e.to_string())
+}
+
+fn main() -> anyhow::Result<()> {
+    let subscriber = FmtSubscriber::builder()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .or_else(|_| EnvFilter::try_new("info"))
+                .unwrap(),
+        )
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let cli = Cli::parse();
+    let mut simulator = Simulator::new();
+    simulator.battery_percentage = cli.battery;
+
+    let device = VirtualDevice::create(cli.vid, cli.pid, simulator)?;
+    tracing::info!("Created virtual device {:04x}:{:04x}", cli.vid, cli.pid);
+
+    loop {
+        match cli.drain {
+            Some(interval) => {
+                thread::sleep(Duration::from_secs(interval));
+                let event = {
+                    let simulator = device.simulator();
+                    let mut simulator = simulator.lock().unwrap();
+                    let percentage = simulator.battery_percentage.saturating_sub(1);
+                    simulator.set_battery(percentage, false)
+                };
+                tracing::info!("Battery drained to {}%", event[4]);
+                device.send_input(&event)?;
+            }
+            None => thread::park(),
+        }
+    }
+}
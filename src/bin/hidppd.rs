@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap,
+    os::unix::net::UnixDatagram,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use hidpp::{config::Config, Device, DeviceManager, Event};
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
+
+#[derive(Parser)]
+#[command(about = "Keep Logitech HID++ devices configured as they come and go")]
+struct Cli {
+    /// Config file, defaults to $XDG_CONFIG_HOME/hidpp/config.json
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    TERMINATE.store(true, Ordering::Relaxed);
+}
+
+// Sends a state update to systemd when running as a Type=notify service
+fn sd_notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let result = UnixDatagram::unbound().and_then(|socket| {
+        let path = path.to_string_lossy();
+        // a leading @ means a socket in the abstract namespace
+        #[cfg(target_os = "linux")]
+        if let Some(name) = path.strip_prefix('@') {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            return socket.send_to_addr(state.as_bytes(), &addr);
+        }
+        socket.send_to(state.as_bytes(), path.as_ref())
+    });
+    if let Err(err) = result {
+        tracing::warn!("Failed to notify systemd: {}", err);
+    }
+}
+
+// Applies the configured settings and stores a new configuration cookie so
+// later changes made by other software can be told apart from ours. Returns
+// the cookie, if the device supports Config Change.
+fn apply(config: &Config, vid: u16, pid: u16, device: &mut Device) -> Option<u16> {
+    let settings = config.device_for(device)?;
+    match settings.apply(device) {
+        Ok(()) => tracing::info!("Applied settings to {:04x}:{:04x}", vid, pid),
+        Err(err) => tracing::warn!(
+            "Failed to apply settings to {:04x}:{:04x}: {}",
+            vid,
+            pid,
+            err
+        ),
+    }
+
+    let cookie = device.config_cookie().ok()?.wrapping_add(1);
+    device.set_config_cookie(cookie).ok()?;
+    Some(cookie)
+}
+
+fn main() -> anyhow::Result<()> {
+    let subscriber = FmtSubscriber::builder()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .or_else(|_| EnvFilter::try_new("info"))
+                .unwrap(),
+        )
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let cli = Cli::parse();
+    let path = match cli.config.or_else(Config::default_path) {
+        Some(path) => path,
+        None => anyhow::bail!("No config file given and no home directory to look in"),
+    };
+    let config = Config::load(&path)?;
+    tracing::info!(
+        "Loaded {} device(s) from {}",
+        config.devices.len(),
+        path.display()
+    );
+
+    // SAFETY: the handler only stores to an atomic
+    unsafe {
+        libc::signal(libc::SIGTERM, on_signal as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGINT, on_signal as *const () as libc::sighandler_t);
+    }
+
+    let mut manager = DeviceManager::new();
+    for settings in &config.devices {
+        manager.add(settings.vid, settings.pid);
+    }
+
+    sd_notify("READY=1");
+
+    let reconnect_interval = Duration::from_secs(config.reconnect_interval);
+    let mut last_connect: Option<Instant> = None;
+    // configuration cookie we last stored on each device
+    let mut cookies = HashMap::new();
+    while !TERMINATE.load(Ordering::Relaxed) {
+        if last_connect.is_none_or(|last| last.elapsed() >= reconnect_interval) {
+            last_connect = Some(Instant::now());
+            for id in manager.connect() {
+                let device = manager.get_mut(id).unwrap();
+                let (vid, pid) = (device.vendor_id(), device.product_id());
+                if let Some(cookie) = apply(&config, vid, pid, device) {
+                    cookies.insert(id, cookie);
+                }
+            }
+        }
+
+        let mut lost = vec![];
+        let mut any_connected = false;
+        for (id, device) in manager.connected() {
+            any_connected = true;
+            let (vid, pid) = (device.vendor_id(), device.product_id());
+            match device.next_event(Duration::from_millis(100)) {
+                Ok(Some(Event::ConfigChanged { cookie })) if cookies.get(&id) != Some(&cookie) => {
+                    tracing::info!("{:04x}:{:04x} reconfigured elsewhere", vid, pid);
+                    if let Some(cookie) = apply(&config, vid, pid, device) {
+                        cookies.insert(id, cookie);
+                    }
+                }
+                // a device behind a receiver forgets its settings while
+                // it's switched off or out of range
+                Ok(Some(
+                    Event::Link {
+                        established: true, ..
+                    }
+                    | Event::PoweredOn,
+                )) => {
+                    tracing::info!("{:04x}:{:04x} came back", vid, pid);
+                    if let Some(cookie) = apply(&config, vid, pid, device) {
+                        cookies.insert(id, cookie);
+                    }
+                }
+                Ok(Some(event)) => tracing::debug!("{:04x}:{:04x} {:?}", vid, pid, event),
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::warn!("Lost {:04x}:{:04x}: {}", vid, pid, err);
+                    lost.push(id);
+                }
+            }
+        }
+        for id in lost {
+            manager.disconnect(id);
+        }
+        if !any_connected {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    sd_notify("STOPPING=1");
+    tracing::info!("Shutting down");
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use std::{ffi::CString, sync::Arc, time::Duration};
+
+use crate::{Backoff, Clock, Device, OverflowPolicy, Quirks, RateLimiter, SystemClock, Transport};
+
+// Options for opening a device, so new ones can be added without yet
+// another constructor:
+//
+//   Device::builder().vid(0x046d).pid(0xc547).timeout(Duration::from_millis(500)).open()
+pub struct DeviceBuilder {
+    pub(crate) vendor_id: Option<u16>,
+    pub(crate) product_id: Option<u16>,
+    pub(crate) path: Option<CString>,
+    pub(crate) transport: Option<Box<dyn Transport>>,
+    pub(crate) device_index: Option<u8>,
+    pub(crate) timeout: Duration,
+    pub(crate) backoff: Backoff,
+    pub(crate) quirks: Option<Quirks>,
+    pub(crate) strict: bool,
+    pub(crate) rate_limiter: Option<RateLimiter>,
+    pub(crate) event_capacity: usize,
+    pub(crate) overflow_policy: OverflowPolicy,
+    pub(crate) clock: Arc<dyn Clock>,
+}
+
+impl Default for DeviceBuilder {
+    fn default() -> Self {
+        Self {
+            vendor_id: None,
+            product_id: None,
+            path: None,
+            transport: None,
+            // index 1, a receiver's first slot, unless the quirks ask for
+            // 0xFF. Discovery opens wired and Bluetooth devices on 0xFF.
+            device_index: None,
+            timeout: Duration::from_millis(100),
+            backoff: Backoff::default(),
+            quirks: None,
+            strict: false,
+            rate_limiter: None,
+            event_capacity: 1024,
+            overflow_policy: OverflowPolicy::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl DeviceBuilder {
+    pub fn vid(mut self, vendor_id: u16) -> Self {
+        self.vendor_id = Some(vendor_id);
+        self
+    }
+
+    pub fn pid(mut self, product_id: u16) -> Self {
+        self.product_id = Some(product_id);
+        self
+    }
+
+    // Opens this hidraw/IOKit path instead of the first device matching the
+    // ids, to pick one of several identical devices
+    pub fn path(mut self, path: impl Into<Vec<u8>>) -> anyhow::Result<Self> {
+        self.path = Some(CString::new(path)?);
+        Ok(self)
+    }
+
+    // Talks to the device through `transport` instead of opening it with
+    // hidapi. The ids are optional then, they only select quirks.
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Box::new(transport));
+        self
+    }
+
+    // Index of the device behind the HID handle, 1-6 for receiver slots
+    pub fn device_index(mut self, device_index: u8) -> Self {
+        self.device_index = Some(device_index);
+        self
+    }
+
+    // How long to wait for each reply
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // Retry policy for opening and reconnecting
+    pub fn retry(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    // Overrides the quirks looked up from the ids
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    // Fails on replies with reserved bits set instead of ignoring them, to
+    // check new firmware against the spec
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    // Time source for retries and deadlines, a `ManualClock` in tests
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Bounds the notifications queued while waiting for replies, e.g. to keep
+    // a fast raw XY stream from piling up when events are read slowly
+    pub fn event_queue(mut self, capacity: usize, policy: OverflowPolicy) -> Self {
+        self.event_capacity = capacity;
+        self.overflow_policy = policy;
+        self
+    }
+
+    pub fn open(self) -> anyhow::Result<Device> {
+        Device::from_builder(self)
+    }
+}
@@ -0,0 +1,113 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use crate::{BatteryCapabilities, DpiCapabilities, Feature};
+
+// What the cache holds for a unit and firmware version: the feature table
+// and the capabilities read once after discovery
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct CachedTables {
+    pub(crate) features_index: HashMap<Feature, u8>,
+    pub(crate) dpi_capabilities: HashMap<u8, DpiCapabilities>,
+    pub(crate) battery_capabilities: Option<BatteryCapabilities>,
+}
+
+// Feature tables persisted on disk so repeated invocations against the same
+// unit and firmware can skip discovery. Stored one entry per line, in hex:
+// `<feature id> <index>` pairs, `DPI <sensor> <min>:<max>:<step>...` for
+// each sensor and `BATTERY <levels> <rechargeable> <state of charge>`.
+pub struct FeatureCache {
+    path: PathBuf,
+}
+
+impl FeatureCache {
+    pub fn new(unit_id: [u8; 4], firmware: &str) -> Option<Self> {
+        let unit_id: String = unit_id.iter().map(|b| format!("{:02X}", b)).collect();
+        let firmware: String = firmware
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = cache_dir()?.join(format!("features-{}-{}", unit_id, firmware));
+        Some(Self { path })
+    }
+
+    pub(crate) fn load(&self) -> Option<CachedTables> {
+        let contents = fs::read_to_string(&self.path).ok()?;
+        let mut tables = CachedTables::default();
+        for line in contents.lines() {
+            let mut fields = line.split(' ');
+            match fields.next()? {
+                "DPI" => {
+                    let sensor = u8::from_str_radix(fields.next()?, 16).ok()?;
+                    let mut ranges = vec![];
+                    for range in fields {
+                        let mut values = range.split(':').map(|v| u16::from_str_radix(v, 16));
+                        let (Some(Ok(min)), Some(Ok(max)), Some(Ok(step)), None) =
+                            (values.next(), values.next(), values.next(), values.next())
+                        else {
+                            return None;
+                        };
+                        ranges.push((min, max, step));
+                    }
+                    tables
+                        .dpi_capabilities
+                        .insert(sensor, DpiCapabilities { sensor, ranges });
+                }
+                "BATTERY" => {
+                    let supported_levels = u8::from_str_radix(fields.next()?, 16).ok()?;
+                    let rechargeable = fields.next()? == "1";
+                    let state_of_charge = fields.next()? == "1";
+                    tables.battery_capabilities = Some(BatteryCapabilities {
+                        supported_levels,
+                        rechargeable,
+                        state_of_charge,
+                    });
+                }
+                id => {
+                    let id = u16::from_str_radix(id, 16).ok()?;
+                    let index = u8::from_str_radix(fields.next()?, 16).ok()?;
+                    if let Some(feature) = Feature::from_value(id) {
+                        tables.features_index.insert(feature, index);
+                    }
+                }
+            }
+        }
+
+        crate::tracing::debug!("Loaded feature table from {}", self.path.display());
+        Some(tables)
+    }
+
+    pub(crate) fn store(&self, tables: &CachedTables) -> anyhow::Result<()> {
+        let mut contents = String::new();
+        for (feature, index) in &tables.features_index {
+            contents.push_str(&format!("{:04X} {:02X}\n", feature.value(), index));
+        }
+        for (sensor, capabilities) in &tables.dpi_capabilities {
+            contents.push_str(&format!("DPI {:02X}", sensor));
+            for (min, max, step) in &capabilities.ranges {
+                contents.push_str(&format!(" {:04X}:{:04X}:{:04X}", min, max, step));
+            }
+            contents.push('\n');
+        }
+        if let Some(battery) = &tables.battery_capabilities {
+            contents.push_str(&format!(
+                "BATTERY {:02X} {} {}\n",
+                battery.supported_levels, battery.rechargeable as u8, battery.state_of_charge as u8
+            ));
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, contents)?;
+        crate::tracing::debug!("Stored feature table in {}", self.path.display());
+        Ok(())
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(base.join("hidpp"))
+}
@@ -0,0 +1,51 @@
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+// Lets another thread, typically a UI, stop a long-running operation. Clones
+// share the same state, so cancelling one cancels them all. Operations check
+// the token between device transactions, so a cancelled operation never
+// leaves a request half sent.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    state: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        let (cancelled, condvar) = &*self.state;
+        *cancelled.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.state.0.lock().unwrap()
+    }
+
+    // Fails with "Cancelled" once the token has been cancelled
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.is_cancelled() {
+            anyhow::bail!("Cancelled");
+        }
+        Ok(())
+    }
+
+    // Sleeps for `duration` or until cancelled, returns false if cancelled
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let (cancelled, condvar) = &*self.state;
+        let mut cancelled = cancelled.lock().unwrap();
+        while !*cancelled {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                return true;
+            };
+            cancelled = condvar.wait_timeout(cancelled, remaining).unwrap().0;
+        }
+        false
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::{Device, HostDefaults};
+
+// Settings applied by hidppd whenever a device (re)connects. Stored as JSON,
+// with vendor and product ids as hex strings:
+//
+//   { "devices": [{ "vid": "046d", "pid": "b35b", "fn_swap": false }] }
+//
+// An "id" from `Device::stable_id` restricts settings to one unit when
+// several of the same model are connected.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // seconds between attempts to open devices that aren't connected
+    pub reconnect_interval: u64,
+    pub devices: Vec<DeviceSettings>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DeviceSettings {
+    #[serde(deserialize_with = "hex_u16")]
+    pub vid: u16,
+    #[serde(deserialize_with = "hex_u16")]
+    pub pid: u16,
+    #[serde(default)]
+    pub id: Option<String>,
+    // configure the platform and Fn keys for the OS hidppd runs on
+    #[serde(default)]
+    pub host_defaults: bool,
+    #[serde(default)]
+    pub fn_swap: Option<bool>,
+    #[serde(default)]
+    pub crown_ratchet: Option<bool>,
+}
+
+fn hex_u16<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).map_err(serde::de::Error::custom)
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let mut config: Config = serde_json::from_str(&contents)?;
+        if config.reconnect_interval == 0 {
+            config.reconnect_interval = 5;
+        }
+        Ok(config)
+    }
+
+    // $XDG_CONFIG_HOME/hidpp/config.json
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("hidpp").join("config.json"))
+    }
+
+    pub fn device(&self, vendor_id: u16, product_id: u16) -> Option<&DeviceSettings> {
+        self.devices
+            .iter()
+            .find(|d| d.vid == vendor_id && d.pid == product_id)
+    }
+
+    // Settings for this particular unit: ones naming its stable id win over
+    // ones for the whole model. A Lightspeed mouse in cable mode also gets
+    // the settings given for its wireless product id, and the other way
+    // around, so they follow it on and off the cable.
+    pub fn device_for(&self, device: &mut Device) -> Option<&DeviceSettings> {
+        let vendor_id = device.vendor_id();
+        let mut candidates = self
+            .devices
+            .iter()
+            .filter(|d| d.vid == vendor_id && d.pid == device.product_id())
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            let other_ids = [device.wireless_product_id(), device.cable_product_id()];
+            candidates = self
+                .devices
+                .iter()
+                .filter(|d| d.vid == vendor_id && other_ids.contains(&Some(d.pid)))
+                .collect();
+        }
+        if candidates.iter().all(|d| d.id.is_none()) {
+            return candidates.first().copied();
+        }
+
+        let stable_id = match device.stable_id() {
+            Ok(id) => Some(id),
+            Err(err) => {
+                crate::tracing::debug!("Failed to read stable id: {}", err);
+                None
+            }
+        };
+        candidates
+            .iter()
+            .find(|d| d.id.is_some() && d.id == stable_id)
+            .or_else(|| candidates.iter().find(|d| d.id.is_none()))
+            .copied()
+    }
+}
+
+impl DeviceSettings {
+    pub fn apply(&self, device: &mut Device) -> anyhow::Result<()> {
+        if self.host_defaults {
+            if let Some(defaults) = HostDefaults::current() {
+                device.apply_host_defaults(&defaults)?;
+            }
+        }
+        if let Some(fn_swap) = self.fn_swap {
+            device.set_fn_swap(fn_swap)?;
+        }
+        if let Some(ratchet) = self.crown_ratchet {
+            device.set_crown_mode(None, Some(ratchet))?;
+        }
+        Ok(())
+    }
+}
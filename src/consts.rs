@@ -0,0 +1,97 @@
+// Numbers from the HID++ 1.0 and 2.0 specs, for code that builds or reads raw
+// frames instead of going through `Device`.
+
+// Report ids, the first byte of every frame
+pub const SHORT_REPORT_ID: u8 = 0x10;
+pub const LONG_REPORT_ID: u8 = 0x11;
+pub const VERY_LONG_REPORT_ID: u8 = 0x12;
+
+// Frame lengths on the wire, header included
+pub const SHORT_REPORT_LEN: usize = 7;
+pub const LONG_REPORT_LEN: usize = 20;
+pub const VERY_LONG_REPORT_LEN: usize = 64;
+// report id, device index, feature index and function/software id
+pub const HEADER_LEN: usize = 4;
+
+// Device index of a receiver itself, and of devices connected directly
+pub const RECEIVER_INDEX: u8 = 0xFF;
+
+// Product ids of Unifying, Nano, Lightspeed and Bolt receivers
+pub const RECEIVER_PRODUCT_IDS: &[u16] = &[
+    // Unifying
+    0xC52B, 0xC532, // Nano
+    0xC517, 0xC518, 0xC51A, 0xC51B, 0xC521, 0xC525, 0xC526, 0xC52E, 0xC52F, 0xC531, 0xC534, 0xC537,
+    0xC542, // Lightspeed
+    0xC539, 0xC53A, 0xC53D, 0xC53F, 0xC541, 0xC545, 0xC547, // Bolt
+    0xC548,
+];
+
+// HID++ 1.0 sub ids for register access, short registers hold 3 bytes and
+// long ones 16
+pub const SET_REGISTER: u8 = 0x80;
+pub const GET_REGISTER: u8 = 0x81;
+pub const SET_LONG_REGISTER: u8 = 0x82;
+pub const GET_LONG_REGISTER: u8 = 0x83;
+
+// Feature index (HID++ 2.0) or sub id (HID++ 1.0) of error frames
+pub const HIDPP20_ERROR: u8 = 0xFF;
+pub const HIDPP10_ERROR: u8 = 0x8F;
+
+// Feature ids
+pub const ROOT: u16 = 0x0000;
+pub const FEATURE_SET: u16 = 0x0001;
+pub const FEATURE_INFO: u16 = 0x0002;
+pub const FIRMWARE_INFO: u16 = 0x0003;
+pub const DEVICE_UNIT_ID: u16 = 0x0004;
+pub const DEVICE_NAME_TYPE: u16 = 0x0005;
+pub const DEVICE_FRIENDLY_NAME: u16 = 0x0007;
+pub const CONFIG_CHANGE: u16 = 0x0020;
+pub const CRYPTO_ID: u16 = 0x0021;
+pub const BATTERY_LEVEL_STATUS: u16 = 0x1000;
+pub const BATTERY_VOLTAGE: u16 = 0x1001;
+pub const UNIFIED_BATTERY: u16 = 0x1004;
+pub const ADC_MEASUREMENT: u16 = 0x1F20;
+pub const TEMPERATURE_MEASUREMENT: u16 = 0x1F30;
+pub const CHANGE_HOST: u16 = 0x1814;
+pub const HOSTS_INFO: u16 = 0x1815;
+pub const BACKLIGHT2: u16 = 0x1982;
+pub const REPROG_CONTROLS_V4: u16 = 0x1B04;
+pub const ADJUSTABLE_DPI: u16 = 0x2201;
+pub const SMART_SHIFT: u16 = 0x2110;
+pub const SMART_SHIFT_ENHANCED: u16 = 0x2111;
+pub const HI_RES_WHEEL: u16 = 0x2121;
+pub const FN_INVERSION: u16 = 0x40A0;
+pub const NEW_FN_INVERSION: u16 = 0x40A2;
+pub const K375S_FN_INVERSION: u16 = 0x40A3;
+pub const CROWN: u16 = 0x4600;
+pub const DISABLE_KEYS: u16 = 0x4521;
+pub const MULTI_PLATFORM: u16 = 0x4531;
+pub const REPORT_RATE: u16 = 0x8060;
+pub const RGB_EFFECTS: u16 = 0x8071;
+pub const PER_KEY_LIGHTING: u16 = 0x8081;
+pub const ONBOARD_PROFILES: u16 = 0x8100;
+
+// HID++ 2.0 error codes
+pub const ERR_UNKNOWN: u8 = 0x01;
+pub const ERR_INVALID_ARGUMENT: u8 = 0x02;
+pub const ERR_OUT_OF_RANGE: u8 = 0x03;
+pub const ERR_HARDWARE_ERROR: u8 = 0x04;
+pub const ERR_INTERNAL: u8 = 0x05;
+pub const ERR_INVALID_FEATURE_INDEX: u8 = 0x06;
+pub const ERR_INVALID_FUNCTION: u8 = 0x07;
+pub const ERR_BUSY: u8 = 0x08;
+pub const ERR_UNSUPPORTED: u8 = 0x09;
+
+// HID++ 1.0 error codes
+pub const ERR10_INVALID_SUB_ID: u8 = 0x01;
+pub const ERR10_INVALID_ADDRESS: u8 = 0x02;
+pub const ERR10_INVALID_VALUE: u8 = 0x03;
+pub const ERR10_CONNECTION_FAILED: u8 = 0x04;
+pub const ERR10_TOO_MANY_DEVICES: u8 = 0x05;
+pub const ERR10_ALREADY_EXISTS: u8 = 0x06;
+pub const ERR10_BUSY: u8 = 0x07;
+pub const ERR10_UNKNOWN_DEVICE: u8 = 0x08;
+pub const ERR10_RESOURCE_ERROR: u8 = 0x09;
+pub const ERR10_REQUEST_UNAVAILABLE: u8 = 0x0A;
+pub const ERR10_INVALID_PARAMETER: u8 = 0x0B;
+pub const ERR10_WRONG_PIN_CODE: u8 = 0x0C;
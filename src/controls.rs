@@ -0,0 +1,238 @@
+use crate::{Device, Feature, Function};
+
+pub(crate) type ButtonCallback = Box<dyn FnMut() + Send>;
+
+// Control IDs of ReprogControlsV4 (0x1B04), the buttons and keys that can
+// be diverted to software
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Cid {
+    LeftClick,
+    RightClick,
+    MiddleButton,
+    Back,
+    Forward,
+    GestureButton,
+    SmartShift,
+    DpiSwitch,
+    Other(u16),
+}
+
+impl From<u16> for Cid {
+    fn from(value: u16) -> Self {
+        match value {
+            0x0050 => Cid::LeftClick,
+            0x0051 => Cid::RightClick,
+            0x0052 => Cid::MiddleButton,
+            0x0053 => Cid::Back,
+            0x0056 => Cid::Forward,
+            0x00C3 => Cid::GestureButton,
+            0x00C4 => Cid::SmartShift,
+            0x00FD => Cid::DpiSwitch,
+            value => Cid::Other(value),
+        }
+    }
+}
+
+impl Cid {
+    pub fn value(&self) -> u16 {
+        match self {
+            Cid::LeftClick => 0x0050,
+            Cid::RightClick => 0x0051,
+            Cid::MiddleButton => 0x0052,
+            Cid::Back => 0x0053,
+            Cid::Forward => 0x0056,
+            Cid::GestureButton => 0x00C3,
+            Cid::SmartShift => 0x00C4,
+            Cid::DpiSwitch => 0x00FD,
+            Cid::Other(value) => *value,
+        }
+    }
+}
+
+// setCidReporting flags, each setting has a "valid" bit telling the device
+// to apply it
+const DIVERT: u8 = 0x01;
+const DIVERT_VALID: u8 = 0x02;
+const RAW_XY: u8 = 0x10;
+const RAW_XY_VALID: u8 = 0x20;
+// getCidInfo flag of controls that can take another control's action
+const REPROGRAMMABLE: u8 = 0x10;
+
+// divertedButtonsEvent lists up to 4 held controls, zero padded
+pub(crate) fn decode_buttons(data: &[u8]) -> Vec<Cid> {
+    data.chunks_exact(2)
+        .take(4)
+        .map(|cid| u16::from_be_bytes([cid[0], cid[1]]))
+        .filter(|cid| *cid != 0)
+        .map(Cid::from)
+        .collect()
+}
+
+impl Device {
+    // Reports presses of `cid` to software instead of performing its
+    // native action
+    pub fn set_divert(&mut self, cid: Cid, divert: bool) -> anyhow::Result<()> {
+        let flags = DIVERT_VALID | if divert { DIVERT } else { 0 };
+        self.set_cid_reporting(cid, flags)
+    }
+
+    // Diverts `cid` and also reports pointer movement while it's held, as
+    // `Event::RawXY`
+    pub fn set_divert_raw_xy(&mut self, cid: Cid, divert: bool) -> anyhow::Result<()> {
+        let flags = DIVERT_VALID | RAW_XY_VALID | if divert { DIVERT | RAW_XY } else { 0 };
+        self.set_cid_reporting(cid, flags)
+    }
+
+    pub(crate) fn set_cid_reporting(&mut self, cid: Cid, flags: u8) -> anyhow::Result<()> {
+        let [high, low] = cid.value().to_be_bytes();
+        // a remap target of 0 keeps the current mapping
+        self.send_feature(
+            Feature::ReprogControlsV4,
+            Function::ReprogControlsSetCidReporting,
+            &[high, low, flags, 0x00, 0x00],
+        )?;
+        if flags & DIVERT_VALID != 0 {
+            if flags & DIVERT != 0 {
+                self.diverted.insert(cid);
+            } else {
+                self.diverted.remove(&cid);
+            }
+        }
+        Ok(())
+    }
+
+    // Controls that can be remapped with `set_remap`
+    pub fn remappable_controls(&mut self) -> anyhow::Result<Vec<Cid>> {
+        let count = self
+            .send_feature(
+                Feature::ReprogControlsV4,
+                Function::ReprogControlsGetCount,
+                &[],
+            )?
+            .data[0];
+        let mut controls = vec![];
+        for index in 0..count {
+            let info = self.send_feature(
+                Feature::ReprogControlsV4,
+                Function::ReprogControlsGetCidInfo,
+                &[index],
+            )?;
+            if info.data[4] & REPROGRAMMABLE != 0 {
+                controls.push(Cid::from(u16::from_be_bytes([info.data[0], info.data[1]])));
+            }
+        }
+        Ok(controls)
+    }
+
+    // The control whose action `cid` performs, None for its own
+    pub fn get_remap(&mut self, cid: Cid) -> anyhow::Result<Option<Cid>> {
+        let result = self.send_feature(
+            Feature::ReprogControlsV4,
+            Function::ReprogControlsGetCidReporting,
+            &cid.value().to_be_bytes(),
+        )?;
+        let target = u16::from_be_bytes([result.data[3], result.data[4]]);
+        Ok((target != 0 && target != cid.value()).then_some(Cid::from(target)))
+    }
+
+    // Makes `cid` perform the action of `target`, None gives it its own
+    // back. Diverting is left as it is.
+    pub fn set_remap(&mut self, cid: Cid, target: Option<Cid>) -> anyhow::Result<()> {
+        let [high, low] = cid.value().to_be_bytes();
+        let [target_high, target_low] = target.unwrap_or(cid).value().to_be_bytes();
+        self.send_feature(
+            Feature::ReprogControlsV4,
+            Function::ReprogControlsSetCidReporting,
+            &[high, low, 0x00, target_high, target_low],
+        )?;
+        Ok(())
+    }
+
+    // Gives every control we diverted back to the OS
+    pub(crate) fn restore_controls(&mut self) -> anyhow::Result<()> {
+        let diverted = self.diverted.iter().copied().collect::<Vec<_>>();
+        for cid in diverted {
+            self.set_cid_reporting(cid, DIVERT_VALID | RAW_XY_VALID)?;
+        }
+        Ok(())
+    }
+
+    // Diverts `cid` and calls `callback` every time it's pressed. Callbacks
+    // run from `next_event`, so something has to keep reading events.
+    pub fn bind_button(
+        &mut self,
+        cid: Cid,
+        callback: impl FnMut() + Send + 'static,
+    ) -> anyhow::Result<()> {
+        self.set_divert(cid, true)?;
+        self.button_bindings.insert(cid, Box::new(callback));
+        Ok(())
+    }
+
+    pub fn unbind_button(&mut self, cid: Cid) -> anyhow::Result<()> {
+        if self.button_bindings.remove(&cid).is_some() && !self.is_bound(cid) {
+            self.set_divert(cid, false)?;
+        }
+        Ok(())
+    }
+
+    // Makes `cid` a G-Shift modifier: while it's held, presses of other
+    // buttons call their `bind_gshift_button` callback instead of the normal
+    // one. None turns the modifier off.
+    pub fn set_gshift_button(&mut self, cid: Option<Cid>) -> anyhow::Result<()> {
+        if let Some(previous) = self.gshift_button.take() {
+            if !self.is_bound(previous) {
+                self.set_divert(previous, false)?;
+            }
+        }
+        if let Some(cid) = cid {
+            self.set_divert(cid, true)?;
+        }
+        self.gshift_button = cid;
+        Ok(())
+    }
+
+    // Like `bind_button`, for presses made while the G-Shift button is held
+    pub fn bind_gshift_button(
+        &mut self,
+        cid: Cid,
+        callback: impl FnMut() + Send + 'static,
+    ) -> anyhow::Result<()> {
+        self.set_divert(cid, true)?;
+        self.gshift_bindings.insert(cid, Box::new(callback));
+        Ok(())
+    }
+
+    pub fn unbind_gshift_button(&mut self, cid: Cid) -> anyhow::Result<()> {
+        if self.gshift_bindings.remove(&cid).is_some() && !self.is_bound(cid) {
+            self.set_divert(cid, false)?;
+        }
+        Ok(())
+    }
+
+    fn is_bound(&self, cid: Cid) -> bool {
+        self.button_bindings.contains_key(&cid)
+            || self.gshift_bindings.contains_key(&cid)
+            || self.gshift_button == Some(cid)
+    }
+
+    pub(crate) fn dispatch_buttons(&mut self, pressed: &[Cid]) {
+        let shifted = self
+            .gshift_button
+            .is_some_and(|gshift| pressed.contains(&gshift));
+        for cid in pressed {
+            if self.pressed_buttons.contains(cid) || self.gshift_button == Some(*cid) {
+                continue;
+            }
+            let bindings = if shifted {
+                &mut self.gshift_bindings
+            } else {
+                &mut self.button_bindings
+            };
+            if let Some(callback) = bindings.get_mut(cid) {
+                callback();
+            }
+        }
+        self.pressed_buttons = pressed.to_vec();
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::bail;
+
+use crate::{Device, Feature, Function};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CrownMode {
+    // rotation is reported to software instead of the OS
+    pub diverted: bool,
+    // clicky steps instead of free spinning
+    pub ratchet: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum CrownPress {
+    Start,
+    Held,
+    Release,
+}
+
+// A crown notification. Rotations are signed, positive is clockwise.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CrownEvent {
+    pub rotation: i8,
+    pub ratchet_rotation: i8,
+    pub touched: bool,
+    pub tapped: bool,
+    pub press: Option<CrownPress>,
+}
+
+impl TryFrom<&[u8]> for CrownEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < 7 {
+            bail!("Crown event too short: {} bytes", data.len());
+        }
+
+        let press = match data[6] {
+            0x01 => Some(CrownPress::Start),
+            0x02..=0x04 => Some(CrownPress::Held),
+            0x05 => Some(CrownPress::Release),
+            _ => None,
+        };
+        Ok(Self {
+            rotation: data[1] as i8,
+            ratchet_rotation: data[2] as i8,
+            touched: data[4] != 0,
+            tapped: data[5] == 0x01,
+            press,
+        })
+    }
+}
+
+impl Device {
+    pub fn get_crown_mode(&mut self) -> anyhow::Result<CrownMode> {
+        let result = self.send_feature(Feature::Crown, Function::CrownGetMode, &[])?;
+        // 0x01 is off, 0x02 is on for both settings
+        Ok(CrownMode {
+            diverted: result.data[0] == 0x02,
+            ratchet: result.data[1] == 0x02,
+        })
+    }
+
+    // Changes the crown settings, leaving those passed as None untouched
+    pub fn set_crown_mode(
+        &mut self,
+        diverted: Option<bool>,
+        ratchet: Option<bool>,
+    ) -> anyhow::Result<()> {
+        let value = |setting: Option<bool>| match setting {
+            Some(true) => 0x02,
+            Some(false) => 0x01,
+            None => 0x00,
+        };
+        self.send_feature(
+            Feature::Crown,
+            Function::CrownSetMode,
+            &[value(diverted), value(ratchet)],
+        )?;
+        Ok(())
+    }
+}
@@ -1,98 +1,1045 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{CStr, CString},
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::bail;
-use enum_iterator::all;
-use retry::{delay::Fixed, retry_with_index, OperationResult};
 
-use crate::{Feature, Function, Message, MessageBuilder};
+#[cfg(feature = "json")]
+use crate::frame_log::{Direction, FrameLogger, FrameRecord};
+use crate::{
+    builder::DeviceBuilder,
+    cache::{CachedTables, FeatureCache},
+    consts,
+    controls::{self, ButtonCallback},
+    hidpp10, lookup_quirks,
+    queue::EventQueue,
+    quirks::lookup_wireless_quirks,
+    strings::to_hex,
+    BacklightConfig, Backoff, ChargingAlert, Cid, Clock, CrownEvent, DeviceType, DpiCapabilities,
+    Error, Event, Feature, Function, HidapiTransport, Message, MessageBuilder, QuirkKey, Quirks,
+    RateLimiter, ReportId, TransactionId, Transport,
+};
+
+// times a request is sent again when no reply comes back
+const RESENDS: usize = 2;
+
+// the Lightspeed receiver built into Powerplay charging mats
+const POWERPLAY_RECEIVERS: [u16; 1] = [0xC53A];
 
 pub struct Device {
     vendor_id: u16,
     product_id: u16,
-    device: hidapi::HidDevice,
+    path: Option<CString>,
+    transport: Box<dyn Transport>,
+    // the transport was opened from the ids or path, `reconnect` can open
+    // it again
+    reopenable: bool,
+    device_index: u8,
+    timeout: Duration,
+    backoff: Backoff,
+    clock: Arc<dyn Clock>,
+    deadline: Option<Instant>,
+    // the transaction in flight, and the most recent one
+    transaction: Option<TransactionId>,
+    last_transaction: Option<TransactionId>,
+    // the request sent with `submit` whose reply hasn't been polled yet
+    submitted: Option<Vec<u8>>,
     features_index: HashMap<Feature, u8>,
+    quirks: Quirks,
+    // reserved bits in replies are errors rather than ignored
+    strict: bool,
+    rate_limiter: RateLimiter,
+    battery_capabilities: Option<BatteryCapabilities>,
+    // by sensor, once read
+    pub(crate) dpi_capabilities: HashMap<u8, DpiCapabilities>,
+    // whether RgbEffects can keep effects in RAM, once asked
+    pub(crate) led_persistence: Option<bool>,
+    // last battery status seen, to tell charging problems from repeats
+    battery_status: Option<BatteryStatus>,
+    // the feature `get_battery` reads, once resolved
+    battery_feature: Option<Feature>,
+    max_report: Option<ReportId>,
+    // (major, minor) HID++ version, once asked
+    protocol: Option<(u8, u8)>,
+    // last value of the receiver's link quality notifications
+    pub(crate) reported_link_quality: Option<u8>,
+    pending_events: EventQueue,
+    // feature indexes `next_event` returns notifications for, None for all
+    subscriptions: Option<HashSet<u8>>,
+    stats: Stats,
+    #[cfg(feature = "json")]
+    frame_logger: Option<FrameLogger>,
+    pub(crate) button_bindings: HashMap<Cid, ButtonCallback>,
+    pub(crate) gshift_bindings: HashMap<Cid, ButtonCallback>,
+    pub(crate) gshift_button: Option<Cid>,
+    pub(crate) pressed_buttons: Vec<Cid>,
+    pub(crate) diverted: HashSet<Cid>,
+    closed: bool,
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        if let Err(err) = self.cleanup() {
+            crate::tracing::debug!("Failed to clean up device: {}", err);
+        }
+    }
+}
+
+// The hidraw node's descriptor becomes readable when the device sends a
+// report. Drain `try_recv_event` until it returns None each time: events read
+// while waiting for a reply are queued and won't make the descriptor readable.
+#[cfg(all(target_os = "linux", feature = "hidraw"))]
+impl std::os::unix::io::AsRawFd for Device {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        // transports without a descriptor can't be polled
+        self.transport.raw_fd().unwrap_or(-1)
+    }
+}
+
+// Strings and numbers reported by the HID layer rather than over HID++
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HidInfo {
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+    // bcdDevice, e.g. 0x1203 for 12.03
+    pub release_number: u16,
+    // -1 when the interface isn't known (e.g. Bluetooth)
+    pub interface_number: i32,
+    // hidraw/IOKit path of the HID++ interface
+    pub path: Option<CString>,
+}
+
+// Counters of transport hiccups since the device was opened
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    pub requests: u64,
+    pub retries: u64,
+    pub timeouts: u64,
+    // notifications dropped because the event queue was full
+    pub dropped_events: u64,
+    // frames that weren't a reply to the request in flight, e.g. late or
+    // duplicated replies to earlier ones, or garbled frames
+    pub discarded: u64,
 }
 
 impl Device {
-    fn open(vendor_id: u16, product_id: u16) -> anyhow::Result<hidapi::HidDevice> {
-        retry_with_index(
-            Fixed::from_millis(10),
-            |attempt| match hidapi::HidApi::new().unwrap().open(vendor_id, product_id) {
-                Ok(device) => OperationResult::Ok(device),
-                Err(err) => {
-                    if attempt > 5 {
-                        tracing::debug!("Giving up opening device: {}", err);
-                        return OperationResult::Err(format!("Error opening device: {}", err));
-                    }
-                    tracing::debug!("Error opening device: {}", err);
-                    OperationResult::Retry(format!("Error opening device: {}", err))
-                }
-            },
-        )
-        .map_err(|e| anyhow::anyhow!("Failed to open device: {}", e))
+    pub fn builder() -> DeviceBuilder {
+        DeviceBuilder::default()
     }
 
     pub fn new(vendor_id: u16, product_id: u16) -> anyhow::Result<Self> {
-        let device = Device::open(vendor_id, product_id)?;
+        Device::builder().vid(vendor_id).pid(product_id).open()
+    }
 
-        Ok(Device {
+    // Opens the device for reading the battery only, for status bar widgets
+    // that run every few seconds: only Root and the battery feature are
+    // resolved, without `init()` or report size negotiation. Other features
+    // are still looked up the first time they're used.
+    pub fn open_for_battery(vendor_id: u16, product_id: u16) -> anyhow::Result<Self> {
+        let mut device = Device::new(vendor_id, product_id)?;
+        device.init_battery()?;
+        Ok(device)
+    }
+
+    pub(crate) fn from_builder(builder: DeviceBuilder) -> anyhow::Result<Self> {
+        let reopenable = builder.transport.is_none();
+        let (transport, vendor_id, product_id): (Box<dyn Transport>, _, _) = match (
+            builder.transport,
+            &builder.path,
+            builder.vendor_id,
+            builder.product_id,
+        ) {
+            (Some(transport), _, vendor_id, product_id) => (
+                transport,
+                vendor_id.unwrap_or_default(),
+                product_id.unwrap_or_default(),
+            ),
+            (None, Some(path), _, _) => {
+                let transport =
+                    HidapiTransport::open(0, 0, Some(path), &builder.backoff, &*builder.clock)?;
+                let (vendor_id, product_id) = transport.ids()?;
+                (Box::new(transport), vendor_id, product_id)
+            }
+            (None, None, Some(vendor_id), Some(product_id)) => {
+                let transport = HidapiTransport::open(
+                    vendor_id,
+                    product_id,
+                    None,
+                    &builder.backoff,
+                    &*builder.clock,
+                )?;
+                (Box::new(transport), vendor_id, product_id)
+            }
+            _ => bail!("Either a path or both vendor and product ids are needed to open a device"),
+        };
+        let quirks = builder
+            .quirks
+            .unwrap_or_else(|| lookup_quirks(QuirkKey::new(vendor_id, product_id, None)));
+        if quirks != Quirks::default() {
+            crate::tracing::debug!("Applying quirks: {:?}", quirks);
+        }
+
+        let explicit_quirks = builder.quirks.is_some();
+        let mut device = Device {
             vendor_id,
             product_id,
-            device,
+            path: builder.path,
+            transport,
+            reopenable,
+            device_index: builder.device_index.unwrap_or(if quirks.direct_index {
+                0xFF
+            } else {
+                0x01
+            }),
+            timeout: builder.timeout,
+            backoff: builder.backoff,
+            clock: builder.clock.clone(),
+            deadline: None,
+            transaction: None,
+            last_transaction: None,
+            submitted: None,
             features_index: HashMap::new(),
-        })
+            quirks,
+            strict: builder.strict,
+            rate_limiter: builder
+                .rate_limiter
+                .unwrap_or_else(|| RateLimiter::default().with_clock(builder.clock.clone())),
+            battery_capabilities: None,
+            dpi_capabilities: HashMap::new(),
+            led_persistence: None,
+            battery_status: None,
+            battery_feature: None,
+            max_report: None,
+            protocol: None,
+            reported_link_quality: None,
+            pending_events: EventQueue::new(builder.event_capacity, builder.overflow_policy),
+            subscriptions: None,
+            stats: Stats::default(),
+            #[cfg(feature = "json")]
+            frame_logger: None,
+            button_bindings: HashMap::new(),
+            gshift_bindings: HashMap::new(),
+            gshift_button: None,
+            pressed_buttons: vec![],
+            diverted: HashSet::new(),
+            closed: false,
+        };
+        if !explicit_quirks {
+            device.apply_wireless_quirks();
+        }
+        Ok(device)
+    }
+
+    // Quirks of a device behind a receiver or in cable mode are keyed on its
+    // wireless product id, which is only known once the handle is open
+    fn apply_wireless_quirks(&mut self) {
+        let behind_receiver = self.is_behind_receiver() && (1..=6).contains(&self.device_index);
+        if !behind_receiver && !self.is_cable_mode() {
+            return;
+        }
+        let Some(wireless_product_id) = self.wireless_product_id() else {
+            return;
+        };
+        let key = QuirkKey::new(self.vendor_id, self.product_id, Some(wireless_product_id));
+        let quirks = lookup_wireless_quirks(key);
+        if quirks != Quirks::default() {
+            crate::tracing::debug!("Applying quirks: {:?}", quirks);
+            self.quirks = quirks;
+        }
+    }
+
+    pub fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    pub fn product_id(&self) -> u16 {
+        self.product_id
+    }
+
+    pub fn device_index(&self) -> u8 {
+        self.device_index
+    }
+
+    // hidraw/IOKit path the device was opened from, if it was
+    pub(crate) fn path(&self) -> Option<&CStr> {
+        self.path.as_deref()
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn hid_info(&self) -> anyhow::Result<HidInfo> {
+        self.transport.hid_info()
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    // Reserved bits set in a reply are ignored, unless the device is in
+    // strict mode
+    pub(crate) fn check_reserved(&self, what: &str, value: u8, known: u8) -> anyhow::Result<()> {
+        let reserved = value & !known;
+        if reserved != 0 && self.strict {
+            crate::tracing::warn!(
+                "{} 0x{:02X}: reserved bits 0x{:02X} set",
+                what,
+                value,
+                reserved
+            );
+            bail!(
+                "{} 0x{:02X} has reserved bits 0x{:02X} set",
+                what,
+                value,
+                reserved
+            );
+        }
+        Ok(())
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    // Replaces the limiter, pass a clone of another device's limiter to throttle
+    // both devices together (e.g. when they share a receiver).
+    pub fn set_rate_limiter(&mut self, rate_limiter: RateLimiter) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    // Logs every frame sent and received from now on, pass None to stop
+    #[cfg(feature = "json")]
+    pub fn set_frame_logger(&mut self, frame_logger: Option<FrameLogger>) {
+        self.frame_logger = frame_logger;
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        self.backoff
+    }
+
+    pub fn set_backoff(&mut self, backoff: Backoff) {
+        self.backoff = backoff;
+    }
+
+    pub(crate) fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    // Runs `f` with an overall deadline: retries, reconnects and reads made
+    // on its behalf all stop once `timeout` has elapsed, and the request in
+    // flight fails with a timeout.
+    pub fn with_deadline<T>(
+        &mut self,
+        timeout: Duration,
+        f: impl FnOnce(&mut Device) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let deadline = self.clock.now() + timeout;
+        // an enclosing deadline that's sooner still applies
+        let previous = self.deadline;
+        self.deadline = Some(previous.map_or(deadline, |previous| previous.min(deadline)));
+        let result = f(self);
+        self.deadline = previous;
+        result
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(self.clock.now()))
+    }
+
+    fn deadline_expired(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    // The smallest report that fits the payload and that the device accepts
+    fn report_id(&self, payload_len: usize) -> anyhow::Result<ReportId> {
+        let mut report_id = ReportId::for_payload_len(payload_len)
+            .ok_or_else(|| anyhow::anyhow!("Payload too long: {} bytes", payload_len))?;
+        let short_allowed = !self.quirks.long_reports_only
+            && self.transport.report_lengths().contains(&ReportId::Short);
+        if report_id == ReportId::Short && !short_allowed {
+            report_id = ReportId::Long;
+        }
+        if let Some(max) = &self.max_report {
+            if report_id.total_len() > max.total_len() {
+                bail!(
+                    "Payload of {} bytes needs a {:?} report, the device accepts up to {:?}",
+                    payload_len,
+                    report_id,
+                    max
+                );
+            }
+        }
+        Ok(report_id)
+    }
+
+    // Largest report the device accepts, if it told us
+    pub fn max_report(&self) -> Option<ReportId> {
+        self.max_report.clone()
+    }
+
+    // Root v2+ replies to getProtocolVersion with the size in bytes of the
+    // largest report the device accepts after the ping byte. Older devices
+    // don't say, and are assumed to take any report.
+    pub fn negotiate_report_size(&mut self) -> anyhow::Result<Option<ReportId>> {
+        let root = self.send_feature(Feature::Root, Function::RootGetFeature, &[0x00, 0x00])?;
+        let root_version = root.data[2];
+        if root_version < 2 {
+            return Ok(None);
+        }
+
+        let result = self.send_feature(
+            Feature::Root,
+            Function::RootGetProtocolVersion,
+            &[0x00, 0x00, 0x00],
+        )?;
+        let size = result.data.get(3).copied().unwrap_or(0) as usize;
+        self.max_report = size
+            .checked_sub(ReportId::HEADER_LEN)
+            .and_then(ReportId::for_payload_len)
+            .filter(|report_id| report_id.total_len() == size);
+        crate::tracing::debug!("Root v{}, max report {:?}", root_version, self.max_report);
+        Ok(self.max_report.clone())
     }
 
     pub fn reconnect(&mut self) -> anyhow::Result<()> {
-        self.device = Device::open(self.vendor_id, self.product_id)?;
+        let mut backoff = self.backoff;
+        if let Some(remaining) = self.remaining() {
+            backoff.budget = backoff.budget.min(remaining);
+        }
+        if !self.reopenable {
+            bail!("The device's transport can't be reopened");
+        }
+        self.transport = Box::new(HidapiTransport::open(
+            self.vendor_id,
+            self.product_id,
+            self.path.as_deref(),
+            &backoff,
+            &*self.clock,
+        )?);
+        Ok(())
+    }
+
+    // Gives diverted controls back to the OS, drops queued events and
+    // releases the HID handle. Dropping the device does the same on a best
+    // effort basis, call this to find out whether cleanup worked or to make
+    // sure the handle is released before reopening the device.
+    pub fn close(mut self) -> anyhow::Result<()> {
+        self.closed = true;
+        self.cleanup()
+    }
+
+    fn cleanup(&mut self) -> anyhow::Result<()> {
+        self.button_bindings.clear();
+        self.pending_events.clear();
+        #[cfg(feature = "json")]
+        {
+            self.frame_logger = None;
+        }
+        if self.diverted.is_empty() {
+            return Ok(());
+        }
+        // don't let a device that's gone hold up the caller
+        self.with_deadline(Duration::from_millis(500), |device| {
+            device.restore_controls()
+        })
+    }
+
+    pub fn init(&mut self) -> anyhow::Result<()> {
+        let start = Instant::now();
+        let feature_set = self.get_feature_index(Feature::FeatureSet)?;
+        self.features_index = HashMap::from([(Feature::Root, 0x00u8)]);
+
+        if feature_set == 0 {
+            // no FeatureSet, fall back to asking Root for every known feature
+            for feature in Feature::ALL.iter().filter(|f| **f != Feature::Root) {
+                let feature_index = self.get_feature_index(feature.clone())?;
+                if feature_index != 0 {
+                    self.features_index.insert(feature.clone(), feature_index);
+                }
+            }
+        } else {
+            self.features_index.insert(Feature::FeatureSet, feature_set);
+            let count = self
+                .send_feature(Feature::FeatureSet, Function::FeatureSetGetCount, &[])?
+                .data[0];
+            for feature_index in 1..=count {
+                let response = self.send_feature(
+                    Feature::FeatureSet,
+                    Function::FeatureSetGetFeatureId,
+                    &[feature_index],
+                )?;
+                let id = u16::from_be_bytes([response.data[0], response.data[1]]);
+                match Feature::from_value(id) {
+                    Some(feature) => {
+                        self.features_index.insert(feature, feature_index);
+                    }
+                    None => crate::tracing::trace!("Skipping unknown feature 0x{:04X}", id),
+                }
+            }
+        }
+
+        crate::tracing::debug!(
+            "Resolved {} feature indexes in {:?}",
+            self.features_index.len(),
+            start.elapsed()
+        );
+        crate::tracing::debug!("{:#?}", self.features_index);
+
+        if let Err(err) = self.negotiate_report_size() {
+            crate::tracing::debug!("Failed to negotiate report size: {}", err);
+        }
+        Ok(())
+    }
+
+    // Resolves just the battery feature `get_battery` will use
+    pub fn init_battery(&mut self) -> anyhow::Result<()> {
+        self.battery_feature()?;
+        Ok(())
+    }
+
+    // The first battery feature the device has, in an order that depends on
+    // its type: headsets usually only report through AdcMeasurement, and
+    // some have a UnifiedBattery that never changes.
+    fn battery_feature(&mut self) -> anyhow::Result<Feature> {
+        if let Some(feature) = &self.battery_feature {
+            return Ok(feature.clone());
+        }
+
+        let candidates = if self.quirks.battery_voltage_only {
+            vec![Feature::BatteryVoltage]
+        } else if self.is_headset() {
+            vec![
+                Feature::AdcMeasurement,
+                Feature::BatteryVoltage,
+                Feature::UnifiedBattery,
+            ]
+        } else {
+            vec![
+                Feature::UnifiedBattery,
+                Feature::BatteryVoltage,
+                Feature::AdcMeasurement,
+            ]
+        };
+        for feature in candidates {
+            if self.feature_index(feature.clone()).is_ok() {
+                crate::tracing::debug!("Reading the battery through {:?}", feature);
+                self.battery_feature = Some(feature.clone());
+                return Ok(feature);
+            }
+        }
+        bail!("Device has no battery feature")
+    }
+
+    fn is_headset(&mut self) -> bool {
+        if self.feature_index(Feature::DeviceNameType).is_err() {
+            return false;
+        }
+        match self.device_type() {
+            Ok(device_type) => device_type == DeviceType::Headset,
+            Err(err) => {
+                crate::tracing::debug!("Failed to read device type: {}", err);
+                false
+            }
+        }
+    }
+
+    // Same as `init()`, but reuses the feature table, DPI and battery
+    // capabilities stored on disk for this unit and firmware version when
+    // there are some.
+    pub fn init_cached(&mut self) -> anyhow::Result<()> {
+        let unit_id = self.unit_id()?;
+        let firmware = self.firmware_version()?;
+        let Some(cache) = FeatureCache::new(unit_id, &firmware.to_string()) else {
+            return self.init();
+        };
+
+        if let Some(tables) = cache.load() {
+            self.features_index = tables.features_index;
+            self.dpi_capabilities = tables.dpi_capabilities;
+            self.battery_capabilities = tables.battery_capabilities;
+            if let Err(err) = self.negotiate_report_size() {
+                crate::tracing::debug!("Failed to negotiate report size: {}", err);
+            }
+            return Ok(());
+        }
+
+        self.init()?;
+        // read now so later runs don't have to
+        if self.index_for(Feature::AdjustableDpi).is_ok() {
+            for sensor in 0..self.dpi_sensor_count().unwrap_or(0) {
+                if let Err(err) = self.dpi_capabilities(sensor) {
+                    crate::tracing::debug!("Failed to read DPI capabilities: {}", err);
+                }
+            }
+        }
+        if self.index_for(Feature::UnifiedBattery).is_ok() {
+            if let Err(err) = self.get_battery_capabilities() {
+                crate::tracing::debug!("Failed to read battery capabilities: {}", err);
+            }
+        }
+        let tables = CachedTables {
+            features_index: self.features_index.clone(),
+            dpi_capabilities: self.dpi_capabilities.clone(),
+            battery_capabilities: self.battery_capabilities,
+        };
+        if let Err(err) = cache.store(&tables) {
+            crate::tracing::warn!("Failed to store feature table: {}", err);
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, request: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.in_transaction(|device| device.exchange(request))
+    }
+
+    // Runs `f` as one transaction, or as part of the one in flight
+    fn in_transaction<T>(
+        &mut self,
+        f: impl FnOnce(&mut Device) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        if self.transaction.is_some() {
+            return f(self);
+        }
+        let id = TransactionId::next();
+        self.transaction = Some(id);
+        self.last_transaction = Some(id);
+        let result = crate::tracing::debug_span!("transaction", id = id.0).in_scope(|| f(self));
+        self.transaction = None;
+        result
+    }
+
+    // The id of the last request sent, to find it in logs
+    pub fn last_transaction(&self) -> Option<TransactionId> {
+        self.last_transaction
+    }
+
+    fn exchange(&mut self, request: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let rate_limiter = self.rate_limiter.clone();
+        let _permit = rate_limiter.acquire();
+        // whatever is already waiting can't be the reply to this request
+        self.drain_input()?;
+        self.write_frame(request)?;
+
+        // notifications can arrive before the reply, keep them for next_event()
+        let mut resends = 0;
+        loop {
+            let mut timeout = self.timeout;
+            if let Some(remaining) = self.remaining() {
+                timeout = timeout.min(remaining);
+            }
+            let buf = self.read(timeout)?;
+            if buf.is_empty() {
+                if resends < RESENDS && !self.deadline_expired() {
+                    resends += 1;
+                    self.stats.retries += 1;
+                    crate::tracing::debug!("No reply, resending {}", to_hex(request));
+                    self.write_frame(request)?;
+                    continue;
+                }
+                self.stats.timeouts += 1;
+                bail!("Timed out waiting for response");
+            }
+
+            if let Some(reply) = self.dispatch(buf, Some(request)) {
+                return Ok(reply);
+            }
+        }
+    }
+
+    // Sorts out a frame read from the device: returns it if it's the reply
+    // to `request`, queues notifications for `next_event` and drops anything
+    // else, like late or duplicated replies to earlier requests
+    fn dispatch(&mut self, buf: Vec<u8>, request: Option<&[u8]>) -> Option<Vec<u8>> {
+        let message = match Message::try_from(buf.clone()) {
+            Ok(message) => message,
+            Err(err) => {
+                crate::tracing::debug!("Discarding frame: {}", err);
+                self.stats.discarded += 1;
+                return None;
+            }
+        };
+        if message.is_notification() {
+            crate::tracing::trace!("Queueing notification: {}", message.dump());
+            self.queue_event(message);
+            return None;
+        }
+        if request.is_some_and(|request| is_reply_to(&buf, request)) {
+            return Some(buf);
+        }
+        crate::tracing::debug!("Discarding stale reply {}", to_hex(&buf));
+        self.stats.discarded += 1;
+        None
+    }
+
+    // Reads everything already received without waiting, queueing
+    // notifications and dropping anything else
+    fn drain_input(&mut self) -> anyhow::Result<()> {
+        loop {
+            let buf = self.read(Duration::ZERO)?;
+            if buf.is_empty() {
+                return Ok(());
+            }
+            self.dispatch(buf, None);
+        }
+    }
+
+    // Sends a feature request without waiting for the reply, pick it up
+    // later with `poll_response`. Only one request should be outstanding at
+    // a time: submitting another one stops waiting for the previous reply.
+    pub fn submit(
+        &mut self,
+        feature: Feature,
+        function: Function,
+        payload: &[u8],
+    ) -> anyhow::Result<()> {
+        let request = MessageBuilder::new_short(self.feature_index(feature)?, function)
+            .report_id(self.report_id(payload.len())?)
+            .device_index(self.device_index)
+            .data(payload.to_vec())
+            .build();
+        // the permit can't outlive this call, so only the minimum interval
+        // between requests is enforced here
+        let rate_limiter = self.rate_limiter.clone();
+        let _permit = rate_limiter.acquire();
+        let request = request.to_bytes();
+        self.write_frame(&request)?;
+        self.submitted = Some(request);
+        Ok(())
+    }
+
+    // Returns the reply to a request sent with `submit` if it has arrived,
+    // without blocking. Notifications read along the way are queued for
+    // `try_recv_event`.
+    pub fn poll_response(&mut self) -> anyhow::Result<Option<Message>> {
+        loop {
+            let buf = self.read(Duration::ZERO)?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+
+            let request = self.submitted.clone();
+            let Some(buf) = self.dispatch(buf, request.as_deref()) else {
+                continue;
+            };
+            self.submitted = None;
+            let message = Message::try_from(buf.clone())?;
+            if let Some(error) = Error::from_message(&message) {
+                let context = format!(
+                    "Device returned error 0x{:02X} ({}) (response {})",
+                    message.data()[1],
+                    error,
+                    to_hex(&buf)
+                );
+                return Err(anyhow::Error::new(error).context(context));
+            }
+            return Ok(Some(message));
+        }
+    }
+
+    // Returns a pending notification without blocking
+    pub fn try_recv_event(&mut self) -> anyhow::Result<Option<Event>> {
+        self.next_event(Duration::ZERO)
+    }
+
+    // Only notifications from these features will be returned by
+    // `next_event`, others are skipped without being decoded. Button
+    // bindings need ReprogControlsV4 in the list to keep working.
+    pub fn subscribe(&mut self, features: &[Feature]) -> anyhow::Result<()> {
+        let mut subscriptions = HashSet::new();
+        for feature in features {
+            subscriptions.insert(self.feature_index(feature.clone())?);
+        }
+        self.subscriptions = Some(subscriptions);
         Ok(())
     }
 
-    pub fn init(&mut self) {
-        let mut features_index = HashMap::from([(Feature::Root, 0x00u8)]);
-        for feature in all::<Feature>().collect::<Vec<_>>() {
-            let feature_index = self.get_feature_index(feature.clone()).unwrap();
-            features_index.insert(feature, feature_index);
+    // Goes back to receiving every notification
+    pub fn clear_subscriptions(&mut self) {
+        self.subscriptions = None;
+    }
+
+    fn is_subscribed(&self, message: &Message) -> bool {
+        self.subscriptions
+            .as_ref()
+            .is_none_or(|subscriptions| subscriptions.contains(&message.feature_index))
+    }
+
+    fn queue_event(&mut self, message: Message) {
+        if !self.is_subscribed(&message) {
+            return;
+        }
+        if !self.pending_events.push(message) {
+            self.stats.dropped_events += 1;
+        }
+    }
+
+    fn write_frame(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+        if self.quirks.no_very_long_reports && buf.first() == Some(&0x12) {
+            bail!("Device does not support very long reports");
+        }
+        if let Some(report_id) = buf.first().and_then(|id| ReportId::try_from(*id).ok()) {
+            if !self.transport.report_lengths().contains(&report_id) {
+                bail!("The transport does not carry {:?} reports", report_id);
+            }
+        }
+        if self.pending_events.is_blocking() {
+            bail!("Event queue is full, drain it with next_event before sending requests");
+        }
+        self.stats.requests += 1;
+
+        let mut attempt = 0;
+        let result = loop {
+            attempt += 1;
+            if self.deadline_expired() {
+                break Err("Deadline expired".to_string());
+            }
+            match self.transport.write_report(buf) {
+                Ok(_) => break Ok(()),
+                Err(e) => {
+                    if attempt > 5 {
+                        break Err(format!("Error writing to device: {}", e));
+                    }
+                    crate::tracing::debug!("Error writing to device: {}", e);
+                    self.stats.retries += 1;
+                    if let Err(err) = self.reconnect() {
+                        crate::tracing::debug!("Error reconnecting: {}", err);
+                    }
+                    self.clock.sleep(Duration::from_millis(1));
+                }
+            }
+        };
+        result.map_err(|e| {
+            if self.deadline_expired() {
+                self.stats.timeouts += 1;
+            }
+            anyhow::anyhow!("Failed to write to device: {}", e)
+        })?;
+        crate::tracing::trace!("Done writing");
+        #[cfg(feature = "json")]
+        self.log_frame(Direction::Out, buf);
+        Ok(())
+    }
+
+    // reads a single report, returns an empty buffer on timeout
+    fn read(&mut self, timeout: Duration) -> anyhow::Result<Vec<u8>> {
+        // responses may come back as longer reports than the request
+        let mut buf = [0u8; 64];
+        let len = self.transport.read_report(&mut buf, timeout)?;
+        #[cfg(feature = "json")]
+        if len > 0 {
+            self.log_frame(Direction::In, &buf[..len]);
+        }
+        Ok(buf[..len].to_vec())
+    }
+
+    #[cfg(feature = "json")]
+    fn log_frame(&mut self, direction: Direction, buf: &[u8]) {
+        if let Some(frame_logger) = self.frame_logger.as_mut() {
+            let mut record = FrameRecord::new(direction, buf);
+            record.transaction = self.transaction;
+            record.feature = buf.get(2).and_then(|feature_index| {
+                self.features_index
+                    .iter()
+                    .find(|(_, index)| *index == feature_index)
+                    .map(|(feature, _)| format!("{:?}", feature))
+            });
+            if let Err(err) = frame_logger.write(&record) {
+                crate::tracing::warn!("Failed to log frame: {}", err);
+            }
+        }
+    }
+
+    // Returns the next notification sent by the device, waiting up to
+    // `timeout` for one to arrive.
+    pub fn next_event(&mut self, timeout: Duration) -> anyhow::Result<Option<Event>> {
+        let dropped = self.pending_events.take_dropped();
+        if dropped > 0 {
+            return Ok(Some(Event::Overflow { dropped }));
         }
 
-        tracing::debug!("{:#?}", features_index);
-        self.features_index = features_index;
+        let Some(message) = self.next_message(timeout)? else {
+            return Ok(None);
+        };
+        let event = self.decode_event(message);
+        if let Event::Buttons(pressed) = &event {
+            self.dispatch_buttons(pressed);
+        }
+        Ok(Some(event))
     }
 
-    pub fn write(&mut self, buf: &[u8]) -> anyhow::Result<Vec<u8>> {
-        retry_with_index(
-            Fixed::from_millis(1),
-            |attempt| -> OperationResult<Vec<u8>, String> {
-                match self.device.write(buf) {
-                    Ok(_) => OperationResult::Ok(vec![]),
-                    Err(e) => {
-                        if attempt > 5 {
-                            return OperationResult::Err(format!("Error writing to device: {}", e));
-                        }
-                        tracing::debug!("Error writing to device: {}", e);
-                        self.reconnect().unwrap();
-                        OperationResult::Retry(format!("Error writing to device: {}", e))
+    // The next subscribed notification, undecoded, for callers that need
+    // more of the frame than the event keeps
+    pub(crate) fn next_message(&mut self, timeout: Duration) -> anyhow::Result<Option<Message>> {
+        let deadline = self.clock.now() + timeout;
+        loop {
+            let message = match self.pending_events.pop() {
+                Some(message) => message,
+                None => {
+                    let buf = self.read(deadline.saturating_duration_since(self.clock.now()))?;
+                    if buf.is_empty() {
+                        return Ok(None);
                     }
+                    // only notifications are kept
+                    self.dispatch(buf, None);
+                    continue;
+                }
+            };
+            if self.is_subscribed(&message) {
+                crate::tracing::debug!("EVT: {}", message.dump());
+                return Ok(Some(message));
+            }
+            crate::tracing::trace!("Skipping unsubscribed notification: {}", message.dump());
+        }
+    }
+
+    // A notification that doesn't decode is passed on as unknown, so one odd
+    // frame doesn't end an event loop the way a transport error does
+    fn decode_event(&mut self, message: Message) -> Event {
+        match self.try_decode_event(message.clone()) {
+            Ok(event) => event,
+            Err(err) => {
+                crate::tracing::warn!("Failed to decode {}: {}", message.dump(), err);
+                Event::Unknown(message)
+            }
+        }
+    }
+
+    fn try_decode_event(&mut self, message: Message) -> anyhow::Result<Event> {
+        let feature = self
+            .features_index
+            .iter()
+            .find(|(_, index)| **index == message.feature_index)
+            .map(|(feature, _)| feature.clone());
+
+        match (feature, message.function_index) {
+            (Some(Feature::UnifiedBattery), 0x00) => {
+                let capabilities = self.get_battery_capabilities()?;
+                self.check_battery_reply(Feature::UnifiedBattery, &message.data)?;
+                let battery = BatteryInfo::from_status(&message.data, &capabilities)?;
+                Ok(self.battery_event(battery))
+            }
+            (Some(Feature::BatteryVoltage), 0x00) => {
+                self.check_battery_reply(Feature::BatteryVoltage, &message.data)?;
+                let battery = BatteryInfo::from_voltage(&message.data);
+                Ok(self.battery_event(battery))
+            }
+            (Some(Feature::AdcMeasurement), 0x00) => {
+                self.check_battery_reply(Feature::AdcMeasurement, &message.data)?;
+                match BatteryInfo::from_adc(&message.data) {
+                    Some(battery) => Ok(self.battery_event(battery)),
+                    None => Ok(Event::Unknown(message)),
                 }
+            }
+            (Some(Feature::Backlight2), 0x00) => Ok(Event::Backlight(BacklightConfig::try_from(
+                message.data.as_slice(),
+            )?)),
+            (Some(Feature::ConfigChange), 0x00) => Ok(Event::ConfigChanged {
+                cookie: u16::from_be_bytes([message.data[0], message.data[1]]),
+            }),
+            (Some(Feature::ReprogControlsV4), 0x00) => {
+                Ok(Event::Buttons(controls::decode_buttons(&message.data)))
+            }
+            (Some(Feature::ReprogControlsV4), 0x01) => match message.data.get(..4) {
+                Some(&[dx_hi, dx_lo, dy_hi, dy_lo]) => Ok(Event::RawXY {
+                    dx: i16::from_be_bytes([dx_hi, dx_lo]),
+                    dy: i16::from_be_bytes([dy_hi, dy_lo]),
+                }),
+                _ => Ok(Event::Unknown(message)),
             },
-        )
-        .expect("Failed to write to device");
-        tracing::trace!("Done writing");
+            (Some(Feature::Crown), 0x00) => {
+                Ok(Event::Crown(CrownEvent::try_from(message.data.as_slice())?))
+            }
+            (None, _) if self.is_hidpp10_frame(&message) => {
+                match hidpp10::decode_notification(&message) {
+                    Some(Event::Battery(battery)) => Ok(self.battery_event(battery)),
+                    Some(Event::LinkQuality(quality)) => {
+                        self.reported_link_quality = Some(quality);
+                        Ok(Event::LinkQuality(quality))
+                    }
+                    Some(event) => Ok(event),
+                    None => Ok(Event::Unknown(message)),
+                }
+            }
+            _ => Ok(Event::Unknown(message)),
+        }
+    }
+
+    // Feature indexes not resolved yet look like HID++ 1.0 sub ids, frames
+    // are only decoded as such when the device speaks HID++ 1.0 or a
+    // receiver sends them about its link
+    fn is_hidpp10_frame(&mut self, message: &Message) -> bool {
+        if self.is_behind_receiver() && hidpp10::is_receiver_notification(message.feature_index) {
+            return true;
+        }
+        if self.protocol.is_none() && hidpp10::decode_notification(message).is_some() {
+            if let Err(err) = self.protocol_version() {
+                crate::tracing::debug!("Failed to read the protocol version: {}", err);
+            }
+        }
+        self.protocol.is_some_and(|(major, _)| major < 2)
+    }
 
-        let mut buf = [0u8; 7];
-        self.device.read_timeout(&mut buf, 100)?;
-        Ok(buf.to_vec())
+    // Whether the handle is a receiver's, for the receiver itself or a slot
+    pub fn is_behind_receiver(&self) -> bool {
+        consts::RECEIVER_PRODUCT_IDS.contains(&self.product_id)
+    }
+
+    // Entering slow charge or a thermal error is reported as a charging
+    // alert instead of a plain battery event, once per transition
+    fn battery_event(&mut self, battery: BatteryInfo) -> Event {
+        let battery = self.charging_source(battery);
+        let previous = self.battery_status.replace(battery.status.clone());
+        let alert = match battery.status {
+            BatteryStatus::SlowRecharge => ChargingAlert::SlowCharge,
+            BatteryStatus::ThermalError => ChargingAlert::Thermal,
+            _ => return Event::Battery(battery),
+        };
+        if previous.as_ref() == Some(&battery.status) {
+            return Event::Battery(battery);
+        }
+        crate::tracing::warn!("Charging alert {:?}: {:?}", alert, battery);
+        Event::ChargingAlert { alert, battery }
     }
 
     pub fn get_feature_index(&mut self, feature: Feature) -> anyhow::Result<u8> {
         let request = MessageBuilder::new_short(0x00, Function::RootGetFeature)
-            .device_index(0x01)
+            .report_id(self.report_id(2)?)
+            .device_index(self.device_index)
             .add_u16(feature.value())
             .build();
-        tracing::debug!("REQ {:?}: {}", feature, request.dump());
-        let response = request.send(self).unwrap();
-        tracing::debug!("RES {:?}: {}", feature, response.dump());
-        tracing::debug!("");
+        let response = self.in_transaction(|device| {
+            crate::tracing::debug!("REQ {:?}: {}", feature, request.dump());
+            let response = request.send(device)?;
+            crate::tracing::debug!("RES {:?}: {}", feature, response.dump());
+            Ok(response)
+        })?;
+        crate::tracing::debug!("");
         Ok(response.data[0])
     }
 
@@ -103,36 +1050,388 @@ impl Device {
             .ok_or_else(|| anyhow::anyhow!("Feature {:?} not found", feature))
     }
 
+    // Returns the cached index for the feature, asking the device the first
+    // time it's needed so callers don't have to run a full `init()`.
+    pub fn feature_index(&mut self, feature: Feature) -> anyhow::Result<u8> {
+        if let Ok(feature_index) = self.index_for(feature.clone()) {
+            return Ok(feature_index);
+        }
+
+        let feature_index = match feature {
+            Feature::Root => 0x00,
+            _ => self.get_feature_index(feature.clone())?,
+        };
+        if feature_index == 0 && feature != Feature::Root {
+            bail!("Feature {:?} not supported by device", feature);
+        }
+
+        self.features_index.insert(feature, feature_index);
+        Ok(feature_index)
+    }
+
     pub fn send_feature(
         &mut self,
         feature: Feature,
         function: Function,
         payload: &[u8],
     ) -> anyhow::Result<Message> {
-        let request = MessageBuilder::new_short(self.index_for(feature.clone())?, function)
-            .device_index(0x01)
+        let request = MessageBuilder::new_short(self.feature_index(feature.clone())?, function)
+            .report_id(self.report_id(payload.len())?)
+            .device_index(self.device_index)
             .data(payload.to_vec())
             .build();
-        tracing::debug!("REQ {:?}: {}", feature, request.dump());
-        let response = request.send(self).unwrap();
-        tracing::debug!("RES {:?}: {}", feature, response.dump());
-        tracing::debug!("");
+        let response = self.in_transaction(|device| {
+            crate::tracing::debug!("REQ {:?}: {}", feature, request.dump());
+            let response = request.send(device)?;
+            crate::tracing::debug!("RES {:?}: {}", feature, response.dump());
+            Ok(response)
+        })?;
+        crate::tracing::debug!("");
         Ok(response)
     }
 
-    pub fn get_battery(&mut self) -> anyhow::Result<(u8, BatteryLevel, BatteryStatus)> {
+    // Root getProtocolVersion echoes back the ping byte
+    pub fn ping(&mut self, value: u8) -> anyhow::Result<()> {
+        let result = self.send_feature(
+            Feature::Root,
+            Function::RootGetProtocolVersion,
+            &[0x00, 0x00, value],
+        )?;
+        if result.data[2] != value {
+            bail!(
+                "Ping mismatch: sent 0x{:02X}, got 0x{:02X}",
+                value,
+                result.data[2]
+            );
+        }
+        Ok(())
+    }
+
+    // (major, minor) HID++ protocol version
+    // HID++ 1.0 devices reject the request with an invalid sub id
+    pub fn protocol_version(&mut self) -> anyhow::Result<(u8, u8)> {
+        let result = self.send_feature(
+            Feature::Root,
+            Function::RootGetProtocolVersion,
+            &[0x00, 0x00, 0x00],
+        );
+        let version = match result {
+            Ok(result) => (result.data[0], result.data[1]),
+            Err(err) if err.downcast_ref::<Error>() == Some(&Error::InvalidSubId) => (1, 0),
+            Err(err) => return Err(err),
+        };
+        self.protocol = Some(version);
+        Ok(version)
+    }
+
+    pub fn unit_id(&mut self) -> anyhow::Result<[u8; 4]> {
+        let result = self.send_feature(
+            Feature::FirmwareInfo,
+            Function::FirmwareInfoGetDeviceInfo,
+            &[],
+        )?;
+        Ok([
+            result.data[1],
+            result.data[2],
+            result.data[3],
+            result.data[4],
+        ])
+    }
+
+    // firmware version of the main application entity (entity 0)
+    pub fn firmware_version(&mut self) -> anyhow::Result<FirmwareVersion> {
+        let result = self.send_feature(
+            Feature::FirmwareInfo,
+            Function::FirmwareInfoGetFwInfo,
+            &[0x00],
+        )?;
+        Ok(FirmwareVersion {
+            prefix: String::from_utf8_lossy(&result.data[1..4]).to_string(),
+            number: result.data[4],
+            revision: result.data[5],
+            build: u16::from_be_bytes([result.data[6], result.data[7]]),
+        })
+    }
+
+    // Cookie identifying the device's current configuration. Software that
+    // configures the device stores a new cookie with `set_config_cookie`, a
+    // different cookie later means someone else changed the settings.
+    pub fn config_cookie(&mut self) -> anyhow::Result<u16> {
+        let result =
+            self.send_feature(Feature::ConfigChange, Function::ConfigChangeGetCookie, &[])?;
+        Ok(u16::from_be_bytes([result.data[0], result.data[1]]))
+    }
+
+    // setConfigurationComplete, marks the configuration as done by us
+    pub fn set_config_cookie(&mut self, cookie: u16) -> anyhow::Result<()> {
+        self.send_feature(
+            Feature::ConfigChange,
+            Function::ConfigChangeSetComplete,
+            &cookie.to_be_bytes(),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_battery_capabilities(&mut self) -> anyhow::Result<BatteryCapabilities> {
+        if let Some(capabilities) = self.battery_capabilities {
+            return Ok(capabilities);
+        }
+
         let result = self.send_feature(
             Feature::UnifiedBattery,
-            Function::UnifiedBatteryGetStatus,
+            Function::UnifiedBatteryGetCapabilities,
             &[],
         )?;
-        tracing::debug!("Battery level: {}", result.dump());
+        self.check_reserved("Battery levels", result.data[0], 0x0F)?;
+        self.check_reserved("Battery flags", result.data[1], 0x03)?;
+        let capabilities = BatteryCapabilities {
+            supported_levels: result.data[0] & 0x0F,
+            rechargeable: result.data[1] & 0x01 != 0,
+            state_of_charge: result.data[1] & 0x02 != 0,
+        };
+        crate::tracing::debug!("Battery capabilities: {:?}", capabilities);
 
-        Ok((
-            result.data[0],
-            BatteryLevel::try_from(result.data[1])?,
-            BatteryStatus::try_from(result.data[2])?,
-        ))
+        self.battery_capabilities = Some(capabilities);
+        Ok(capabilities)
+    }
+
+    pub fn get_battery(&mut self) -> anyhow::Result<BatteryInfo> {
+        let battery = match self.battery_feature()? {
+            Feature::BatteryVoltage => self.get_battery_voltage()?,
+            Feature::AdcMeasurement => {
+                let result =
+                    self.send_feature(Feature::AdcMeasurement, Function::AdcMeasurementGet, &[])?;
+                crate::tracing::debug!("Battery ADC: {}", result.dump());
+                self.check_battery_reply(Feature::AdcMeasurement, &result.data)?;
+                BatteryInfo::from_adc(&result.data)
+                    .ok_or_else(|| anyhow::anyhow!("The device is off"))?
+            }
+            _ => {
+                let capabilities = self.get_battery_capabilities()?;
+                let result = self.send_feature(
+                    Feature::UnifiedBattery,
+                    Function::UnifiedBatteryGetStatus,
+                    &[],
+                )?;
+                crate::tracing::debug!("Battery level: {}", result.dump());
+                self.check_battery_reply(Feature::UnifiedBattery, &result.data)?;
+                BatteryInfo::from_status(&result.data, &capabilities)?
+            }
+        };
+        let battery = self.charging_source(battery);
+        self.battery_status = Some(battery.status.clone());
+        Ok(battery)
+    }
+
+    // Whether the device is reached through a Powerplay mat's receiver
+    pub fn is_powerplay(&self) -> bool {
+        POWERPLAY_RECEIVERS.contains(&self.product_id)
+    }
+
+    // A mouse can only charge through the mat while it's paired to the
+    // mat's receiver, plugging in a cable switches it to wired mode
+    fn charging_source(&self, mut battery: BatteryInfo) -> BatteryInfo {
+        if self.is_powerplay() && battery.status == BatteryStatus::Recharging {
+            battery.status = BatteryStatus::WirelessCharging;
+        }
+        battery
+    }
+
+    // BatteryVoltage only reports millivolts, the percentage is estimated from a
+    // typical Li-ion discharge curve
+    fn get_battery_voltage(&mut self) -> anyhow::Result<BatteryInfo> {
+        let result = self.send_feature(
+            Feature::BatteryVoltage,
+            Function::BatteryVoltageGetBatteryInfo,
+            &[],
+        )?;
+        crate::tracing::debug!("Battery voltage: {}", result.dump());
+        self.check_battery_reply(Feature::BatteryVoltage, &result.data)?;
+
+        Ok(BatteryInfo::from_voltage(&result.data))
+    }
+
+    // Battery replies and events share their layout
+    fn check_battery_reply(&self, feature: Feature, data: &[u8]) -> anyhow::Result<()> {
+        match feature {
+            Feature::UnifiedBattery => {
+                self.check_reserved("Battery level", data[1], 0x0F)?;
+                // external power: 0 none, 1 wired, 2 wireless
+                if data.get(3).is_some_and(|power| *power > 2) && self.strict {
+                    bail!("Unknown external power source 0x{:02X}", data[3]);
+                }
+                Ok(())
+            }
+            Feature::BatteryVoltage => self.check_reserved("Battery voltage flags", data[2], 0x93),
+            Feature::AdcMeasurement => self.check_reserved("Battery ADC flags", data[2], 0x03),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Replies echo the device index, feature index (or sub id) and function
+// byte of the request. Errors carry those two bytes after 0xFF or 0x8F.
+fn is_reply_to(reply: &[u8], request: &[u8]) -> bool {
+    if reply.len() < 5 || request.len() < 4 || reply[1] != request[1] {
+        return false;
+    }
+    match reply[2] {
+        0xFF | 0x8F if reply[3..5] == request[2..4] => true,
+        _ => reply[2..4] == request[2..4],
+    }
+}
+
+// (percentage, millivolts) pairs, from full to empty
+const VOLTAGE_CURVE: [(u8, u16); 13] = [
+    (100, 4186),
+    (90, 4067),
+    (80, 3989),
+    (70, 3922),
+    (60, 3859),
+    (50, 3811),
+    (40, 3778),
+    (30, 3751),
+    (20, 3717),
+    (10, 3671),
+    (5, 3646),
+    (2, 3579),
+    (0, 3500),
+];
+
+fn percentage_from_voltage(voltage: u16) -> u8 {
+    if voltage >= VOLTAGE_CURVE[0].1 {
+        return 100;
+    }
+
+    for pair in VOLTAGE_CURVE.windows(2) {
+        let (high_pct, high_mv) = pair[0];
+        let (low_pct, low_mv) = pair[1];
+        if voltage >= low_mv {
+            let span = (high_mv - low_mv) as u32;
+            let offset = (voltage - low_mv) as u32;
+            return low_pct + ((high_pct - low_pct) as u32 * offset / span) as u8;
+        }
+    }
+
+    0
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct FirmwareVersion {
+    pub prefix: String,
+    // number, revision and build are BCD encoded
+    pub number: u8,
+    pub revision: u8,
+    pub build: u16,
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:02X}.{:02X}.B{:04X}",
+            self.prefix, self.number, self.revision, self.build
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct BatteryCapabilities {
+    // bitmask of the levels the device reports: critical, low, good, full
+    pub supported_levels: u8,
+    pub rechargeable: bool,
+    // whether the device reports an actual percentage
+    pub state_of_charge: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BatteryInfo {
+    pub percentage: u8,
+    // the percentage was derived from the level or voltage instead of being
+    // reported by the device
+    pub estimated: bool,
+    pub level: BatteryLevel,
+    pub status: BatteryStatus,
+    // millivolts, only for devices with BatteryVoltage
+    pub voltage: Option<u16>,
+}
+
+impl BatteryInfo {
+    // decodes a UnifiedBattery getStatus reply or battery_status_event
+    fn from_status(data: &[u8], capabilities: &BatteryCapabilities) -> anyhow::Result<Self> {
+        let level = BatteryLevel::try_from(data[1])?;
+        let mut status = BatteryStatus::try_from(data[2])?;
+        // external power: 0 none, 1 wired, 2 wireless
+        if status == BatteryStatus::Recharging && data.get(3) == Some(&0x02) {
+            status = BatteryStatus::WirelessCharging;
+        }
+        if capabilities.state_of_charge {
+            Ok(BatteryInfo {
+                percentage: data[0],
+                estimated: false,
+                level,
+                status,
+                voltage: None,
+            })
+        } else {
+            Ok(BatteryInfo {
+                percentage: level.approximate_percentage(),
+                estimated: true,
+                level,
+                status,
+                voltage: None,
+            })
+        }
+    }
+
+    // decodes an AdcMeasurement reply or event, None while the device is off
+    fn from_adc(data: &[u8]) -> Option<Self> {
+        let voltage = u16::from_be_bytes([data[0], data[1]]);
+        let flags = data[2];
+        // bit 0 = measurement valid, bit 1 = charging
+        if flags & 0x01 == 0 {
+            return None;
+        }
+        let percentage = percentage_from_voltage(voltage);
+        Some(BatteryInfo {
+            percentage,
+            estimated: true,
+            level: BatteryLevel::from_percentage(percentage),
+            status: if flags & 0x02 != 0 {
+                BatteryStatus::Recharging
+            } else {
+                BatteryStatus::Discharging
+            },
+            voltage: Some(voltage),
+        })
+    }
+
+    // decodes a BatteryVoltage get_battery_info reply or battery_voltage_event
+    fn from_voltage(data: &[u8]) -> Self {
+        let voltage = u16::from_be_bytes([data[0], data[1]]);
+        let flags = data[2];
+        let percentage = percentage_from_voltage(voltage);
+        // bit 7 = external power, bits 0-1 = charge status, bit 4 = slow charge
+        let status = if flags & 0x80 == 0 {
+            BatteryStatus::Discharging
+        } else if flags & 0x10 != 0 {
+            BatteryStatus::SlowRecharge
+        } else {
+            match flags & 0x03 {
+                0x00 => BatteryStatus::Recharging,
+                0x01 => BatteryStatus::Full,
+                0x02 => BatteryStatus::Discharging,
+                _ => BatteryStatus::InvalidBattery,
+            }
+        };
+
+        BatteryInfo {
+            percentage,
+            estimated: true,
+            level: BatteryLevel::from_percentage(percentage),
+            status,
+            voltage: Some(voltage),
+        }
     }
 }
 
@@ -145,6 +1444,8 @@ pub enum BatteryStatus {
     SlowRecharge,
     InvalidBattery,
     ThermalError,
+    // charging on a Powerplay mat or another wireless charger
+    WirelessCharging,
 }
 
 impl TryFrom<u8> for BatteryStatus {
@@ -187,3 +1488,26 @@ impl TryFrom<u8> for BatteryLevel {
         }
     }
 }
+
+impl BatteryLevel {
+    // rough midpoint of each level's range
+    pub fn approximate_percentage(&self) -> u8 {
+        match self {
+            BatteryLevel::Full => 90,
+            BatteryLevel::Good => 50,
+            BatteryLevel::Low => 20,
+            BatteryLevel::Critical => 5,
+            BatteryLevel::Empty => 0,
+        }
+    }
+
+    pub(crate) fn from_percentage(percentage: u8) -> Self {
+        match percentage {
+            0 => BatteryLevel::Empty,
+            1..=5 => BatteryLevel::Critical,
+            6..=20 => BatteryLevel::Low,
+            21..=80 => BatteryLevel::Good,
+            _ => BatteryLevel::Full,
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::{fmt, time::Instant};
+
+use crate::{consts::RECEIVER_PRODUCT_IDS, Device, Receiver};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    Skip,
+}
+
+#[derive(Clone, Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn push(&mut self, name: &'static str, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name,
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    pub fn healthy(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Fail)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = match check.status {
+                CheckStatus::Pass => "ok",
+                CheckStatus::Warn => "warn",
+                CheckStatus::Fail => "FAIL",
+                CheckStatus::Skip => "skip",
+            };
+            writeln!(f, "[{:>4}] {:<20} {}", status, check.name, check.detail)?;
+        }
+        Ok(())
+    }
+}
+
+// Runs the checks in order, stopping at the first one the rest depend on
+pub fn diagnose(vendor_id: u16, product_id: u16) -> Report {
+    let mut report = Report::default();
+
+    let paths = match hidapi::HidApi::new() {
+        Ok(api) => api
+            .device_list()
+            .filter(|d| d.vendor_id() == vendor_id && d.product_id() == product_id)
+            .map(|d| d.path().to_string_lossy().to_string())
+            .collect::<Vec<_>>(),
+        Err(err) => {
+            report.push("hidapi", CheckStatus::Fail, err.to_string());
+            return report;
+        }
+    };
+    if paths.is_empty() {
+        report.push(
+            "Device present",
+            CheckStatus::Fail,
+            format!(
+                "no HID device {:04x}:{:04x} connected",
+                vendor_id, product_id
+            ),
+        );
+        return report;
+    }
+    report.push(
+        "Device present",
+        CheckStatus::Pass,
+        format!("{} interface(s)", paths.len()),
+    );
+
+    check_permissions(&mut report, &paths);
+
+    // behind a receiver, check the device in slot 1 through the receiver's
+    // handle so both can be asked
+    let opened = if RECEIVER_PRODUCT_IDS.contains(&product_id) {
+        Receiver::open(vendor_id, product_id).and_then(|receiver| {
+            let device = receiver.device_in(1)?;
+            Ok((device, Some(receiver)))
+        })
+    } else {
+        Device::new(vendor_id, product_id).map(|device| (device, None))
+    };
+    let (mut device, mut receiver) = match opened {
+        Ok(opened) => opened,
+        Err(err) => {
+            report.push("Open", CheckStatus::Fail, err.to_string());
+            return report;
+        }
+    };
+    report.push("Open", CheckStatus::Pass, "");
+
+    match device.protocol_version() {
+        Ok((major, minor)) if major >= 2 => report.push(
+            "Protocol version",
+            CheckStatus::Pass,
+            format!("{}.{}", major, minor),
+        ),
+        Ok((major, minor)) => report.push(
+            "Protocol version",
+            CheckStatus::Warn,
+            format!("{}.{}, only HID++ 2.0 features are supported", major, minor),
+        ),
+        Err(err) => {
+            report.push(
+                "Protocol version",
+                CheckStatus::Fail,
+                format!("no reply: {}", err),
+            );
+            return report;
+        }
+    }
+
+    let start = Instant::now();
+    match device.init() {
+        Ok(()) => {
+            let elapsed = start.elapsed();
+            let status = if elapsed.as_millis() > 1000 {
+                CheckStatus::Warn
+            } else {
+                CheckStatus::Pass
+            };
+            report.push("Feature discovery", status, format!("{:?}", elapsed));
+        }
+        Err(err) => report.push("Feature discovery", CheckStatus::Fail, err.to_string()),
+    }
+
+    match device.measure_latency(10) {
+        Ok(stats) => {
+            let status = if stats.avg.as_millis() > 50 {
+                CheckStatus::Warn
+            } else {
+                CheckStatus::Pass
+            };
+            report.push(
+                "Latency",
+                status,
+                format!("avg {:?}, p95 {:?}", stats.avg, stats.p95),
+            );
+        }
+        Err(err) => report.push("Latency", CheckStatus::Fail, err.to_string()),
+    }
+
+    if let Some(receiver) = &mut receiver {
+        match receiver.firmware_info() {
+            Ok(firmware) => {
+                report.push("Receiver firmware", CheckStatus::Pass, firmware.to_string())
+            }
+            Err(err) => report.push("Receiver firmware", CheckStatus::Skip, err.to_string()),
+        }
+    }
+
+    match device.firmware_version() {
+        Ok(version) => report.push("Firmware", CheckStatus::Pass, version.to_string()),
+        Err(err) => report.push("Firmware", CheckStatus::Skip, err.to_string()),
+    }
+
+    match device.get_battery() {
+        Ok(battery) => report.push(
+            "Battery",
+            CheckStatus::Pass,
+            format!("{}% {:?}", battery.percentage, battery.status),
+        ),
+        Err(err) => report.push("Battery", CheckStatus::Skip, err.to_string()),
+    }
+
+    match device.temperature() {
+        Ok(Some(celsius)) => {
+            report.push("Temperature", CheckStatus::Pass, format!("{} °C", celsius))
+        }
+        Ok(None) => report.push("Temperature", CheckStatus::Skip, "not exposed"),
+        Err(err) => report.push("Temperature", CheckStatus::Skip, err.to_string()),
+    }
+
+    // by now the checks above have sent enough requests for an estimate
+    let link = device.link_quality();
+    let status = if link.quality < 80 {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    };
+    let detail = if link.estimated {
+        format!("~{}%, estimated from lost requests", link.quality)
+    } else {
+        format!("{}%", link.quality)
+    };
+    report.push("Link quality", status, detail);
+
+    report
+}
+
+// hidapi only reports a generic error when the node isn't accessible, so try
+// opening the hidraw nodes ourselves to tell permission problems apart
+fn check_permissions(report: &mut Report, paths: &[String]) {
+    let nodes: Vec<_> = paths.iter().filter(|p| p.starts_with("/dev/")).collect();
+    if nodes.is_empty() {
+        return;
+    }
+
+    let denied: Vec<_> = nodes
+        .iter()
+        .filter(|path| {
+            matches!(
+                std::fs::OpenOptions::new().read(true).write(true).open(path),
+                Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied
+            )
+        })
+        .map(|path| path.as_str())
+        .collect();
+    if denied.is_empty() {
+        report.push("Permissions", CheckStatus::Pass, "");
+    } else {
+        report.push(
+            "Permissions",
+            CheckStatus::Fail,
+            format!(
+                "no read/write access to {}, add a udev rule granting access",
+                denied.join(", ")
+            ),
+        );
+    }
+}
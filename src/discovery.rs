@@ -0,0 +1,376 @@
+use std::{
+    ffi::{CStr, CString},
+    time::Duration,
+};
+
+use anyhow::bail;
+
+use crate::{consts, Device, Error, ReportId};
+
+const LOGITECH: u16 = 0x046d;
+
+// HID++ is only spoken on the vendor defined collections: 0xFF00 for USB and
+// receivers, 0xFF43 for Bluetooth LE devices. Opening any other interface of
+// the same device would read (and swallow) its keystrokes or pointer reports.
+pub(crate) fn is_hidpp_collection(info: &hidapi::DeviceInfo) -> bool {
+    matches!(info.usage_page(), 0xFF00 | 0xFF43)
+}
+
+// Path of the HID++ interface of the first device with the given ids
+pub(crate) fn hidpp_path(
+    api: &hidapi::HidApi,
+    vendor_id: u16,
+    product_id: u16,
+) -> anyhow::Result<CString> {
+    let mut interfaces = api
+        .device_list()
+        .filter(|d| d.vendor_id() == vendor_id && d.product_id() == product_id)
+        .peekable();
+    if interfaces.peek().is_none() {
+        bail!("No device {:04x}:{:04x} found", vendor_id, product_id);
+    }
+    match interfaces.find(|d| is_hidpp_collection(d)) {
+        Some(info) => Ok(info.path().to_owned()),
+        None => bail!(
+            "Device {:04x}:{:04x} has no HID++ interface",
+            vendor_id,
+            product_id
+        ),
+    }
+}
+
+// Refuses paths that aren't a HID++ interface
+pub(crate) fn check_path(api: &hidapi::HidApi, path: &CStr) -> anyhow::Result<()> {
+    match api.device_list().find(|d| d.path() == path) {
+        Some(info) if is_hidpp_collection(info) => Ok(()),
+        Some(info) => bail!(
+            "{} is not a HID++ interface (usage page 0x{:04X})",
+            path.to_string_lossy(),
+            info.usage_page()
+        ),
+        None => bail!("No device at {}", path.to_string_lossy()),
+    }
+}
+
+// One physical device as seen by hidapi, reduced to its HID++ interface
+pub(crate) struct Candidate {
+    pub(crate) path: CString,
+    pub(crate) vendor_id: u16,
+    pub(crate) product_id: u16,
+    pub(crate) product: String,
+    pub(crate) serial_number: Option<String>,
+    // reports the HID++ interface declares a collection for
+    pub(crate) report_lengths: Vec<ReportId>,
+    // interface number of the HID++ channel, -1 over Bluetooth
+    pub(crate) interface_number: i32,
+    // every interface of the device, keyboard and pointer ones included
+    pub(crate) interfaces: Vec<i32>,
+}
+
+impl Candidate {
+    pub(crate) fn is_receiver(&self) -> bool {
+        consts::RECEIVER_PRODUCT_IDS.contains(&self.product_id)
+    }
+
+    pub(crate) fn open(&self) -> anyhow::Result<Device> {
+        open_path(&self.path, self.is_receiver())
+    }
+}
+
+// Wired and Bluetooth devices answer on index 0xFF, receivers are opened on
+// their first slot
+fn open_path(path: &CStr, receiver: bool) -> anyhow::Result<Device> {
+    let device_index = if receiver { 0x01 } else { 0xFF };
+    Device::builder()
+        .path(path.to_bytes())?
+        .device_index(device_index)
+        .open()
+}
+
+pub(crate) fn candidates() -> anyhow::Result<Vec<Candidate>> {
+    let api = hidapi::HidApi::new()?;
+    let mut candidates = vec![];
+    for unit in units(api.device_list().filter(|d| d.vendor_id() == LOGITECH)) {
+        let Some(info) = unit.iter().find(|d| is_hidpp_collection(d)) else {
+            continue;
+        };
+        let mut interfaces = unit
+            .iter()
+            .map(|d| d.interface_number())
+            .collect::<Vec<_>>();
+        interfaces.dedup();
+        let mut report_lengths = unit
+            .iter()
+            .filter(|d| is_hidpp_collection(d))
+            .filter_map(|d| report_for_usage(d.usage()))
+            .collect::<Vec<_>>();
+        // backends that list one entry per interface don't say which
+        if report_lengths.is_empty() {
+            report_lengths = vec![ReportId::Short, ReportId::Long, ReportId::VeryLong];
+        }
+        report_lengths.dedup();
+        candidates.push(Candidate {
+            path: info.path().to_owned(),
+            vendor_id: info.vendor_id(),
+            product_id: info.product_id(),
+            product: info.product_string().unwrap_or_default().to_string(),
+            serial_number: info.serial_number().map(str::to_string),
+            report_lengths,
+            interface_number: info.interface_number(),
+            interfaces,
+        });
+    }
+    Ok(candidates)
+}
+
+// Each HID++ report has its own collection, usage 1 for short reports, 2 for
+// long and 4 for very long ones (0x0202 and 0x0204 over Bluetooth LE)
+fn report_for_usage(usage: u16) -> Option<ReportId> {
+    match usage & 0xFF {
+        0x01 => Some(ReportId::Short),
+        0x02 => Some(ReportId::Long),
+        0x04 => Some(ReportId::VeryLong),
+        _ => None,
+    }
+}
+
+// hidapi lists one entry per interface and collection, those of a device
+// come one after the other. An entry belongs to the device before it when
+// the ids and serial match and it's another collection of the same
+// interface or an interface that device hasn't listed yet, so two identical
+// devices without a serial number stay apart.
+fn units<'a>(
+    infos: impl Iterator<Item = &'a hidapi::DeviceInfo>,
+) -> Vec<Vec<&'a hidapi::DeviceInfo>> {
+    let mut units: Vec<Vec<&hidapi::DeviceInfo>> = vec![];
+    for info in infos {
+        let same_unit = units.last().is_some_and(|unit| {
+            let last = unit[unit.len() - 1];
+            last.vendor_id() == info.vendor_id()
+                && last.product_id() == info.product_id()
+                && last.serial_number() == info.serial_number()
+                && (last.interface_number() == info.interface_number()
+                    || unit
+                        .iter()
+                        .all(|d| d.interface_number() != info.interface_number()))
+        });
+        match units.last_mut() {
+            Some(unit) if same_unit => unit.push(info),
+            _ => units.push(vec![info]),
+        }
+    }
+    units
+}
+
+// A HID++ device found by `enumerate`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeviceInfo {
+    pub path: CString,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    // the HID++ name of wired devices, the product string of receivers
+    pub name: String,
+    pub serial_number: Option<String>,
+    // a receiver, whose devices answer on indexes 1-6, rather than a device
+    // connected with a cable or over Bluetooth
+    pub receiver: bool,
+    pub report_lengths: Vec<ReportId>,
+}
+
+impl DeviceInfo {
+    // Opens the device, or the one in the first slot of a receiver
+    pub fn open(&self) -> anyhow::Result<Device> {
+        open_path(&self.path, self.receiver)
+    }
+}
+
+// Every Logitech device with a HID++ interface that answers a ping. Wired
+// and Bluetooth devices answer on index 0xFF, receivers reply with a HID++
+// 1.0 error there since they don't have the 2.0 Root feature.
+pub fn enumerate() -> anyhow::Result<Vec<DeviceInfo>> {
+    let mut found = vec![];
+    for candidate in candidates()? {
+        let probe = Device::builder()
+            .path(candidate.path.clone())?
+            .device_index(0xFF)
+            .timeout(Duration::from_millis(200))
+            .open();
+        let mut device = match probe {
+            Ok(device) => device,
+            Err(err) => {
+                crate::tracing::debug!("Skipping {}: {}", candidate.product, err);
+                continue;
+            }
+        };
+        let receiver = match device.ping(0x5A) {
+            Ok(()) => false,
+            Err(err) if err.downcast_ref::<Error>().is_some() => true,
+            Err(err) => {
+                crate::tracing::debug!("{} didn't answer a ping: {}", candidate.product, err);
+                continue;
+            }
+        };
+        let name = match receiver {
+            true => candidate.product.clone(),
+            false => device
+                .product_name()
+                .unwrap_or_else(|_| candidate.product.clone()),
+        };
+        found.push(DeviceInfo {
+            path: candidate.path,
+            vendor_id: candidate.vendor_id,
+            product_id: candidate.product_id,
+            name,
+            serial_number: candidate.serial_number,
+            receiver,
+            report_lengths: candidate.report_lengths,
+        });
+    }
+    Ok(found)
+}
+
+impl Device {
+    // Opens the one device `predicate` picks out of those `enumerate` finds,
+    // e.g. `Device::open_where(|info| info.product_id == 0xc547 && !info.receiver)`
+    pub fn open_where(mut predicate: impl FnMut(&DeviceInfo) -> bool) -> anyhow::Result<Device> {
+        let found = enumerate()?;
+        let mut matches = found.iter().filter(|info| predicate(info));
+        match (matches.next(), matches.next()) {
+            (Some(info), None) => info.open(),
+            (None, _) => bail!(
+                "No device matches, found: {}",
+                describe_all(found.iter().map(|i| (&*i.name, i.vendor_id, i.product_id)))
+            ),
+            (Some(first), Some(second)) => bail!(
+                "Several devices match: {}, be more specific",
+                describe_all([first, second].into_iter().chain(matches).map(|i| (
+                    &*i.name,
+                    i.vendor_id,
+                    i.product_id
+                )))
+            ),
+        }
+    }
+
+    // Opens the device with this serial number, ignoring case: the USB serial
+    // of wired devices and receivers, or the unit id of devices behind a
+    // receiver, which is the serial printed on them
+    pub fn open_by_serial(serial: &str) -> anyhow::Result<Device> {
+        let serial = serial.trim().to_uppercase();
+        let candidates = candidates()?;
+
+        let mut matches = candidates.iter().filter(|c| {
+            c.serial_number
+                .as_deref()
+                .is_some_and(|s| s.trim().to_uppercase() == serial)
+        });
+        match (matches.next(), matches.next()) {
+            (Some(candidate), None) => return candidate.open(),
+            (Some(_), Some(_)) => bail!("Several devices have serial number {}", serial),
+            (None, _) => {}
+        }
+
+        for candidate in &candidates {
+            let Ok(mut device) = candidate.open() else {
+                continue;
+            };
+            let unit_id = device
+                .unit_id()
+                .ok()
+                .map(|id| id.iter().map(|b| format!("{:02X}", b)).collect::<String>());
+            if unit_id.as_deref() == Some(&*serial) {
+                return Ok(device);
+            }
+        }
+        let serials = candidates
+            .iter()
+            .filter_map(|c| c.serial_number.as_deref())
+            .filter(|s| !s.trim().is_empty())
+            .collect::<Vec<_>>();
+        match serials.is_empty() {
+            true => bail!("No device with serial number {}", serial),
+            false => bail!(
+                "No device with serial number {}, found: {}",
+                serial,
+                serials.join(", ")
+            ),
+        }
+    }
+
+    // Opens the one device whose product string or HID++ name contains
+    // `name`, ignoring case, e.g. `Device::open_matching("Superlight")`
+    pub fn open_matching(name: &str) -> anyhow::Result<Device> {
+        let needle = name.to_lowercase();
+        let candidates = candidates()?;
+
+        let matches = candidates
+            .iter()
+            .filter(|c| c.product.to_lowercase().contains(&needle))
+            .collect::<Vec<_>>();
+        // receivers report their own product string, ask the devices for
+        // their names when that didn't help
+        let mut opened = vec![];
+        if matches.is_empty() {
+            for candidate in &candidates {
+                let Ok(mut device) = candidate.open() else {
+                    continue;
+                };
+                if let Ok(product) = device.product_name() {
+                    if product.to_lowercase().contains(&needle) {
+                        opened.push((product, device));
+                    }
+                }
+            }
+        }
+
+        let describe = |product: &str, vendor_id: u16, product_id: u16| {
+            format!("{} ({:04x}:{:04x})", product, vendor_id, product_id)
+        };
+        match (matches.len(), opened.len()) {
+            (1, _) => matches[0].open(),
+            (0, 1) => Ok(opened.remove(0).1),
+            (0, 0) => {
+                let found = candidates
+                    .iter()
+                    .map(|c| describe(&c.product, c.vendor_id, c.product_id))
+                    .collect::<Vec<_>>();
+                if found.is_empty() {
+                    bail!("No device matches \"{}\", no HID++ devices found", name);
+                }
+                bail!(
+                    "No device matches \"{}\", found: {}",
+                    name,
+                    found.join(", ")
+                )
+            }
+            _ => {
+                let found = matches
+                    .iter()
+                    .map(|c| describe(&c.product, c.vendor_id, c.product_id))
+                    .chain(
+                        opened
+                            .iter()
+                            .map(|(product, d)| describe(product, d.vendor_id(), d.product_id())),
+                    )
+                    .collect::<Vec<_>>();
+                bail!(
+                    "\"{}\" matches several devices: {}, be more specific",
+                    name,
+                    found.join(", ")
+                )
+            }
+        }
+    }
+}
+
+fn describe_all<'a>(devices: impl Iterator<Item = (&'a str, u16, u16)>) -> String {
+    let described = devices
+        .map(|(name, vendor_id, product_id)| {
+            format!("{} ({:04x}:{:04x})", name, vendor_id, product_id)
+        })
+        .collect::<Vec<_>>();
+    if described.is_empty() {
+        return "none".to_string();
+    }
+    described.join(", ")
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+
+use anyhow::bail;
+
+use crate::{Device, Feature, Function};
+
+// values at or above this in a getSensorDpiList reply are a step, meaning
+// every value from the previous entry to the next one in `value & 0x1FFF`
+// increments is supported
+const DPI_STEP_MARKER: u16 = 0xE000;
+
+// A DPI value known to be supported by the sensor it was built for, the only
+// kind `set_dpi` accepts
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Dpi(u16);
+
+impl Dpi {
+    // Rounds `value` to the closest supported DPI, clamping it to the sensor
+    // range
+    pub fn new(value: u16, capabilities: &DpiCapabilities) -> Self {
+        Dpi(capabilities.nearest(value))
+    }
+
+    // Fails instead of rounding when the sensor doesn't support `value`
+    pub fn exact(value: u16, capabilities: &DpiCapabilities) -> anyhow::Result<Self> {
+        if !capabilities.supports(value) {
+            bail!(
+                "{} DPI is not supported by sensor {} ({}-{})",
+                value,
+                capabilities.sensor,
+                capabilities.min(),
+                capabilities.max()
+            );
+        }
+        Ok(Dpi(value))
+    }
+
+    pub fn value(&self) -> u16 {
+        self.0
+    }
+}
+
+impl fmt::Display for Dpi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} DPI", self.0)
+    }
+}
+
+// Supported DPI values of a sensor, as (min, max, step) ranges in ascending
+// order. A single value is a range with min == max.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DpiCapabilities {
+    pub sensor: u8,
+    pub ranges: Vec<(u16, u16, u16)>,
+}
+
+impl DpiCapabilities {
+    // decodes the DPI list of a getSensorDpiList reply, sensor index excluded
+    fn from_list(sensor: u8, data: &[u8]) -> anyhow::Result<Self> {
+        let mut ranges: Vec<(u16, u16, u16)> = vec![];
+        let mut step = None;
+        for chunk in data.chunks_exact(2) {
+            let value = u16::from_be_bytes([chunk[0], chunk[1]]);
+            match value {
+                0 => break,
+                value if value >= DPI_STEP_MARKER => step = Some(value & 0x1FFF),
+                value => match (step.take(), ranges.last_mut()) {
+                    (Some(step), Some(last)) if step > 0 => {
+                        *last = (last.0, value, step);
+                    }
+                    _ => ranges.push((value, value, 1)),
+                },
+            }
+        }
+        if ranges.is_empty() {
+            bail!("Sensor {} reported no DPI values", sensor);
+        }
+
+        Ok(Self { sensor, ranges })
+    }
+
+    pub fn min(&self) -> u16 {
+        self.ranges[0].0
+    }
+
+    pub fn max(&self) -> u16 {
+        self.ranges[self.ranges.len() - 1].1
+    }
+
+    pub fn supports(&self, value: u16) -> bool {
+        self.ranges.iter().any(|(min, max, step)| {
+            (*min..=*max).contains(&value) && (value - min).is_multiple_of(*step)
+        })
+    }
+
+    // The supported value closest to `value`, the lower one on a tie
+    pub fn nearest(&self, value: u16) -> u16 {
+        self.ranges
+            .iter()
+            .flat_map(|(min, max, step)| {
+                let clamped = value.clamp(*min, *max);
+                let below = clamped - (clamped - min) % step;
+                let above = if below == clamped {
+                    below
+                } else {
+                    below.saturating_add(*step).min(*max)
+                };
+                [below, above]
+            })
+            .min_by_key(|candidate| (candidate.abs_diff(value), *candidate))
+            .unwrap_or(value)
+    }
+
+    // Every supported value, in ascending order
+    pub fn values(&self) -> Vec<u16> {
+        self.ranges
+            .iter()
+            .flat_map(|(min, max, step)| (*min..=*max).step_by(*step as usize))
+            .collect()
+    }
+}
+
+impl Device {
+    pub fn dpi_sensor_count(&mut self) -> anyhow::Result<u8> {
+        let result = self.send_feature(
+            Feature::AdjustableDpi,
+            Function::AdjustableDpiGetSensorCount,
+            &[],
+        )?;
+        Ok(result.data[0])
+    }
+
+    pub fn dpi_capabilities(&mut self, sensor: u8) -> anyhow::Result<DpiCapabilities> {
+        if let Some(capabilities) = self.dpi_capabilities.get(&sensor) {
+            return Ok(capabilities.clone());
+        }
+
+        let result = self.send_feature(
+            Feature::AdjustableDpi,
+            Function::AdjustableDpiGetSensorDpiList,
+            &[sensor],
+        )?;
+        let capabilities = DpiCapabilities::from_list(sensor, &result.data[1..])?;
+        self.dpi_capabilities.insert(sensor, capabilities.clone());
+        Ok(capabilities)
+    }
+
+    // Current and default DPI of the sensor
+    pub fn get_dpi(&mut self, sensor: u8) -> anyhow::Result<(u16, u16)> {
+        let result = self.send_feature(
+            Feature::AdjustableDpi,
+            Function::AdjustableDpiGetSensorDpi,
+            &[sensor],
+        )?;
+        let current = u16::from_be_bytes([result.data[1], result.data[2]]);
+        let default = u16::from_be_bytes([result.data[3], result.data[4]]);
+        Ok((current, default))
+    }
+
+    pub fn set_dpi(&mut self, sensor: u8, dpi: Dpi) -> anyhow::Result<()> {
+        let mut payload = vec![sensor];
+        payload.extend_from_slice(&dpi.value().to_be_bytes());
+        self.send_feature(
+            Feature::AdjustableDpi,
+            Function::AdjustableDpiSetSensorDpi,
+            &payload,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+
+use crate::{consts::*, Message};
+
+// Why a device rejected a request. HID++ 2.0 devices answer with feature
+// index 0xFF, HID++ 1.0 receivers and devices with sub id 0x8F, and the two
+// number their codes differently.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Error {
+    // HID++ 2.0
+    Unknown,
+    InvalidArgument,
+    OutOfRange,
+    HardwareError,
+    Internal,
+    InvalidFeatureIndex,
+    InvalidFunction,
+    Busy,
+    Unsupported,
+    // HID++ 1.0
+    InvalidSubId,
+    InvalidAddress,
+    InvalidValue,
+    ConnectionFailed,
+    TooManyDevices,
+    AlreadyExists,
+    UnknownDevice,
+    ResourceError,
+    RequestUnavailable,
+    InvalidParameter,
+    WrongPinCode,
+    // (feature index of the error frame, code)
+    Other(u8, u8),
+}
+
+impl Error {
+    // The error an error frame carries, None for any other frame
+    pub fn from_message(message: &Message) -> Option<Self> {
+        let code = message.error_code()?;
+        Some(match message.feature_index() {
+            HIDPP20_ERROR => Self::from_hidpp20(code),
+            _ => Self::from_hidpp10(code),
+        })
+    }
+
+    pub fn from_hidpp20(code: u8) -> Self {
+        match code {
+            ERR_UNKNOWN => Error::Unknown,
+            ERR_INVALID_ARGUMENT => Error::InvalidArgument,
+            ERR_OUT_OF_RANGE => Error::OutOfRange,
+            ERR_HARDWARE_ERROR => Error::HardwareError,
+            ERR_INTERNAL => Error::Internal,
+            ERR_INVALID_FEATURE_INDEX => Error::InvalidFeatureIndex,
+            ERR_INVALID_FUNCTION => Error::InvalidFunction,
+            ERR_BUSY => Error::Busy,
+            ERR_UNSUPPORTED => Error::Unsupported,
+            code => Error::Other(HIDPP20_ERROR, code),
+        }
+    }
+
+    pub fn from_hidpp10(code: u8) -> Self {
+        match code {
+            ERR10_INVALID_SUB_ID => Error::InvalidSubId,
+            ERR10_INVALID_ADDRESS => Error::InvalidAddress,
+            ERR10_INVALID_VALUE => Error::InvalidValue,
+            ERR10_CONNECTION_FAILED => Error::ConnectionFailed,
+            ERR10_TOO_MANY_DEVICES => Error::TooManyDevices,
+            ERR10_ALREADY_EXISTS => Error::AlreadyExists,
+            ERR10_BUSY => Error::Busy,
+            ERR10_UNKNOWN_DEVICE => Error::UnknownDevice,
+            ERR10_RESOURCE_ERROR => Error::ResourceError,
+            ERR10_REQUEST_UNAVAILABLE => Error::RequestUnavailable,
+            ERR10_INVALID_PARAMETER => Error::InvalidParameter,
+            ERR10_WRONG_PIN_CODE => Error::WrongPinCode,
+            code => Error::Other(HIDPP10_ERROR, code),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Error::Unknown => "unknown",
+            Error::InvalidArgument => "invalid argument",
+            Error::OutOfRange => "out of range",
+            Error::HardwareError => "hardware error",
+            Error::Internal => "internal error",
+            Error::InvalidFeatureIndex => "invalid feature index",
+            Error::InvalidFunction => "invalid function",
+            Error::Busy => "busy",
+            Error::Unsupported => "unsupported",
+            Error::InvalidSubId => "invalid sub id",
+            Error::InvalidAddress => "invalid address",
+            Error::InvalidValue => "invalid value",
+            Error::ConnectionFailed => "connection request failed",
+            Error::TooManyDevices => "too many devices",
+            Error::AlreadyExists => "already exists",
+            Error::UnknownDevice => "unknown device",
+            Error::ResourceError => "resource error",
+            Error::RequestUnavailable => "request unavailable",
+            Error::InvalidParameter => "invalid parameter value",
+            Error::WrongPinCode => "wrong pin code",
+            Error::Other(_, code) => return write!(f, "unknown error code 0x{:02X}", code),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,59 @@
+use crate::{BacklightConfig, BatteryInfo, Cid, CrownEvent, Message};
+
+// Unsolicited notifications sent by the device
+#[derive(Clone, Debug)]
+pub enum Event {
+    // UnifiedBattery battery_status_event, sent on charge state changes
+    Battery(BatteryInfo),
+    // the battery started charging slowly (e.g. a weak cable or charger) or
+    // stopped charging because of its temperature
+    ChargingAlert {
+        alert: ChargingAlert,
+        battery: BatteryInfo,
+    },
+    // the configuration was changed by another host or application
+    ConfigChanged {
+        cookie: u16,
+    },
+    // Backlight2 change made on the device itself (keys, ambient light sensor)
+    Backlight(BacklightConfig),
+    // diverted buttons currently held down, empty once all are released
+    Buttons(Vec<Cid>),
+    // pointer movement while a raw XY diverted button is held
+    RawXY {
+        dx: i16,
+        dy: i16,
+    },
+    // rotation and touch of a Craft keyboard crown, only sent while diverted
+    Crown(CrownEvent),
+    // HID++ 1.0 receiver notification: the wireless link to the device was
+    // established or lost
+    Link {
+        established: bool,
+        encrypted: bool,
+    },
+    // link quality reported by the receiver, 0-100
+    LinkQuality(u8),
+    // HID++ 1.0: the device was unpaired from the receiver
+    Unpaired,
+    // HID++ 1.0: the receiver's pairing lock opened or closed, with the
+    // error code when pairing failed
+    PairingLock {
+        open: bool,
+        error: Option<u8>,
+    },
+    // HID++ 1.0: the device was switched on or woke up
+    PoweredOn,
+    // notifications were dropped because the event queue was full
+    Overflow {
+        dropped: u64,
+    },
+    // any notification we don't know how to decode yet
+    Unknown(Message),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ChargingAlert {
+    SlowCharge,
+    Thermal,
+}
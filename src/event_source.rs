@@ -0,0 +1,63 @@
+use std::{io, os::unix::io::AsRawFd};
+
+use mio::{event::Source, unix::SourceFd, Interest, Registry, Token};
+
+use crate::{Device, Event};
+
+// Wraps a device so it can be registered with a mio `Poll`. When the token
+// fires, call `drain` to collect the decoded events.
+//
+//   let mut source = EventSource::new(device);
+//   poll.registry().register(&mut source, TOKEN, Interest::READABLE)?;
+pub struct EventSource {
+    device: Device,
+}
+
+impl EventSource {
+    pub fn new(device: Device) -> Self {
+        Self { device }
+    }
+
+    pub fn device(&mut self) -> &mut Device {
+        &mut self.device
+    }
+
+    pub fn into_inner(self) -> Device {
+        self.device
+    }
+
+    // Every event available without blocking. Must be called until empty
+    // after each readiness notification, edge triggered pollers won't fire
+    // again for reports already waiting.
+    pub fn drain(&mut self) -> anyhow::Result<Vec<Event>> {
+        let mut events = vec![];
+        while let Some(event) = self.device.try_recv_event()? {
+            events.push(event);
+        }
+        Ok(events)
+    }
+}
+
+impl Source for EventSource {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.device.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.device.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.device.as_raw_fd()).deregister(registry)
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{CancellationToken, Device, Event};
+
+// how often the reader thread checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Notifications read by a background thread, for applications that react to
+// events instead of polling. The device moves to the thread, `stop` hands it
+// back.
+//
+//   for event in device.events() {
+//       println!("{:?}", event?);
+//   }
+//
+// The iterator ends after the first read error, e.g. when the device is
+// unplugged. Notifications that don't decode come through as
+// `Event::Unknown`.
+pub struct EventStream {
+    events: Receiver<anyhow::Result<Event>>,
+    token: CancellationToken,
+    reader: Option<JoinHandle<Device>>,
+}
+
+impl Device {
+    pub fn events(self) -> EventStream {
+        let (sender, events) = mpsc::channel();
+        let token = CancellationToken::new();
+        let reader = thread::spawn({
+            let token = token.clone();
+            let mut device = self;
+            move || {
+                while !token.is_cancelled() {
+                    let event = match device.next_event(POLL_INTERVAL) {
+                        Ok(Some(event)) => Ok(event),
+                        Ok(None) => continue,
+                        Err(err) => Err(err),
+                    };
+                    let failed = event.is_err();
+                    if sender.send(event).is_err() || failed {
+                        break;
+                    }
+                }
+                device
+            }
+        });
+        EventStream {
+            events,
+            token,
+            reader: Some(reader),
+        }
+    }
+}
+
+impl EventStream {
+    // The next event, None if none came within `timeout` or the reader
+    // stopped
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<anyhow::Result<Event>> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    // An event already read, without blocking
+    pub fn try_recv(&self) -> Option<anyhow::Result<Event>> {
+        self.events.try_recv().ok()
+    }
+
+    // Stops the reader thread and returns the device. Events read but not
+    // received yet are dropped.
+    pub fn stop(mut self) -> anyhow::Result<Device> {
+        self.token.cancel();
+        let reader = self.reader.take().expect("reader already stopped");
+        reader
+            .join()
+            .map_err(|_| anyhow::anyhow!("Event reader panicked"))
+    }
+}
+
+impl Iterator for EventStream {
+    type Item = anyhow::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.recv().ok()
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.token.cancel();
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+// How often each fault hits a frame, as probabilities between 0 and 1
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultConfig {
+    pub drop: f64,
+    pub duplicate: f64,
+    // held back and delivered after the next frame
+    pub reorder: f64,
+    // a header byte flipped, so the frame no longer matches its request.
+    // HID has no checksum, payload corruption can't be detected at all.
+    pub corrupt: f64,
+    // each frame waits a random time up to this before delivery
+    pub max_delay: Duration,
+    // the same seed gives the same faults for the same frames
+    pub seed: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FaultStats {
+    pub frames: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+    pub corrupted: u64,
+}
+
+// Mangles the frames a simulated device sends, for testing that requests
+// survive an unreliable link:
+//
+//   virtual_device.set_faults(Some(FaultInjector::new(FaultConfig {
+//       drop: 0.1,
+//       ..FaultConfig::default()
+//   })));
+#[derive(Clone, Debug)]
+pub struct FaultInjector {
+    config: FaultConfig,
+    state: u64,
+    held: Option<Vec<u8>>,
+    stats: FaultStats,
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultConfig) -> Self {
+        Self {
+            // xorshift can't start from zero
+            state: config.seed | 1,
+            config,
+            held: None,
+            stats: FaultStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> FaultStats {
+        self.stats
+    }
+
+    // The frames to deliver in place of `frame`, in order, and how long to
+    // wait before delivering them
+    pub fn apply(&mut self, mut frame: Vec<u8>) -> (Duration, Vec<Vec<u8>>) {
+        self.stats.frames += 1;
+        let delay = self.config.max_delay.mul_f64(self.random());
+
+        if self.chance(self.config.drop) {
+            self.stats.dropped += 1;
+            return (delay, vec![]);
+        }
+        if frame.len() > 3 && self.chance(self.config.corrupt) {
+            self.stats.corrupted += 1;
+            // device index, feature index or function byte
+            let byte = 1 + self.next() as usize % 3;
+            frame[byte] ^= 1 << (self.next() % 8);
+        }
+        if self.held.is_none() && self.chance(self.config.reorder) {
+            self.stats.reordered += 1;
+            self.held = Some(frame);
+            return (delay, vec![]);
+        }
+
+        let mut frames = vec![frame.clone()];
+        if self.chance(self.config.duplicate) {
+            self.stats.duplicated += 1;
+            frames.push(frame);
+        }
+        frames.extend(self.held.take());
+        (delay, frames)
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.random() < probability
+    }
+
+    // uniform in [0, 1)
+    fn random(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // xorshift64*
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
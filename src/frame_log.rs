@@ -0,0 +1,114 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+pub use crate::strings::to_hex;
+use crate::{Message, TransactionId};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    // host to device
+    Out,
+    // device to host
+    In,
+}
+
+// One line of a frame log
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FrameRecord {
+    pub direction: Direction,
+    // microseconds since the unix epoch
+    pub timestamp_us: u64,
+    // the frame as sent or received, as space separated hex bytes
+    pub raw: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded: Option<DecodedFrame>,
+    // the request this frame was sent or read for, None for notifications
+    // read outside of one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionId>,
+    // name of the feature at the frame's feature index, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feature: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DecodedFrame {
+    pub report_id: u8,
+    pub device_index: u8,
+    pub feature_index: u8,
+    pub function_index: u8,
+    pub software_id: u8,
+    pub data: String,
+}
+
+impl FrameRecord {
+    pub fn new(direction: Direction, buf: &[u8]) -> Self {
+        let timestamp_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or_default();
+        let decoded = Message::try_from(buf.to_vec())
+            .ok()
+            .map(|message| DecodedFrame {
+                report_id: buf[0],
+                device_index: message.device_index,
+                feature_index: message.feature_index,
+                function_index: message.function_index,
+                software_id: message.software_id,
+                data: to_hex(&message.data),
+            });
+
+        Self {
+            direction,
+            timestamp_us,
+            raw: to_hex(buf),
+            decoded,
+            transaction: None,
+            feature: None,
+        }
+    }
+
+    pub fn bytes(&self) -> anyhow::Result<Vec<u8>> {
+        from_hex(&self.raw)
+    }
+}
+
+// Writes every frame going through a device as a JSON object per line
+pub struct FrameLogger {
+    writer: BufWriter<File>,
+}
+
+impl FrameLogger {
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn log(&mut self, direction: Direction, buf: &[u8]) -> anyhow::Result<()> {
+        self.write(&FrameRecord::new(direction, buf))
+    }
+
+    pub fn write(&mut self, record: &FrameRecord) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        // flushed per frame so the log survives a crash
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+pub fn from_hex(s: &str) -> anyhow::Result<Vec<u8>> {
+    s.split_whitespace()
+        .map(|b| {
+            u8::from_str_radix(b, 16).map_err(|e| anyhow::anyhow!("Invalid byte {:?}: {}", b, e))
+        })
+        .collect()
+}
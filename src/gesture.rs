@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use crate::{Cid, Device, Event};
+
+type GestureCallback = Box<dyn FnMut() + Send>;
+
+// Screen directions, up is towards the top of the screen (negative y)
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum GestureDirection {
+    // the button was pressed and released without moving
+    None,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl GestureDirection {
+    fn from_movement(dx: i32, dy: i32, threshold: u32) -> Self {
+        let threshold = threshold as i32;
+        if dx.abs() < threshold && dy.abs() < threshold {
+            GestureDirection::None
+        } else if dx.abs() > dy.abs() {
+            if dx < 0 {
+                GestureDirection::Left
+            } else {
+                GestureDirection::Right
+            }
+        } else if dy < 0 {
+            GestureDirection::Up
+        } else {
+            GestureDirection::Down
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GestureMode {
+    // fires when the button is released
+    OnRelease,
+    // fires as soon as the movement crosses the threshold, once per press
+    OnThreshold,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Gesture {
+    pub direction: GestureDirection,
+    // movement, in raw device counts, needed to tell a gesture from a click
+    pub threshold: u32,
+    pub mode: GestureMode,
+}
+
+impl Gesture {
+    // same defaults as logiops
+    pub fn new(direction: GestureDirection) -> Self {
+        Self {
+            direction,
+            threshold: 50,
+            mode: GestureMode::OnRelease,
+        }
+    }
+
+    pub fn threshold(mut self, threshold: u32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn mode(mut self, mode: GestureMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+struct Binding {
+    gesture: Gesture,
+    callback: GestureCallback,
+}
+
+#[derive(Default)]
+struct ButtonState {
+    bindings: Vec<Binding>,
+    held: bool,
+    dx: i32,
+    dy: i32,
+    // a gesture already fired during this press
+    performed: bool,
+}
+
+// Turns "hold a button and move the mouse" into gestures, the way logiops
+// gesture buttons work. Feed it the device's events with `handle`.
+#[derive(Default)]
+pub struct GestureEngine {
+    buttons: HashMap<Cid, ButtonState>,
+}
+
+impl GestureEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on(
+        &mut self,
+        cid: Cid,
+        gesture: Gesture,
+        callback: impl FnMut() + Send + 'static,
+    ) -> &mut Self {
+        self.buttons.entry(cid).or_default().bindings.push(Binding {
+            gesture,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    // Diverts every configured button with raw XY reporting
+    pub fn attach(&self, device: &mut Device) -> anyhow::Result<()> {
+        for cid in self.buttons.keys() {
+            device.set_divert_raw_xy(*cid, true)?;
+        }
+        Ok(())
+    }
+
+    pub fn detach(&self, device: &mut Device) -> anyhow::Result<()> {
+        for cid in self.buttons.keys() {
+            device.set_divert_raw_xy(*cid, false)?;
+        }
+        Ok(())
+    }
+
+    pub fn handle(&mut self, event: &Event) {
+        match event {
+            Event::Buttons(pressed) => {
+                for (cid, state) in &mut self.buttons {
+                    let held = pressed.contains(cid);
+                    if held && !state.held {
+                        state.dx = 0;
+                        state.dy = 0;
+                        state.performed = false;
+                    } else if !held && state.held && !state.performed {
+                        state.release();
+                    }
+                    state.held = held;
+                }
+            }
+            Event::RawXY { dx, dy } => {
+                for state in self.buttons.values_mut().filter(|state| state.held) {
+                    state.dx += *dx as i32;
+                    state.dy += *dy as i32;
+                    if !state.performed {
+                        state.check_threshold();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ButtonState {
+    fn check_threshold(&mut self) {
+        for binding in &mut self.bindings {
+            let gesture = binding.gesture;
+            if gesture.mode != GestureMode::OnThreshold
+                || gesture.direction == GestureDirection::None
+            {
+                continue;
+            }
+            if GestureDirection::from_movement(self.dx, self.dy, gesture.threshold)
+                == gesture.direction
+            {
+                crate::tracing::debug!("Gesture {:?}", gesture.direction);
+                (binding.callback)();
+                self.performed = true;
+                return;
+            }
+        }
+    }
+
+    fn release(&mut self) {
+        for binding in &mut self.bindings {
+            let gesture = binding.gesture;
+            if gesture.mode != GestureMode::OnRelease {
+                continue;
+            }
+            if GestureDirection::from_movement(self.dx, self.dy, gesture.threshold)
+                == gesture.direction
+            {
+                crate::tracing::debug!("Gesture {:?}", gesture.direction);
+                (binding.callback)();
+                return;
+            }
+        }
+    }
+}
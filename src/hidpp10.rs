@@ -0,0 +1,221 @@
+use crate::{
+    consts::{GET_LONG_REGISTER, GET_REGISTER, SET_LONG_REGISTER, SET_REGISTER},
+    BatteryInfo, BatteryLevel, BatteryStatus, Device, Event, Message, MessageBuilder, ReportId,
+};
+
+// HID++ 1.0 notification sub ids. Receivers send them about the device in a
+// slot, very old devices about themselves. The byte after the sub id is an
+// address (a parameter) rather than a function and software id.
+pub(crate) const BATTERY_STATUS: u8 = 0x07;
+pub(crate) const BATTERY_CHARGE: u8 = 0x0D;
+pub(crate) const DEVICE_DISCONNECTION: u8 = 0x40;
+pub(crate) const DEVICE_CONNECTION: u8 = 0x41;
+pub(crate) const LINK_QUALITY: u8 = 0x49;
+pub(crate) const PAIRING_LOCK: u8 = 0x4A;
+pub(crate) const POWER: u8 = 0x4B;
+
+// Notifications a receiver sends about the link to the device in a slot,
+// whichever protocol that device speaks
+pub(crate) fn is_receiver_notification(sub_id: u8) -> bool {
+    matches!(
+        sub_id,
+        DEVICE_DISCONNECTION | DEVICE_CONNECTION | LINK_QUALITY | PAIRING_LOCK | POWER
+    )
+}
+
+// 0x41 flags
+const LINK_NOT_ESTABLISHED: u8 = 0x40;
+const LINK_ENCRYPTED: u8 = 0x20;
+// 0x41 address for a Unifying device, other protocols work the same
+const PROTOCOL_UNIFYING: u8 = 0x04;
+
+// HID++ 1.0 register access, which receivers and very old devices speak
+// instead of features. `device_index` is 0xFF for the receiver itself and
+// the slot for the devices paired to it.
+impl Device {
+    // Reads a short register, returning its 3 value bytes
+    pub fn read_register(
+        &mut self,
+        device_index: u8,
+        register: u8,
+        params: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let request = MessageBuilder::new_register(GET_REGISTER, register)
+            .device_index(device_index)
+            .add_bytes(params)
+            .try_build()?;
+        Ok(request.send(self)?.data().to_vec())
+    }
+
+    pub fn write_register(
+        &mut self,
+        device_index: u8,
+        register: u8,
+        value: &[u8],
+    ) -> anyhow::Result<()> {
+        let request = MessageBuilder::new_register(SET_REGISTER, register)
+            .device_index(device_index)
+            .add_bytes(value)
+            .try_build()?;
+        request.send(self)?;
+        Ok(())
+    }
+
+    // Reads a long register, returning its 16 value bytes
+    pub fn read_long_register(
+        &mut self,
+        device_index: u8,
+        register: u8,
+        params: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let request = MessageBuilder::new_register(GET_LONG_REGISTER, register)
+            .device_index(device_index)
+            .add_bytes(params)
+            .try_build()?;
+        Ok(request.send(self)?.data().to_vec())
+    }
+
+    // Long registers are written with a long report
+    pub fn write_long_register(
+        &mut self,
+        device_index: u8,
+        register: u8,
+        value: &[u8],
+    ) -> anyhow::Result<()> {
+        let request = MessageBuilder::new_register(SET_LONG_REGISTER, register)
+            .report_id(ReportId::Long)
+            .device_index(device_index)
+            .add_bytes(value)
+            .try_build()?;
+        request.send(self)?;
+        Ok(())
+    }
+}
+
+// Decodes a HID++ 1.0 notification, None if it isn't one we know. Battery
+// reports use the sub id of the register they mirror: 0x07 for the coarse
+// level, 0x0D for the percentage.
+pub fn decode_notification(message: &Message) -> Option<Event> {
+    let data = &message.data;
+    let address = message.function_index << 4 | message.software_id;
+    match message.feature_index {
+        BATTERY_STATUS => {
+            let level = match data[0] {
+                7 => BatteryLevel::Full,
+                5 => BatteryLevel::Good,
+                3 => BatteryLevel::Low,
+                1 => BatteryLevel::Critical,
+                _ => return None,
+            };
+            let status = match data[1] {
+                0x00 => BatteryStatus::Discharging,
+                charging if charging & 0x21 == 0x21 => BatteryStatus::Recharging,
+                charging if charging & 0x22 == 0x22 => BatteryStatus::Full,
+                _ => BatteryStatus::InvalidBattery,
+            };
+            Some(Event::Battery(BatteryInfo {
+                percentage: level.approximate_percentage(),
+                estimated: true,
+                level,
+                status,
+                voltage: None,
+            }))
+        }
+        BATTERY_CHARGE => {
+            let status = match data[2] & 0xF0 {
+                0x30 => BatteryStatus::Discharging,
+                0x50 => BatteryStatus::Recharging,
+                0x90 => BatteryStatus::Full,
+                _ => BatteryStatus::InvalidBattery,
+            };
+            let percentage = data[0].min(100);
+            Some(Event::Battery(BatteryInfo {
+                percentage,
+                estimated: false,
+                level: BatteryLevel::from_percentage(percentage),
+                status,
+                voltage: None,
+            }))
+        }
+        // 0x02 is the only address defined, the device was unpaired
+        DEVICE_DISCONNECTION if address == 0x02 => Some(Event::Unpaired),
+        DEVICE_CONNECTION => Some(Event::Link {
+            established: data[0] & LINK_NOT_ESTABLISHED == 0,
+            encrypted: data[0] & LINK_ENCRYPTED != 0,
+        }),
+        LINK_QUALITY => Some(Event::LinkQuality(data[0])),
+        PAIRING_LOCK => Some(Event::PairingLock {
+            open: address & 0x01 != 0,
+            error: (data[0] != 0).then_some(data[0]),
+        }),
+        POWER if address == 0x01 => Some(Event::PoweredOn),
+        _ => None,
+    }
+}
+
+// Builds the notification a receiver would send for `event`, so simulators
+// and tests can produce them. None for events HID++ 1.0 has no notification
+// for, or battery statuses it can't express.
+pub fn encode_notification(device_index: u8, event: &Event) -> Option<Message> {
+    let (sub_id, address, data) = match event {
+        Event::Battery(battery) if battery.estimated => {
+            let level = match battery.level {
+                BatteryLevel::Full => 7,
+                BatteryLevel::Good => 5,
+                BatteryLevel::Low => 3,
+                BatteryLevel::Critical => 1,
+                BatteryLevel::Empty => return None,
+            };
+            let charging = match battery.status {
+                BatteryStatus::Discharging => 0x00,
+                BatteryStatus::Recharging | BatteryStatus::WirelessCharging => 0x21,
+                BatteryStatus::Full => 0x22,
+                _ => return None,
+            };
+            (BATTERY_STATUS, 0x00, vec![level, charging])
+        }
+        Event::Battery(battery) => {
+            let status = match battery.status {
+                BatteryStatus::Discharging => 0x30,
+                BatteryStatus::Recharging | BatteryStatus::WirelessCharging => 0x50,
+                BatteryStatus::Full => 0x90,
+                _ => return None,
+            };
+            (BATTERY_CHARGE, 0x00, vec![battery.percentage, 0x00, status])
+        }
+        Event::Unpaired => (DEVICE_DISCONNECTION, 0x02, vec![]),
+        Event::Link {
+            established,
+            encrypted,
+        } => {
+            let mut flags = 0x00;
+            if !established {
+                flags |= LINK_NOT_ESTABLISHED;
+            }
+            if *encrypted {
+                flags |= LINK_ENCRYPTED;
+            }
+            (DEVICE_CONNECTION, PROTOCOL_UNIFYING, vec![flags])
+        }
+        Event::LinkQuality(quality) => (LINK_QUALITY, 0x00, vec![*quality]),
+        Event::PairingLock { open, error } => (
+            PAIRING_LOCK,
+            u8::from(*open),
+            vec![error.unwrap_or_default()],
+        ),
+        Event::PoweredOn => (POWER, 0x01, vec![]),
+        _ => return None,
+    };
+    Some(Message {
+        report_id: ReportId::Short,
+        device_index,
+        feature_index: sub_id,
+        function_index: address >> 4,
+        software_id: address & 0x0F,
+        data: data
+            .into_iter()
+            .chain(std::iter::repeat(0))
+            .take(ReportId::Short.payload_len())
+            .collect(),
+    })
+}
@@ -0,0 +1,73 @@
+use std::{
+    ffi::CStr,
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, RawFd},
+    },
+    time::Duration,
+};
+
+use anyhow::bail;
+
+// Direct access to a /dev/hidrawN node. hidapi keeps its file descriptor to
+// itself, so with the `hidraw` feature reads and writes go through this
+// handle instead, letting callers poll the descriptor from their own event
+// loop.
+pub(crate) struct Hidraw {
+    file: File,
+}
+
+impl Hidraw {
+    pub(crate) fn open(path: &CStr) -> anyhow::Result<Self> {
+        let path = std::ffi::OsStr::from_bytes(path.to_bytes());
+        if !path.as_bytes().starts_with(b"/dev/hidraw") {
+            bail!(
+                "{} is not a hidraw node, hidapi must use its hidraw backend",
+                path.to_string_lossy()
+            );
+        }
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    pub(crate) fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    // Waits up to `timeout` for a report, returns 0 if none arrived
+    pub(crate) fn read_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> anyhow::Result<usize> {
+        let mut pollfd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: pollfd is a single valid entry that outlives the call
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+        if ready < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                return Ok(0);
+            }
+            return Err(err.into());
+        }
+        if ready == 0 {
+            return Ok(0);
+        }
+        if pollfd.revents & (libc::POLLERR | libc::POLLHUP) != 0 {
+            bail!("Device disconnected");
+        }
+        Ok(self.file.read(buf)?)
+    }
+}
+
+impl AsRawFd for Hidraw {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
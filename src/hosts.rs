@@ -0,0 +1,119 @@
+use anyhow::bail;
+
+use crate::{strings::StringAssembler, Device, Feature, Function};
+
+// An Easy-Switch channel, `index` is 0 based while devices label them from 1
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct HostInfo {
+    pub index: u8,
+    // None when the device can't tell, without HostsInfo
+    pub paired: Option<bool>,
+    pub current: bool,
+    // name the host's Bluetooth stack or Logitech software gave it, None if
+    // the device doesn't keep names
+    pub name: Option<String>,
+}
+
+impl Device {
+    // Number of channels and the 0 based index of the current one
+    pub fn current_host(&mut self) -> anyhow::Result<(u8, u8)> {
+        let result = self.send_feature(Feature::ChangeHost, Function::ChangeHostGetInfo, &[])?;
+        Ok((result.data[0], result.data[1]))
+    }
+
+    // Every channel, with pairing status and names where HostsInfo (0x1815)
+    // is supported
+    pub fn hosts(&mut self) -> anyhow::Result<Vec<HostInfo>> {
+        if self.feature_index(Feature::HostsInfo).is_err() {
+            let (count, current) = self.current_host()?;
+            return Ok((0..count)
+                .map(|index| HostInfo {
+                    index,
+                    paired: (index == current).then_some(true),
+                    current: index == current,
+                    name: None,
+                })
+                .collect());
+        }
+
+        let result =
+            self.send_feature(Feature::HostsInfo, Function::HostsInfoGetFeatureInfo, &[])?;
+        let has_names = result.data[0] & 0x01 != 0;
+        let (count, current) = (result.data[2], result.data[3]);
+
+        let mut hosts = vec![];
+        for index in 0..count {
+            let result =
+                self.send_feature(Feature::HostsInfo, Function::HostsInfoGetHostInfo, &[index])?;
+            let paired = result.data[1] != 0;
+            let name_len = result.data[4] as usize;
+            let name = if has_names && paired {
+                Some(self.host_name(index, name_len)?)
+            } else {
+                None
+            };
+            hosts.push(HostInfo {
+                index,
+                paired: Some(paired),
+                current: index == current,
+                name,
+            });
+        }
+        Ok(hosts)
+    }
+
+    fn host_name(&mut self, index: u8, len: usize) -> anyhow::Result<String> {
+        let mut name = StringAssembler::new(len);
+        while !name.is_complete() {
+            let result = self.send_feature(
+                Feature::HostsInfo,
+                Function::HostsInfoGetHostFriendlyName,
+                &[index, name.offset() as u8],
+            )?;
+            // replies echo the host and byte index before the characters
+            if !name.push(&result.data, 2) {
+                break;
+            }
+        }
+        Ok(name.finish())
+    }
+
+    // Renames a host as shown by the device, written 14 bytes at a time
+    pub fn set_host_name(&mut self, index: u8, name: &str) -> anyhow::Result<()> {
+        let bytes = name.as_bytes();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let chunk = &bytes[offset..bytes.len().min(offset + 14)];
+            let mut payload = vec![index, offset as u8];
+            payload.extend_from_slice(chunk);
+            let result = self.send_feature(
+                Feature::HostsInfo,
+                Function::HostsInfoSetHostFriendlyName,
+                &payload,
+            )?;
+            // the reply says how long the name is now
+            if result.data[1] as usize <= offset {
+                bail!("The device didn't take the name {:?}", name);
+            }
+            offset = result.data[1] as usize;
+        }
+        Ok(())
+    }
+
+    // Moves the device to another channel. It disconnects from this host
+    // right away, so no reply is waited for.
+    pub fn switch_host(&mut self, index: u8) -> anyhow::Result<()> {
+        let (count, current) = self.current_host()?;
+        if index >= count {
+            bail!("Host {} out of range, the device has {}", index + 1, count);
+        }
+        if index == current {
+            return Ok(());
+        }
+        self.submit(
+            Feature::ChangeHost,
+            Function::ChangeHostSetCurrent,
+            &[index],
+        )
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+use anyhow::bail;
+
+use crate::{consts::RECEIVER_INDEX, Device, Feature, Function};
+
+// HID++ 1.0 long register on the receiver with per slot pairing info
+const RECEIVER_INFO: u8 = 0xB5;
+const PAIRING_INFO: u8 = 0x20;
+
+// (USB product id, wireless product id) of Lightspeed mice, which show up
+// with their own product id when charging over the cable instead of behind
+// the receiver
+const CABLE_PRODUCT_IDS: [(u16, u16); 7] = [
+    // G703
+    (0xC087, 0x4070),
+    // G703 Hero
+    (0xC090, 0x4086),
+    // G903
+    (0xC086, 0x4067),
+    // G903 Hero
+    (0xC091, 0x4087),
+    // G Pro Wireless
+    (0xC088, 0x4079),
+    // G502 Lightspeed
+    (0xC08D, 0x407F),
+    // G Pro X Superlight
+    (0xC094, 0x4093),
+];
+
+// Everything that identifies a physical unit, as opposed to a model
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DeviceIdentity {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    // 4 byte unit id from FirmwareInfo, shared by all devices that support it
+    pub unit_id: Option<[u8; 4]>,
+    // 32 byte id from CryptoId (0x0021), used by newer devices to match
+    // profiles stored in the cloud
+    pub extended_id: Option<[u8; 32]>,
+}
+
+impl fmt::Display for DeviceIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vendor_id, self.product_id)?;
+        if let Some(unit_id) = self.unit_id {
+            write!(f, " unit ")?;
+            for byte in unit_id {
+                write!(f, "{:02X}", byte)?;
+            }
+        }
+        if let Some(extended_id) = self.extended_id {
+            write!(f, " id ")?;
+            for byte in extended_id {
+                write!(f, "{:02X}", byte)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Device {
+    // The id doesn't fit in a long report, it's read in two 16 byte halves
+    pub fn extended_id(&mut self) -> anyhow::Result<[u8; 32]> {
+        let mut id = [0u8; 32];
+        for (part, chunk) in id.chunks_exact_mut(16).enumerate() {
+            let result =
+                self.send_feature(Feature::CryptoId, Function::CryptoIdGetId, &[part as u8])?;
+            if result.data.len() < 16 {
+                anyhow::bail!("Short extended id reply: {} bytes", result.data.len());
+            }
+            chunk.copy_from_slice(&result.data[..16]);
+        }
+        Ok(id)
+    }
+
+    // Canonical id of the physical unit, for matching saved settings: the
+    // unit id where the device has one, else the USB serial number, else the
+    // wireless product id and receiver slot, which hold as long as the device
+    // stays paired. The serial of a receiver is shared by every device paired
+    // to it, so it's only used for devices that aren't behind one. Only the
+    // unit id stays the same when a Lightspeed mouse switches between the
+    // receiver and its cable.
+    pub fn stable_id(&mut self) -> anyhow::Result<String> {
+        if let Ok(unit_id) = self.unit_id() {
+            if unit_id != [0; 4] && unit_id != [0xFF; 4] {
+                let hex = unit_id
+                    .iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<String>();
+                return Ok(format!("unit:{}", hex));
+            }
+        }
+
+        let wireless_pid = self.wireless_pid();
+        if wireless_pid.is_err() {
+            let serial = self.hid_info()?.serial_number.unwrap_or_default();
+            if !serial.trim().is_empty() {
+                return Ok(format!("serial:{}", serial.trim()));
+            }
+        }
+
+        match wireless_pid {
+            Ok(wireless_pid) => Ok(format!("wpid:{:04x}@{}", wireless_pid, self.device_index())),
+            Err(err) => bail!("No stable id for the device: {}", err),
+        }
+    }
+
+    // Whether this is a Lightspeed mouse plugged in with its cable, which
+    // talks HID++ on its own USB product id until it's unplugged
+    pub fn is_cable_mode(&self) -> bool {
+        CABLE_PRODUCT_IDS
+            .iter()
+            .any(|(usb, _)| *usb == self.product_id())
+    }
+
+    // Product id of the device on the wireless link: asked from the receiver
+    // it's paired to, or looked up for a mouse in cable mode. None for
+    // devices that are neither.
+    pub fn wireless_product_id(&mut self) -> Option<u16> {
+        if let Some((_, wireless)) = CABLE_PRODUCT_IDS
+            .iter()
+            .find(|(usb, _)| *usb == self.product_id())
+        {
+            return Some(*wireless);
+        }
+        self.wireless_pid().ok()
+    }
+
+    // Product id the device has when plugged in with its cable, for
+    // Lightspeed mice that have a cable mode
+    pub fn cable_product_id(&mut self) -> Option<u16> {
+        if self.is_cable_mode() {
+            return Some(self.product_id());
+        }
+        let wireless = self.wireless_product_id()?;
+        CABLE_PRODUCT_IDS
+            .iter()
+            .find(|(_, w)| *w == wireless)
+            .map(|(usb, _)| *usb)
+    }
+
+    // Product id of the device on the wireless link, asked from the receiver
+    // it's paired to. Fails for devices that aren't behind a receiver.
+    fn wireless_pid(&mut self) -> anyhow::Result<u16> {
+        let slot = self.device_index();
+        if !(1..=6).contains(&slot) {
+            bail!("Device index {} is not a receiver slot", slot);
+        }
+        let info =
+            self.read_long_register(RECEIVER_INDEX, RECEIVER_INFO, &[PAIRING_INFO + slot - 1])?;
+        Ok(u16::from_be_bytes([info[3], info[4]]))
+    }
+
+    // Collects the ids the device supports, leaving the others out
+    pub fn identity(&mut self) -> DeviceIdentity {
+        let unit_id = self.unit_id().ok();
+        let extended_id = match self.feature_index(Feature::CryptoId) {
+            Ok(_) => self.extended_id().ok(),
+            Err(_) => None,
+        };
+        DeviceIdentity {
+            vendor_id: self.vendor_id(),
+            product_id: self.product_id(),
+            unit_id,
+            extended_id,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::ops::BitOr;
+
+use anyhow::bail;
+
+use crate::{Device, Feature, Function};
+
+// Keys DisableKeys (0x4521) can turn off, e.g. so Caps Lock can't be hit by
+// accident
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct DisabledKeys(pub u8);
+
+impl DisabledKeys {
+    pub const CAPS_LOCK: Self = Self(0x01);
+    pub const NUM_LOCK: Self = Self(0x02);
+    pub const SCROLL_LOCK: Self = Self(0x04);
+    pub const INSERT: Self = Self(0x08);
+    // the Windows/Start key
+    pub const WINDOWS: Self = Self(0x10);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for DisabledKeys {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl Device {
+    // Keys the device is able to disable
+    pub fn disableable_keys(&mut self) -> anyhow::Result<DisabledKeys> {
+        let result = self.send_feature(
+            Feature::DisableKeys,
+            Function::DisableKeysGetCapabilities,
+            &[],
+        )?;
+        Ok(DisabledKeys(result.data[0]))
+    }
+
+    pub fn get_disabled_keys(&mut self) -> anyhow::Result<DisabledKeys> {
+        let result =
+            self.send_feature(Feature::DisableKeys, Function::DisableKeysGetDisabled, &[])?;
+        Ok(DisabledKeys(result.data[0]))
+    }
+
+    // Disables exactly `keys`, enabling the others
+    pub fn set_disabled_keys(&mut self, keys: DisabledKeys) -> anyhow::Result<()> {
+        let supported = self.disableable_keys()?;
+        if !supported.contains(keys) {
+            bail!(
+                "Keys 0x{:02X} can't be disabled, the device supports 0x{:02X}",
+                keys.0,
+                supported.0
+            );
+        }
+        self.send_feature(
+            Feature::DisableKeys,
+            Function::DisableKeysSetDisabled,
+            &[keys.0],
+        )?;
+        Ok(())
+    }
+}
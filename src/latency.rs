@@ -0,0 +1,40 @@
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+
+use crate::Device;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LatencyStats {
+    pub samples: usize,
+    pub min: Duration,
+    pub avg: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl Device {
+    // Pings the device `count` times and summarizes the round-trip times
+    pub fn measure_latency(&mut self, count: usize) -> anyhow::Result<LatencyStats> {
+        if count == 0 {
+            bail!("At least one ping is needed to measure latency");
+        }
+
+        let mut rtts = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = Instant::now();
+            self.ping(i as u8)?;
+            rtts.push(start.elapsed());
+        }
+        rtts.sort();
+
+        let p95 = ((count * 95).div_ceil(100)).max(1) - 1;
+        Ok(LatencyStats {
+            samples: count,
+            min: rtts[0],
+            avg: rtts.iter().sum::<Duration>() / count as u32,
+            p95: rtts[p95],
+            max: rtts[count - 1],
+        })
+    }
+}
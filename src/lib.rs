@@ -1,11 +1,137 @@
+use std::fmt;
+
 use anyhow::bail;
+#[cfg(feature = "enum-iterator")]
 use enum_iterator::Sequence;
+use strings::to_hex;
+
+// tracing's macros, or stand-ins that log nothing when it's left out
+#[cfg(feature = "tracing")]
+use ::tracing;
+#[cfg(not(feature = "tracing"))]
+mod tracing;
 
+#[cfg(feature = "async")]
+mod async_device;
+mod backlight;
+mod backoff;
+mod builder;
+mod cache;
+mod cancel;
+mod clock;
+#[cfg(feature = "json")]
+pub mod config;
+pub mod consts;
+mod controls;
+mod crown;
 mod device;
+pub mod diagnostics;
+mod discovery;
+mod dpi;
+mod errors;
+mod event;
+#[cfg(all(target_os = "linux", feature = "mio"))]
+mod event_source;
+mod event_stream;
+pub mod faults;
+#[cfg(feature = "json")]
+pub mod frame_log;
+mod gesture;
+mod hidpp10;
+#[cfg(all(target_os = "linux", feature = "hidraw"))]
+mod hidraw;
+mod hosts;
+mod identity;
+mod keys;
+mod latency;
+mod lighting;
+mod link;
+mod manager;
+pub mod mock;
+mod monitor;
+mod name;
+#[cfg(feature = "notifications")]
+pub mod notifications;
+mod platform;
+#[cfg(feature = "json")]
+mod profile_json;
+mod profiles;
+mod queue;
+mod quirks;
+mod rate_limit;
+mod receiver;
+#[cfg(feature = "json")]
+pub mod replay;
+mod report_rate;
+mod settings;
+pub mod sim;
+#[cfg(feature = "stress")]
+pub mod stress;
+mod strings;
+mod temperature;
+mod transaction;
+mod transport;
+#[cfg(all(target_os = "linux", feature = "uhid"))]
+pub mod uhid;
+#[cfg(all(target_os = "linux", feature = "uinput"))]
+pub mod uinput;
+mod update;
+mod wheel;
 
-pub use device::Device;
+#[cfg(feature = "async")]
+pub use async_device::AsyncDevice;
+pub use backlight::{BacklightConfig, BacklightMode};
+pub use backoff::Backoff;
+pub use builder::DeviceBuilder;
+pub use cancel::CancellationToken;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use controls::Cid;
+pub use crown::{CrownEvent, CrownMode, CrownPress};
+pub use device::{
+    BatteryCapabilities, BatteryInfo, BatteryLevel, BatteryStatus, Device, FirmwareVersion,
+    HidInfo, Stats,
+};
+pub use discovery::{enumerate, DeviceInfo};
+pub use dpi::{Dpi, DpiCapabilities};
+pub use errors::Error;
+pub use event::{ChargingAlert, Event};
+#[cfg(all(target_os = "linux", feature = "mio"))]
+pub use event_source::EventSource;
+pub use event_stream::EventStream;
+pub use gesture::{Gesture, GestureDirection, GestureEngine, GestureMode};
+pub use hidpp10::{decode_notification, encode_notification};
+pub use hosts::HostInfo;
+pub use identity::DeviceIdentity;
+pub use keys::DisabledKeys;
+pub use latency::LatencyStats;
+pub use lighting::{FrameStats, LedEffect, LedPersistence, LedStreamer, LedZone, LedZoneLocation};
+pub use link::LinkQuality;
+pub use manager::{DeviceManager, HandleId, LogicalDevice, Route, ScanDiff};
+pub use monitor::{BatteryMonitor, BatteryReading};
+pub use name::DeviceType;
+pub use platform::{HostDefaults, HostOs, PlatformDescriptor};
+#[cfg(feature = "json")]
+pub use profile_json::{
+    JsonAction, JsonAssignment, JsonColor, JsonDpiLevel, JsonFunction, JsonProfile,
+};
+pub use profiles::{
+    sector_crc, verify_sector, ButtonAction, ButtonFunction, CorruptProfile, OnboardMode,
+    ProfileConfig, ProfileEntry, ProfileField, ProfileTemplate, ProfilesInfo,
+};
+pub use queue::OverflowPolicy;
+pub use quirks::{lookup_quirks, register_quirks, QuirkKey, Quirks};
+pub use rate_limit::{Permit, RateLimiter};
+pub use receiver::{
+    ActivityCounters, DeviceKind, NotificationFlags, PairingError, PairingEvent, Receiver,
+    ReceiverFirmware,
+};
+pub use settings::{copy_settings, KeyboardSettings, MouseSettings};
+pub use transaction::TransactionId;
+pub use transport::{HidapiTransport, Transport};
+pub use wheel::{SmartShift, WheelCapabilities, WheelMode};
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash, Sequence)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "enum-iterator", derive(Sequence))]
 pub enum Feature {
     Root,
     FeatureSet,
@@ -13,31 +139,194 @@ pub enum Feature {
     FirmwareInfo,
     DeviceUnitId,
     DeviceNameType,
+    DeviceFriendlyName,
+    ConfigChange,
+    CryptoId,
     BatteryLevelStatus,
+    BatteryVoltage,
     UnifiedBattery,
+    AdcMeasurement,
+    TemperatureMeasurement,
+    ChangeHost,
+    HostsInfo,
+    Backlight2,
+    ReprogControlsV4,
+    AdjustableDpi,
+    SmartShift,
+    SmartShiftEnhanced,
+    HiResWheel,
+    FnInversion,
+    NewFnInversion,
+    K375sFnInversion,
+    Crown,
+    DisableKeys,
+    MultiPlatform,
+    ReportRate,
+    RgbEffects,
+    PerKeyLighting,
+    OnboardProfiles,
 }
 
 impl Feature {
+    // every feature the crate knows, in declaration order
+    pub const ALL: &'static [Feature] = &[
+        Feature::Root,
+        Feature::FeatureSet,
+        Feature::FeatureInfo,
+        Feature::FirmwareInfo,
+        Feature::DeviceUnitId,
+        Feature::DeviceNameType,
+        Feature::DeviceFriendlyName,
+        Feature::ConfigChange,
+        Feature::CryptoId,
+        Feature::BatteryLevelStatus,
+        Feature::BatteryVoltage,
+        Feature::UnifiedBattery,
+        Feature::AdcMeasurement,
+        Feature::TemperatureMeasurement,
+        Feature::ChangeHost,
+        Feature::HostsInfo,
+        Feature::Backlight2,
+        Feature::ReprogControlsV4,
+        Feature::AdjustableDpi,
+        Feature::SmartShift,
+        Feature::SmartShiftEnhanced,
+        Feature::HiResWheel,
+        Feature::FnInversion,
+        Feature::NewFnInversion,
+        Feature::K375sFnInversion,
+        Feature::Crown,
+        Feature::DisableKeys,
+        Feature::MultiPlatform,
+        Feature::ReportRate,
+        Feature::RgbEffects,
+        Feature::PerKeyLighting,
+        Feature::OnboardProfiles,
+    ];
+
     fn value(&self) -> u16 {
         match self {
-            Feature::Root { .. } => 0x0000,
-            Feature::FeatureSet => 0x0001,
-            Feature::FeatureInfo => 0x0002,
-            Feature::FirmwareInfo => 0x0003,
-            Feature::DeviceUnitId => 0x0004,
-            Feature::DeviceNameType => 0x0005,
-            Feature::BatteryLevelStatus => 0x1000,
-            Feature::UnifiedBattery => 0x1004,
+            Feature::Root => consts::ROOT,
+            Feature::FeatureSet => consts::FEATURE_SET,
+            Feature::FeatureInfo => consts::FEATURE_INFO,
+            Feature::FirmwareInfo => consts::FIRMWARE_INFO,
+            Feature::DeviceUnitId => consts::DEVICE_UNIT_ID,
+            Feature::DeviceNameType => consts::DEVICE_NAME_TYPE,
+            Feature::DeviceFriendlyName => consts::DEVICE_FRIENDLY_NAME,
+            Feature::ConfigChange => consts::CONFIG_CHANGE,
+            Feature::CryptoId => consts::CRYPTO_ID,
+            Feature::BatteryLevelStatus => consts::BATTERY_LEVEL_STATUS,
+            Feature::BatteryVoltage => consts::BATTERY_VOLTAGE,
+            Feature::UnifiedBattery => consts::UNIFIED_BATTERY,
+            Feature::AdcMeasurement => consts::ADC_MEASUREMENT,
+            Feature::TemperatureMeasurement => consts::TEMPERATURE_MEASUREMENT,
+            Feature::ChangeHost => consts::CHANGE_HOST,
+            Feature::HostsInfo => consts::HOSTS_INFO,
+            Feature::Backlight2 => consts::BACKLIGHT2,
+            Feature::ReprogControlsV4 => consts::REPROG_CONTROLS_V4,
+            Feature::AdjustableDpi => consts::ADJUSTABLE_DPI,
+            Feature::SmartShift => consts::SMART_SHIFT,
+            Feature::SmartShiftEnhanced => consts::SMART_SHIFT_ENHANCED,
+            Feature::HiResWheel => consts::HI_RES_WHEEL,
+            Feature::FnInversion => consts::FN_INVERSION,
+            Feature::NewFnInversion => consts::NEW_FN_INVERSION,
+            Feature::K375sFnInversion => consts::K375S_FN_INVERSION,
+            Feature::Crown => consts::CROWN,
+            Feature::DisableKeys => consts::DISABLE_KEYS,
+            Feature::MultiPlatform => consts::MULTI_PLATFORM,
+            Feature::ReportRate => consts::REPORT_RATE,
+            Feature::RgbEffects => consts::RGB_EFFECTS,
+            Feature::PerKeyLighting => consts::PER_KEY_LIGHTING,
+            Feature::OnboardProfiles => consts::ONBOARD_PROFILES,
         }
     }
+
+    fn from_value(value: u16) -> Option<Feature> {
+        Feature::ALL
+            .iter()
+            .find(|feature| feature.value() == value)
+            .cloned()
+    }
 }
 
 #[allow(unused)]
 pub enum Function {
     RootGetFeature,
     RootGetProtocolVersion,
+    FeatureSetGetCount,
+    FeatureSetGetFeatureId,
+    FirmwareInfoGetDeviceInfo,
+    FirmwareInfoGetFwInfo,
+    DeviceNameGetCount,
+    DeviceNameGetName,
+    DeviceNameGetType,
+    FriendlyNameGetLen,
+    FriendlyNameGetName,
+    FriendlyNameGetDefault,
+    ConfigChangeGetCookie,
+    ConfigChangeSetComplete,
+    CryptoIdGetId,
+    BatteryVoltageGetBatteryInfo,
+    AdcMeasurementGet,
+    TemperatureMeasurementGet,
     UnifiedBatteryGetCapabilities,
     UnifiedBatteryGetStatus,
+    AdjustableDpiGetSensorCount,
+    AdjustableDpiGetSensorDpiList,
+    AdjustableDpiGetSensorDpi,
+    AdjustableDpiSetSensorDpi,
+    SmartShiftGetRatchetControl,
+    SmartShiftSetRatchetControl,
+    SmartShiftEnhancedGetRatchetControl,
+    SmartShiftEnhancedSetRatchetControl,
+    HiResWheelGetCapability,
+    HiResWheelGetMode,
+    HiResWheelSetMode,
+    HiResWheelGetRatchetSwitch,
+    ChangeHostGetInfo,
+    ChangeHostSetCurrent,
+    HostsInfoGetFeatureInfo,
+    HostsInfoGetHostInfo,
+    HostsInfoGetHostFriendlyName,
+    HostsInfoSetHostFriendlyName,
+    Backlight2GetConfig,
+    Backlight2SetConfig,
+    Backlight2GetInfo,
+    ReprogControlsGetCount,
+    ReprogControlsGetCidInfo,
+    ReprogControlsGetCidReporting,
+    ReprogControlsSetCidReporting,
+    CrownGetInfo,
+    CrownGetMode,
+    CrownSetMode,
+    FnInversionGet,
+    FnInversionSet,
+    DisableKeysGetCapabilities,
+    DisableKeysGetDisabled,
+    DisableKeysSetDisabled,
+    MultiPlatformGetFeatureInfos,
+    MultiPlatformGetPlatformDescriptor,
+    MultiPlatformGetHostPlatform,
+    MultiPlatformSetHostPlatform,
+    ReportRateGetList,
+    ReportRateGetRate,
+    ReportRateSetRate,
+    RgbEffectsGetInfo,
+    RgbEffectsSetClusterEffect,
+    PerKeyLightingGetInfo,
+    PerKeyLightingSetZones,
+    PerKeyLightingFrameEnd,
+    OnboardProfilesGetInfo,
+    OnboardProfilesSetMode,
+    OnboardProfilesGetMode,
+    OnboardProfilesSetCurrentProfile,
+    OnboardProfilesGetCurrentProfile,
+    OnboardProfilesMemoryRead,
+    OnboardProfilesMemoryAddrWrite,
+    OnboardProfilesMemoryWrite,
+    OnboardProfilesMemoryWriteEnd,
+    OnboardProfilesGetCurrentDpiIndex,
+    OnboardProfilesSetCurrentDpiIndex,
 }
 
 impl Function {
@@ -45,8 +334,80 @@ impl Function {
         match self {
             Function::RootGetFeature => 0x00,
             Function::RootGetProtocolVersion => 0x01,
+            Function::FeatureSetGetCount => 0x00,
+            Function::FeatureSetGetFeatureId => 0x01,
+            Function::FirmwareInfoGetDeviceInfo => 0x00,
+            Function::FirmwareInfoGetFwInfo => 0x01,
+            Function::DeviceNameGetCount => 0x00,
+            Function::DeviceNameGetName => 0x01,
+            Function::DeviceNameGetType => 0x02,
+            Function::FriendlyNameGetLen => 0x00,
+            Function::FriendlyNameGetName => 0x01,
+            Function::FriendlyNameGetDefault => 0x02,
+            Function::ConfigChangeGetCookie => 0x00,
+            Function::ConfigChangeSetComplete => 0x01,
+            Function::CryptoIdGetId => 0x00,
+            Function::BatteryVoltageGetBatteryInfo => 0x00,
+            Function::AdcMeasurementGet => 0x00,
+            Function::TemperatureMeasurementGet => 0x00,
             Function::UnifiedBatteryGetCapabilities => 0x00,
             Function::UnifiedBatteryGetStatus => 0x01,
+            Function::AdjustableDpiGetSensorCount => 0x00,
+            Function::AdjustableDpiGetSensorDpiList => 0x01,
+            Function::AdjustableDpiGetSensorDpi => 0x02,
+            Function::AdjustableDpiSetSensorDpi => 0x03,
+            Function::SmartShiftGetRatchetControl => 0x00,
+            Function::SmartShiftSetRatchetControl => 0x01,
+            Function::SmartShiftEnhancedGetRatchetControl => 0x01,
+            Function::SmartShiftEnhancedSetRatchetControl => 0x02,
+            Function::HiResWheelGetCapability => 0x00,
+            Function::HiResWheelGetMode => 0x01,
+            Function::HiResWheelSetMode => 0x02,
+            Function::HiResWheelGetRatchetSwitch => 0x03,
+            Function::ChangeHostGetInfo => 0x00,
+            Function::ChangeHostSetCurrent => 0x01,
+            Function::HostsInfoGetFeatureInfo => 0x00,
+            Function::HostsInfoGetHostInfo => 0x01,
+            Function::HostsInfoGetHostFriendlyName => 0x03,
+            Function::HostsInfoSetHostFriendlyName => 0x04,
+            Function::Backlight2GetConfig => 0x00,
+            Function::Backlight2SetConfig => 0x01,
+            Function::Backlight2GetInfo => 0x02,
+            Function::ReprogControlsGetCount => 0x00,
+            Function::ReprogControlsGetCidInfo => 0x01,
+            Function::ReprogControlsGetCidReporting => 0x02,
+            Function::ReprogControlsSetCidReporting => 0x03,
+            Function::CrownGetInfo => 0x00,
+            Function::CrownGetMode => 0x01,
+            Function::CrownSetMode => 0x02,
+            Function::FnInversionGet => 0x00,
+            Function::FnInversionSet => 0x01,
+            Function::DisableKeysGetCapabilities => 0x00,
+            Function::DisableKeysGetDisabled => 0x01,
+            Function::DisableKeysSetDisabled => 0x02,
+            Function::MultiPlatformGetFeatureInfos => 0x00,
+            Function::MultiPlatformGetPlatformDescriptor => 0x01,
+            Function::MultiPlatformGetHostPlatform => 0x02,
+            Function::MultiPlatformSetHostPlatform => 0x03,
+            Function::ReportRateGetList => 0x00,
+            Function::ReportRateGetRate => 0x01,
+            Function::ReportRateSetRate => 0x02,
+            Function::RgbEffectsGetInfo => 0x00,
+            Function::RgbEffectsSetClusterEffect => 0x01,
+            Function::PerKeyLightingGetInfo => 0x00,
+            Function::PerKeyLightingSetZones => 0x01,
+            Function::PerKeyLightingFrameEnd => 0x07,
+            Function::OnboardProfilesGetInfo => 0x00,
+            Function::OnboardProfilesSetMode => 0x01,
+            Function::OnboardProfilesGetMode => 0x02,
+            Function::OnboardProfilesSetCurrentProfile => 0x03,
+            Function::OnboardProfilesGetCurrentProfile => 0x04,
+            Function::OnboardProfilesMemoryRead => 0x05,
+            Function::OnboardProfilesMemoryAddrWrite => 0x06,
+            Function::OnboardProfilesMemoryWrite => 0x07,
+            Function::OnboardProfilesMemoryWriteEnd => 0x08,
+            Function::OnboardProfilesGetCurrentDpiIndex => 0x0B,
+            Function::OnboardProfilesSetCurrentDpiIndex => 0x0C,
         }
     }
 }
@@ -59,11 +420,49 @@ pub enum ReportId {
 }
 
 impl ReportId {
-    fn to_u8(&self) -> u8 {
+    // bytes before the payload: report id, device index, feature index and
+    // function/software id
+    pub const HEADER_LEN: usize = consts::HEADER_LEN;
+
+    pub fn to_u8(&self) -> u8 {
         match self {
-            ReportId::Short => 0x10,
-            ReportId::Long => 0x11,
-            ReportId::VeryLong => 0x12,
+            ReportId::Short => consts::SHORT_REPORT_ID,
+            ReportId::Long => consts::LONG_REPORT_ID,
+            ReportId::VeryLong => consts::VERY_LONG_REPORT_ID,
+        }
+    }
+
+    // total length of the report on the wire, including the header
+    pub fn total_len(&self) -> usize {
+        match self {
+            ReportId::Short => consts::SHORT_REPORT_LEN,
+            ReportId::Long => consts::LONG_REPORT_LEN,
+            ReportId::VeryLong => consts::VERY_LONG_REPORT_LEN,
+        }
+    }
+
+    pub fn payload_len(&self) -> usize {
+        self.total_len() - Self::HEADER_LEN
+    }
+
+    // The smallest report that fits `len` payload bytes, None if even a very
+    // long report is too small
+    pub fn for_payload_len(len: usize) -> Option<ReportId> {
+        [ReportId::Short, ReportId::Long, ReportId::VeryLong]
+            .into_iter()
+            .find(|report_id| report_id.payload_len() >= len)
+    }
+}
+
+impl TryFrom<u8> for ReportId {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> anyhow::Result<Self> {
+        match value {
+            consts::SHORT_REPORT_ID => Ok(ReportId::Short),
+            consts::LONG_REPORT_ID => Ok(ReportId::Long),
+            consts::VERY_LONG_REPORT_ID => Ok(ReportId::VeryLong),
+            id => bail!("Invalid report id: 0x{:X}", id),
         }
     }
 }
@@ -74,6 +473,7 @@ impl ReportId {
 // 00 = feature_index
 // 10 = function_index (0x01 = ping) and software_id (0x00 = unknown)
 // 00 00 AA = data
+#[derive(Clone, Debug)]
 pub struct Message {
     // byte 0 - the report id (Short, Long or VeryLong)
     report_id: ReportId,
@@ -92,24 +492,80 @@ pub struct Message {
 }
 
 impl Message {
-    pub fn send(&self, device: &mut Device) -> anyhow::Result<Message> {
+    // the frame as sent on the wire, with the payload zero padded to the
+    // length of the report
+    pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = vec![
             self.report_id.to_u8(),
             self.device_index,
             self.feature_index,
             self.function_index << 4 | self.software_id & 0x0F,
         ];
-        // appends data to buf, padding with 0 until the length of 7
         buf.extend(
             self.data
                 .iter()
                 .copied()
                 .chain(std::iter::repeat(0))
-                .take(7),
+                .take(self.report_id.payload_len()),
         );
+        buf
+    }
 
-        let buf = device.write(&buf)?;
-        Message::try_from(buf.to_vec())
+    pub fn send(&self, device: &mut Device) -> anyhow::Result<Message> {
+        let request = self.to_bytes();
+        let buf = device
+            .write(&request)
+            .map_err(|e| anyhow::anyhow!("{} (request {})", e, to_hex(&request)))?;
+        let response = Message::try_from(buf.clone())
+            .map_err(|e| anyhow::anyhow!("{} (request {})", e, to_hex(&request)))?;
+        // the typed error stays reachable with `downcast_ref::<hidpp::Error>()`
+        let Some(error) = Error::from_message(&response) else {
+            return Ok(response);
+        };
+        let context = format!(
+            "Device returned error 0x{:02X} ({}) (request {}, response {})",
+            response.data[1],
+            error,
+            to_hex(&request),
+            to_hex(&buf)
+        );
+        Err(anyhow::Error::new(error).context(context))
+    }
+
+    // notifications carry a zero software id, replies echo the one we sent.
+    // Error replies and HID++ 1.0 register replies (0x80-0x83) can have a
+    // zero there too, HID++ 1.0 receiver notifications (0x40-0x4F) a
+    // parameter instead.
+    pub fn is_notification(&self) -> bool {
+        if self.error_code().is_some() || (0x80..=0x83).contains(&self.feature_index) {
+            return false;
+        }
+        self.software_id == 0 || (0x40..=0x4F).contains(&self.feature_index)
+    }
+
+    // HID++ 2.0 errors use feature index 0xFF, HID++ 1.0 ones sub id 0x8F,
+    // both with the error code after the echoed request bytes
+    pub fn error_code(&self) -> Option<u8> {
+        match self.feature_index {
+            consts::HIDPP20_ERROR | consts::HIDPP10_ERROR => self.data.get(1).copied(),
+            _ => None,
+        }
+    }
+
+    pub fn device_index(&self) -> u8 {
+        self.device_index
+    }
+
+    pub fn feature_index(&self) -> u8 {
+        self.feature_index
+    }
+
+    pub fn function_index(&self) -> u8 {
+        self.function_index
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
     }
 
     pub fn dump(&self) -> String {
@@ -121,17 +577,59 @@ impl Message {
     }
 }
 
+// A frame read from the device that can't be a HID++ report: empty, with an
+// unknown report id, or shorter than its report id calls for
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MalformedFrame {
+    pub bytes: Vec<u8>,
+    // length of the report, None if the report id isn't a HID++ one
+    pub expected_len: Option<usize>,
+}
+
+impl fmt::Display for MalformedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.bytes.first(), self.expected_len) {
+            (None, _) => write!(f, "Empty frame"),
+            (Some(id), None) => write!(
+                f,
+                "Invalid report id: 0x{:X} (frame {})",
+                id,
+                to_hex(&self.bytes)
+            ),
+            (Some(id), Some(expected_len)) => write!(
+                f,
+                "Truncated frame: report 0x{:X} is {} bytes, got {} (frame {})",
+                id,
+                expected_len,
+                self.bytes.len(),
+                to_hex(&self.bytes)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MalformedFrame {}
+
 impl TryFrom<Vec<u8>> for Message {
     type Error = anyhow::Error;
 
     fn try_from(buf: Vec<u8>) -> anyhow::Result<Self> {
+        let Some(report_id) = buf.first().and_then(|id| ReportId::try_from(*id).ok()) else {
+            return Err(MalformedFrame {
+                bytes: buf,
+                expected_len: None,
+            }
+            .into());
+        };
+        if buf.len() < report_id.total_len() {
+            return Err(MalformedFrame {
+                bytes: buf,
+                expected_len: Some(report_id.total_len()),
+            }
+            .into());
+        }
         Ok(Self {
-            report_id: match buf[0] {
-                0x10 => ReportId::Short,
-                0x11 => ReportId::Long,
-                0x12 => ReportId::VeryLong,
-                id => bail!("Invalid report id: 0x{:X}", id),
-            },
+            report_id,
             device_index: buf[1],
             feature_index: buf[2],
             function_index: buf[3] >> 4,
@@ -163,6 +661,19 @@ impl MessageBuilder {
         }
     }
 
+    // HID++ 1.0 register access: the sub id takes the place of the feature
+    // index and the register address that of function and software id
+    pub fn new_register(sub_id: u8, register: u8) -> Self {
+        Self {
+            report_id: ReportId::Short,
+            device_index: 0xff,
+            feature_index: sub_id,
+            function_index: register >> 4,
+            software_id: register & 0x0F,
+            data: vec![],
+        }
+    }
+
     pub fn report_id(mut self, report_id: ReportId) -> Self {
         self.report_id = report_id;
         self
@@ -193,19 +704,78 @@ impl MessageBuilder {
         self
     }
 
+    // HID++ numbers are big endian, the `_le` variants are for the few
+    // fields that aren't, e.g. wireless product ids in receiver registers
+    pub fn add_u8(mut self, data: u8) -> Self {
+        self.data.push(data);
+        self
+    }
+
+    pub fn add_bytes(mut self, data: &[u8]) -> Self {
+        self.data.extend_from_slice(data);
+        self
+    }
+
     pub fn add_u16(mut self, data: u16) -> Self {
         self.data.extend_from_slice(&data.to_be_bytes());
         self
     }
 
+    pub fn add_u16_le(mut self, data: u16) -> Self {
+        self.data.extend_from_slice(&data.to_le_bytes());
+        self
+    }
+
+    pub fn add_i16(mut self, data: i16) -> Self {
+        self.data.extend_from_slice(&data.to_be_bytes());
+        self
+    }
+
+    pub fn add_i16_le(mut self, data: i16) -> Self {
+        self.data.extend_from_slice(&data.to_le_bytes());
+        self
+    }
+
+    pub fn add_u32(mut self, data: u32) -> Self {
+        self.data.extend_from_slice(&data.to_be_bytes());
+        self
+    }
+
+    pub fn add_u32_le(mut self, data: u32) -> Self {
+        self.data.extend_from_slice(&data.to_le_bytes());
+        self
+    }
+
+    // Like `build`, but fails when the payload doesn't fit the report
+    // instead of cutting it short
+    pub fn try_build(self) -> anyhow::Result<Message> {
+        if self.data.len() > self.report_id.payload_len() {
+            bail!(
+                "Payload of {} bytes doesn't fit a {:?} report ({} bytes)",
+                self.data.len(),
+                self.report_id,
+                self.report_id.payload_len()
+            );
+        }
+        Ok(self.build())
+    }
+
     pub fn build(self) -> Message {
-        // if self.data len is less than 3 then fill it up with 0x00
+        if self.data.len() > self.report_id.payload_len() {
+            crate::tracing::warn!(
+                "Truncating a {} byte payload to fit a {:?} report",
+                self.data.len(),
+                self.report_id
+            );
+        }
+        // the payload is zero padded to the length of the report: 3 bytes for
+        // short reports, 16 for long and 60 for very long ones
         let data = self
             .data
             .iter()
             .copied()
             .chain(std::iter::repeat(0))
-            .take(3)
+            .take(self.report_id.payload_len())
             .collect();
         Message {
             report_id: self.report_id,
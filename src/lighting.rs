@@ -0,0 +1,336 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+
+use crate::{Device, Feature, Function, ReportId};
+
+// each key update is the key's zone id followed by its color
+const ZONE_UPDATE_LEN: usize = 4;
+// RgbEffects getInfo target meaning "the device" or "the zone" rather than a
+// particular zone or effect
+const ALL: u8 = 0xFF;
+const EFFECT_PARAMS_LEN: usize = 10;
+// device capability bit for effects that can be kept in RAM only, and the
+// setClusterEffect byte choosing where they go
+const NON_VOLATILE_CAPABLE: u16 = 0x0001;
+const PERSIST_FLASH: u8 = 0x01;
+const PERSIST_RAM: u8 = 0x00;
+
+// Where a lighting zone sits on the device
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum LedZoneLocation {
+    Primary,
+    Logo,
+    LeftSide,
+    RightSide,
+    Combined,
+    // one of several primary zones, numbered from 1
+    PrimaryN(u8),
+    Other(u16),
+}
+
+impl From<u16> for LedZoneLocation {
+    fn from(value: u16) -> Self {
+        match value {
+            0x0001 => LedZoneLocation::Primary,
+            0x0002 => LedZoneLocation::Logo,
+            0x0003 => LedZoneLocation::LeftSide,
+            0x0004 => LedZoneLocation::RightSide,
+            0x0005 => LedZoneLocation::Combined,
+            0x0006..=0x000B => LedZoneLocation::PrimaryN(value as u8 - 0x05),
+            value => LedZoneLocation::Other(value),
+        }
+    }
+}
+
+// RgbEffects (0x8071) effects, the parameters only apply to some
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum LedEffect {
+    Off,
+    Static { color: [u8; 3] },
+    Pulse { color: [u8; 3], speed: u8 },
+    // through all colors, `period` milliseconds per cycle
+    Cycle { period: u16 },
+    Breathing { color: [u8; 3], period: u16 },
+    Ripple { color: [u8; 3], period: u16 },
+    Other(u16),
+}
+
+impl LedEffect {
+    pub fn id(&self) -> u16 {
+        match self {
+            LedEffect::Off => 0x0000,
+            LedEffect::Static { .. } => 0x0001,
+            LedEffect::Pulse { .. } => 0x0002,
+            LedEffect::Cycle { .. } => 0x0003,
+            LedEffect::Breathing { .. } => 0x000A,
+            LedEffect::Ripple { .. } => 0x000B,
+            LedEffect::Other(id) => *id,
+        }
+    }
+
+    // The effect with default parameters, as listed by a zone
+    fn from_id(id: u16) -> Self {
+        let color = [0xFF, 0xFF, 0xFF];
+        match id {
+            0x0000 => LedEffect::Off,
+            0x0001 => LedEffect::Static { color },
+            0x0002 => LedEffect::Pulse { color, speed: 0 },
+            0x0003 => LedEffect::Cycle { period: 10_000 },
+            0x000A => LedEffect::Breathing {
+                color,
+                period: 5_000,
+            },
+            0x000B => LedEffect::Ripple { color, period: 100 },
+            id => LedEffect::Other(id),
+        }
+    }
+
+    fn params(&self) -> [u8; EFFECT_PARAMS_LEN] {
+        let mut params = [0; EFFECT_PARAMS_LEN];
+        match *self {
+            LedEffect::Off | LedEffect::Other(_) => {}
+            LedEffect::Static { color } => params[..3].copy_from_slice(&color),
+            LedEffect::Pulse { color, speed } => {
+                params[..3].copy_from_slice(&color);
+                params[3] = speed;
+            }
+            LedEffect::Cycle { period } => {
+                params[5..7].copy_from_slice(&period.to_be_bytes());
+                // intensity, 100%
+                params[7] = 100;
+            }
+            LedEffect::Breathing { color, period } => {
+                params[..3].copy_from_slice(&color);
+                params[3..5].copy_from_slice(&period.to_be_bytes());
+                params[6] = 100;
+            }
+            LedEffect::Ripple { color, period } => {
+                params[..3].copy_from_slice(&color);
+                params[4..6].copy_from_slice(&period.to_be_bytes());
+            }
+        }
+        params
+    }
+}
+
+// A lighting zone (cluster) and the effects it supports, in the device's order
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct LedZone {
+    pub index: u8,
+    pub location: LedZoneLocation,
+    pub effects: Vec<LedEffect>,
+}
+
+// Where effect changes go: RAM only, lost on power off but free to change as
+// often as needed, or onboard memory, which wears out with every write
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum LedPersistence {
+    #[default]
+    Volatile,
+    Persistent,
+}
+
+impl Device {
+    pub fn led_zones(&mut self) -> anyhow::Result<Vec<LedZone>> {
+        let info = self.send_feature(
+            Feature::RgbEffects,
+            Function::RgbEffectsGetInfo,
+            &[ALL, ALL, 0],
+        )?;
+        let count = info.data[2];
+
+        let mut zones = vec![];
+        for index in 0..count {
+            let zone = self.send_feature(
+                Feature::RgbEffects,
+                Function::RgbEffectsGetInfo,
+                &[index, ALL, 0],
+            )?;
+            let location = u16::from_be_bytes([zone.data[2], zone.data[3]]);
+            let mut effects = vec![];
+            for effect in 0..zone.data[4] {
+                let info = self.send_feature(
+                    Feature::RgbEffects,
+                    Function::RgbEffectsGetInfo,
+                    &[index, effect, 0],
+                )?;
+                effects.push(LedEffect::from_id(u16::from_be_bytes([
+                    info.data[2],
+                    info.data[3],
+                ])));
+            }
+            zones.push(LedZone {
+                index,
+                location: LedZoneLocation::from(location),
+                effects,
+            });
+        }
+        Ok(zones)
+    }
+
+    // Whether the device lets effects be kept out of onboard memory. Those
+    // that don't always persist them. Asked once, `set_led_effect` checks it
+    // on every change.
+    pub fn led_persistence_supported(&mut self) -> anyhow::Result<bool> {
+        if let Some(supported) = self.led_persistence {
+            return Ok(supported);
+        }
+        let info = self.send_feature(
+            Feature::RgbEffects,
+            Function::RgbEffectsGetInfo,
+            &[ALL, ALL, 0],
+        )?;
+        let capabilities = u16::from_be_bytes([info.data[3], info.data[4]]);
+        let supported = capabilities & NON_VOLATILE_CAPABLE != 0;
+        self.led_persistence = Some(supported);
+        Ok(supported)
+    }
+
+    // Applies an effect to a zone. Animation tools should stick to
+    // `LedPersistence::Volatile` to spare the onboard memory.
+    pub fn set_led_effect(
+        &mut self,
+        zone: &LedZone,
+        effect: LedEffect,
+        persistence: LedPersistence,
+    ) -> anyhow::Result<()> {
+        let Some(effect_index) = zone.effects.iter().position(|e| e.id() == effect.id()) else {
+            bail!(
+                "Zone {:?} doesn't support {:?}, it has {:?}",
+                zone.location,
+                effect,
+                zone.effects
+            );
+        };
+        let persist = match persistence {
+            LedPersistence::Persistent => PERSIST_FLASH,
+            LedPersistence::Volatile if self.led_persistence_supported()? => PERSIST_RAM,
+            LedPersistence::Volatile => {
+                crate::tracing::debug!(
+                    "Device can't keep LED effects in RAM, they'll be persisted"
+                );
+                PERSIST_FLASH
+            }
+        };
+
+        let mut payload = vec![zone.index, effect_index as u8];
+        payload.extend_from_slice(&effect.params());
+        payload.push(persist);
+        self.send_feature(
+            Feature::RgbEffects,
+            Function::RgbEffectsSetClusterEffect,
+            &payload,
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct FrameStats {
+    // frames sent to the device
+    pub frames: u64,
+    // frames replaced by a newer one before they could be sent
+    pub dropped: u64,
+    // reports the frames took
+    pub reports: u64,
+}
+
+// Streams per-key colors (PerKeyLighting, 0x8081) at up to a target frame
+// rate. Frames are queued with `push_frame` and sent by `tick`; a frame
+// queued while the previous one is still waiting is merged into it and
+// counted as dropped, so a slow device falls behind in frames rather than in
+// time. Only keys whose color changed since the last frame are sent, as many
+// per report as the largest report the device accepts holds.
+pub struct LedStreamer {
+    interval: Duration,
+    last_sent: Option<Instant>,
+    // colors sent to the device so far
+    current: BTreeMap<u8, [u8; 3]>,
+    pending: Option<BTreeMap<u8, [u8; 3]>>,
+    stats: FrameStats,
+}
+
+impl LedStreamer {
+    pub fn new(fps: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / fps.max(1),
+            last_sent: None,
+            current: BTreeMap::new(),
+            pending: None,
+            stats: FrameStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    // Queues colors for some keys, keys not listed keep their color
+    pub fn push_frame(&mut self, keys: &[(u8, [u8; 3])]) {
+        if self.pending.is_some() {
+            self.stats.dropped += 1;
+        }
+        let pending = self.pending.get_or_insert_with(BTreeMap::new);
+        pending.extend(keys.iter().copied());
+    }
+
+    // Sends the queued frame if the frame interval has passed, returning
+    // whether one was sent
+    pub fn tick(&mut self, device: &mut Device) -> anyhow::Result<bool> {
+        if self
+            .last_sent
+            .is_some_and(|last| last.elapsed() < self.interval)
+        {
+            return Ok(false);
+        }
+        let Some(pending) = self.pending.take() else {
+            return Ok(false);
+        };
+        let changed = pending
+            .into_iter()
+            .filter(|(key, color)| self.current.get(key) != Some(color))
+            .collect::<Vec<_>>();
+        if changed.is_empty() {
+            return Ok(false);
+        }
+        self.last_sent = Some(Instant::now());
+
+        let per_report =
+            device.max_report().unwrap_or(ReportId::Long).payload_len() / ZONE_UPDATE_LEN;
+        for chunk in changed.chunks(per_report) {
+            let payload = chunk
+                .iter()
+                .flat_map(|(key, [r, g, b])| [*key, *r, *g, *b])
+                .collect::<Vec<_>>();
+            device.send_feature(
+                Feature::PerKeyLighting,
+                Function::PerKeyLightingSetZones,
+                &payload,
+            )?;
+            self.stats.reports += 1;
+        }
+        // nothing shows until the frame is ended
+        device.send_feature(
+            Feature::PerKeyLighting,
+            Function::PerKeyLightingFrameEnd,
+            &[0x00],
+        )?;
+        self.stats.reports += 1;
+        self.stats.frames += 1;
+        self.current.extend(changed);
+        Ok(true)
+    }
+
+    // Waits for the next frame slot and sends the queued frame
+    pub fn flush(&mut self, device: &mut Device) -> anyhow::Result<()> {
+        if let Some(last) = self.last_sent {
+            std::thread::sleep(self.interval.saturating_sub(last.elapsed()));
+        }
+        self.tick(device)?;
+        Ok(())
+    }
+}
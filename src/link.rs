@@ -0,0 +1,34 @@
+use crate::Device;
+
+// How well the wireless link is doing, 0-100. Receivers that send link
+// quality notifications report it directly; otherwise it's estimated from
+// the share of requests that had to be retried or timed out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct LinkQuality {
+    pub quality: u8,
+    pub estimated: bool,
+}
+
+impl Device {
+    pub fn link_quality(&self) -> LinkQuality {
+        if let Some(quality) = self.reported_link_quality {
+            return LinkQuality {
+                quality,
+                estimated: false,
+            };
+        }
+
+        let stats = self.stats();
+        let quality = match stats.requests {
+            0 => 100,
+            requests => {
+                let lost = (stats.retries + stats.timeouts).min(requests);
+                100 - (lost * 100 / requests) as u8
+            }
+        };
+        LinkQuality {
+            quality,
+            estimated: true,
+        }
+    }
+}
@@ -0,0 +1,565 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use hidpp::{
+    diagnostics,
+    frame_log::{to_hex, FrameLogger},
+    replay, BacklightConfig, BacklightMode, BatteryMonitor, CancellationToken, Device, Event,
+    LedEffect, LedPersistence, LedZone, LedZoneLocation,
+};
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
+
+#[derive(Parser)]
+#[command(version, about = "Query and configure Logitech HID++ devices")]
+struct Cli {
+    #[arg(long, value_parser = parse_hex, default_value = "046d")]
+    vid: u16,
+
+    #[arg(long, value_parser = parse_hex, default_value = "c547")]
+    pid: u16,
+
+    /// Write every frame sent and received to this file, as JSON lines
+    #[arg(long)]
+    log_frames: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the battery status
+    Battery,
+
+    /// Measure the round-trip time to the device
+    Ping {
+        #[arg(short = 'n', long, default_value_t = 10)]
+        count: usize,
+    },
+
+    /// Print battery changes as they happen
+    Watch {
+        /// Seconds between polls when the device sends no events
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+
+        /// Show desktop notifications when the battery runs low
+        #[arg(long)]
+        notify: bool,
+
+        /// Don't poll while the device is asleep, to avoid waking its radio
+        #[arg(long)]
+        skip_asleep: bool,
+    },
+
+    /// Run a series of checks to troubleshoot an unresponsive device
+    Doctor,
+
+    /// Re-send the requests of a recorded session and diff the replies
+    Replay {
+        session: PathBuf,
+
+        /// Send requests back to back instead of with the recorded spacing
+        #[arg(long)]
+        no_timing: bool,
+    },
+
+    /// Print or change the keyboard backlight
+    Backlight {
+        /// Turn the backlight on or off
+        #[arg(long)]
+        enabled: Option<bool>,
+
+        #[arg(long, value_enum)]
+        mode: Option<BacklightModeArg>,
+
+        /// Brightness, implies manual mode
+        #[arg(long)]
+        level: Option<u8>,
+
+        /// Keep printing changes made on the keyboard
+        #[arg(long)]
+        follow: bool,
+    },
+
+    /// Print or change the scroll wheel and SmartShift settings
+    Wheel {
+        /// High resolution scrolling
+        #[arg(long)]
+        hires: Option<bool>,
+
+        /// Invert the scroll direction
+        #[arg(long)]
+        invert: Option<bool>,
+
+        #[arg(long, value_enum)]
+        mode: Option<WheelModeArg>,
+
+        /// Scroll speed at which SmartShift switches to free spin, 1-254 or
+        /// 255 to never switch
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..))]
+        smartshift: Option<u8>,
+    },
+
+    /// List the Easy-Switch host channels
+    Hosts {
+        #[command(subcommand)]
+        action: Option<HostsAction>,
+    },
+
+    /// Record all traffic with the device to a file until Ctrl-C, for bug
+    /// reports. Play it back with `replay`.
+    Capture { output: PathBuf },
+
+    /// Change the color and effect of the lighting zones
+    Rgb {
+        /// List the zones and the effects they support
+        #[arg(long)]
+        list: bool,
+
+        #[command(subcommand)]
+        action: Option<RgbAction>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RgbAction {
+    /// Apply an effect to a zone
+    Set {
+        /// Zone name as listed, e.g. logo, or its number
+        #[arg(long)]
+        zone: String,
+
+        /// Color as hex, e.g. ff0000
+        #[arg(long, value_parser = parse_color, default_value = "ffffff")]
+        color: [u8; 3],
+
+        #[arg(long, value_enum, default_value = "static")]
+        effect: EffectArg,
+
+        /// Milliseconds per cycle of animated effects
+        #[arg(long, default_value_t = 5000)]
+        period: u16,
+
+        /// Store the effect in onboard memory so it survives power off
+        #[arg(long)]
+        persist: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EffectArg {
+    Off,
+    Static,
+    Pulse,
+    Cycle,
+    Breathing,
+    Ripple,
+}
+
+#[derive(Subcommand)]
+enum HostsAction {
+    /// Switch the device to another channel
+    Switch {
+        /// Channel number, as labeled on the device
+        #[arg(value_parser = clap::value_parser!(u8).range(1..))]
+        host: u8,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum WheelModeArg {
+    Ratchet,
+    Freespin,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BacklightModeArg {
+    Basic,
+    Automatic,
+    Manual,
+}
+
+impl From<BacklightModeArg> for BacklightMode {
+    fn from(mode: BacklightModeArg) -> Self {
+        match mode {
+            BacklightModeArg::Basic => BacklightMode::Basic,
+            BacklightModeArg::Automatic => BacklightMode::Automatic,
+            BacklightModeArg::Manual => BacklightMode::Manual,
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u16, String> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|e| e.to_string())
+}
+
+fn parse_color(s: &str) -> Result<[u8; 3], String> {
+    let s = s.trim_start_matches('#');
+    if s.len() != 6 {
+        return Err("expected 6 hex digits".to_string());
+    }
+    let rgb = u32::from_str_radix(s, 16).map_err(|e| e.to_string())?;
+    let [_, r, g, b] = rgb.to_be_bytes();
+    Ok([r, g, b])
+}
+
+fn main() -> anyhow::Result<()> {
+    let subscriber = FmtSubscriber::builder()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .or_else(|_| EnvFilter::try_new("info"))
+                .unwrap(),
+        )
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let cli = Cli::parse();
+    match &cli.command {
+        Command::Battery => battery(&mut open(&cli)?),
+        Command::Ping { count } => ping(&mut open(&cli)?, *count),
+        Command::Watch {
+            interval,
+            notify,
+            skip_asleep,
+        } => watch(&mut open(&cli)?, *interval, *notify, *skip_asleep),
+        Command::Doctor => doctor(&cli),
+        Command::Replay { session, no_timing } => replay(&mut open(&cli)?, session, *no_timing),
+        Command::Backlight {
+            enabled,
+            mode,
+            level,
+            follow,
+        } => backlight(&mut open(&cli)?, *enabled, *mode, *level, *follow),
+        Command::Wheel {
+            hires,
+            invert,
+            mode,
+            smartshift,
+        } => wheel(&mut open(&cli)?, *hires, *invert, *mode, *smartshift),
+        Command::Hosts { action } => hosts(&mut open(&cli)?, action.as_ref()),
+        Command::Capture { output } => capture(&cli, output),
+        Command::Rgb { list, action } => rgb(&mut open(&cli)?, *list, action.as_ref()),
+    }
+}
+
+fn open(cli: &Cli) -> anyhow::Result<Device> {
+    let mut device = Device::new(cli.vid, cli.pid)?;
+    if let Some(path) = &cli.log_frames {
+        device.set_frame_logger(Some(FrameLogger::create(path)?));
+    }
+    Ok(device)
+}
+
+fn battery(device: &mut Device) -> anyhow::Result<()> {
+    let battery = device.get_battery()?;
+    if battery.estimated {
+        println!("Battery: ~{}%", battery.percentage);
+    } else {
+        println!("Battery: {}%", battery.percentage);
+    }
+    println!("Level: {:?}", battery.level);
+    println!("Status: {:?}", battery.status);
+    Ok(())
+}
+
+fn ping(device: &mut Device, count: usize) -> anyhow::Result<()> {
+    let stats = device.measure_latency(count)?;
+    println!("{} pings", stats.samples);
+    println!("min: {:?}", stats.min);
+    println!("avg: {:?}", stats.avg);
+    println!("p95: {:?}", stats.p95);
+    println!("max: {:?}", stats.max);
+    Ok(())
+}
+
+fn watch(
+    device: &mut Device,
+    interval: u64,
+    notify: bool,
+    skip_asleep: bool,
+) -> anyhow::Result<()> {
+    let mut monitor = BatteryMonitor::new(Duration::from_secs(interval));
+    monitor.skip_while_asleep(skip_asleep);
+    monitor.on_change(|battery| {
+        println!(
+            "{}% {:?} {:?}",
+            battery.percentage, battery.level, battery.status
+        )
+    });
+
+    if notify {
+        #[cfg(feature = "notifications")]
+        monitor.on_threshold(
+            vec![20, 10, 5],
+            hidpp::notifications::threshold_notifier("Battery"),
+        );
+        #[cfg(not(feature = "notifications"))]
+        anyhow::bail!("Built without the notifications feature");
+    }
+
+    monitor.run(device)
+}
+
+fn doctor(cli: &Cli) -> anyhow::Result<()> {
+    let report = diagnostics::diagnose(cli.vid, cli.pid);
+    print!("{}", report);
+    if !report.healthy() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn replay(device: &mut Device, session: &Path, no_timing: bool) -> anyhow::Result<()> {
+    let transactions = replay::load_session(session)?;
+    let results = replay::replay(device, &transactions, !no_timing);
+    let mismatches = results.iter().filter(|r| !r.matches()).count();
+    for result in results.iter().filter(|r| !r.matches()) {
+        println!("request  {}", to_hex(&result.request));
+        match &result.expected {
+            Some(expected) => println!("- {}", to_hex(expected)),
+            None => println!("- (no reply)"),
+        }
+        match &result.actual {
+            Ok(actual) => println!("+ {}", to_hex(actual)),
+            Err(err) => println!("+ (error: {})", err),
+        }
+        println!();
+    }
+    println!(
+        "{} of {} replies matched",
+        results.len() - mismatches,
+        results.len()
+    );
+    if mismatches > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn capture(cli: &Cli, output: &Path) -> anyhow::Result<()> {
+    let mut device = Device::new(cli.vid, cli.pid)?;
+    device.set_frame_logger(Some(FrameLogger::create(output)?));
+    let stop = CancellationToken::new();
+    {
+        let stop = stop.clone();
+        ctrlc::set_handler(move || stop.cancel())?;
+    }
+
+    // frames for features discovered here get annotated with their names
+    device.init()?;
+    println!("Capturing to {}, press Ctrl-C to stop", output.display());
+    let mut events = 0;
+    while !stop.is_cancelled() {
+        if let Some(event) = device.next_event(Duration::from_millis(200))? {
+            println!("{:?}", event);
+            events += 1;
+        }
+    }
+    println!(
+        "Captured {} requests and {} events",
+        device.stats().requests,
+        events
+    );
+    Ok(())
+}
+
+fn backlight(
+    device: &mut Device,
+    enabled: Option<bool>,
+    mode: Option<BacklightModeArg>,
+    level: Option<u8>,
+    follow: bool,
+) -> anyhow::Result<()> {
+    let mut config = device.get_backlight()?;
+    if enabled.is_some() || mode.is_some() || level.is_some() {
+        if let Some(enabled) = enabled {
+            config.enabled = enabled;
+        }
+        if let Some(mode) = mode {
+            config.mode = mode.into();
+        }
+        if let Some(level) = level {
+            let levels = device.backlight_levels()?;
+            if level >= levels {
+                anyhow::bail!("Level must be below {}", levels);
+            }
+            config.mode = BacklightMode::Manual;
+            config.level = level;
+        }
+        device.set_backlight(&config)?;
+        config = device.get_backlight()?;
+    }
+    print_backlight(&config);
+    if !follow {
+        return Ok(());
+    }
+
+    loop {
+        if let Some(Event::Backlight(config)) = device.next_event(Duration::from_secs(1))? {
+            print_backlight(&config);
+        }
+    }
+}
+
+fn print_backlight(config: &BacklightConfig) {
+    println!(
+        "{} {:?} level {}",
+        if config.enabled { "on" } else { "off" },
+        config.mode,
+        config.level
+    );
+}
+
+fn wheel(
+    device: &mut Device,
+    hires: Option<bool>,
+    invert: Option<bool>,
+    mode: Option<WheelModeArg>,
+    smartshift: Option<u8>,
+) -> anyhow::Result<()> {
+    if hires.is_some() || invert.is_some() {
+        let mut wheel_mode = device.get_wheel_mode()?;
+        if let Some(hires) = hires {
+            wheel_mode.hires = hires;
+        }
+        if let Some(invert) = invert {
+            wheel_mode.inverted = invert;
+        }
+        device.set_wheel_mode(wheel_mode)?;
+    }
+    if mode.is_some() || smartshift.is_some() {
+        let ratchet = mode.map(|mode| mode == WheelModeArg::Ratchet);
+        device.set_smart_shift(ratchet, smartshift)?;
+    }
+
+    match device.wheel_capabilities() {
+        Ok(capabilities) => {
+            let wheel_mode = device.get_wheel_mode()?;
+            println!("Resolution: {}x", capabilities.multiplier);
+            println!("Hi-res: {}", if wheel_mode.hires { "on" } else { "off" });
+            if capabilities.has_invert {
+                println!(
+                    "Inverted: {}",
+                    if wheel_mode.inverted { "yes" } else { "no" }
+                );
+            }
+        }
+        Err(err) => tracing::debug!("No hi-res wheel: {}", err),
+    }
+    match device.get_smart_shift() {
+        Ok(smart_shift) => {
+            let mode = if smart_shift.ratchet {
+                "ratchet"
+            } else {
+                "free spin"
+            };
+            println!("Mode: {}", mode);
+            println!("SmartShift threshold: {}", smart_shift.threshold);
+        }
+        Err(err) => tracing::debug!("No SmartShift: {}", err),
+    }
+    Ok(())
+}
+
+fn hosts(device: &mut Device, action: Option<&HostsAction>) -> anyhow::Result<()> {
+    if let Some(HostsAction::Switch { host }) = action {
+        device.switch_host(host - 1)?;
+        println!("Switched to host {}", host);
+        return Ok(());
+    }
+
+    for host in device.hosts()? {
+        let marker = if host.current { "*" } else { " " };
+        let status = match host.paired {
+            Some(true) => "paired",
+            Some(false) => "empty",
+            None => "unknown",
+        };
+        match &host.name {
+            Some(name) => println!("{} {} {} ({})", marker, host.index + 1, name, status),
+            None => println!("{} {} ({})", marker, host.index + 1, status),
+        }
+    }
+    Ok(())
+}
+
+fn rgb(device: &mut Device, list: bool, action: Option<&RgbAction>) -> anyhow::Result<()> {
+    let zones = device.led_zones()?;
+    if let Some(RgbAction::Set {
+        zone,
+        color,
+        effect,
+        period,
+        persist,
+    }) = action
+    {
+        let zone = find_zone(&zones, zone)?;
+        let (color, period) = (*color, *period);
+        let effect = match effect {
+            EffectArg::Off => LedEffect::Off,
+            EffectArg::Static => LedEffect::Static { color },
+            EffectArg::Pulse => LedEffect::Pulse { color, speed: 0 },
+            EffectArg::Cycle => LedEffect::Cycle { period },
+            EffectArg::Breathing => LedEffect::Breathing { color, period },
+            EffectArg::Ripple => LedEffect::Ripple { color, period },
+        };
+        let persistence = if *persist {
+            LedPersistence::Persistent
+        } else {
+            LedPersistence::Volatile
+        };
+        device.set_led_effect(zone, effect, persistence)?;
+        if !list {
+            return Ok(());
+        }
+    }
+
+    for zone in &zones {
+        let effects = zone
+            .effects
+            .iter()
+            .map(effect_name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!("{} {}: {}", zone.index, zone_name(zone.location), effects);
+    }
+    Ok(())
+}
+
+fn find_zone<'a>(zones: &'a [LedZone], name: &str) -> anyhow::Result<&'a LedZone> {
+    zones
+        .iter()
+        .find(|zone| {
+            zone_name(zone.location).eq_ignore_ascii_case(name)
+                || name.parse::<u8>() == Ok(zone.index)
+        })
+        .ok_or_else(|| anyhow::anyhow!("No zone {}, see --list", name))
+}
+
+fn zone_name(location: LedZoneLocation) -> String {
+    match location {
+        LedZoneLocation::Primary => "primary".to_string(),
+        LedZoneLocation::Logo => "logo".to_string(),
+        LedZoneLocation::LeftSide => "left".to_string(),
+        LedZoneLocation::RightSide => "right".to_string(),
+        LedZoneLocation::Combined => "combined".to_string(),
+        LedZoneLocation::PrimaryN(n) => format!("primary{}", n),
+        LedZoneLocation::Other(value) => format!("0x{:04X}", value),
+    }
+}
+
+fn effect_name(effect: &LedEffect) -> String {
+    match effect {
+        LedEffect::Off => "off".to_string(),
+        LedEffect::Static { .. } => "static".to_string(),
+        LedEffect::Pulse { .. } => "pulse".to_string(),
+        LedEffect::Cycle { .. } => "cycle".to_string(),
+        LedEffect::Breathing { .. } => "breathing".to_string(),
+        LedEffect::Ripple { .. } => "ripple".to_string(),
+        LedEffect::Other(id) => format!("0x{:04X}", id),
+    }
+}
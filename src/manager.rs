@@ -0,0 +1,530 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    ffi::{CStr, CString},
+    fmt, thread,
+};
+
+use crate::{consts::RECEIVER_PRODUCT_IDS, discovery, BatteryInfo, Device, Receiver};
+
+// Identifies a managed device for as long as the manager has it, across
+// disconnects. Each receiver slot gets its own, so devices sharing a
+// receiver, or identical receivers, stay apart.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct HandleId(u32);
+
+struct ManagedDevice {
+    id: HandleId,
+    vendor_id: u16,
+    product_id: u16,
+    // HID node the handle is opened from, so identical receivers stay apart.
+    // Devices added by ids learn it when they connect.
+    path: Option<CString>,
+    // receiver entry and slot (1-6) of a device behind a receiver
+    slot: Option<(HandleId, u8)>,
+    device: Option<Device>,
+    // set on a receiver's own entry while it's connected, the devices paired
+    // to it are opened through it
+    receiver: Option<Receiver>,
+    // read when the device connects, None when it doesn't report one
+    unit_id: Option<[u8; 4]>,
+    route: Route,
+}
+
+impl ManagedDevice {
+    fn new(id: HandleId, vendor_id: u16, product_id: u16) -> Self {
+        Self {
+            id,
+            vendor_id,
+            product_id,
+            path: None,
+            slot: None,
+            device: None,
+            receiver: None,
+            unit_id: None,
+            route: Route::default(),
+        }
+    }
+
+    fn is_receiver(&self) -> bool {
+        self.slot.is_none() && RECEIVER_PRODUCT_IDS.contains(&self.product_id)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.device.is_some() || self.receiver.is_some()
+    }
+}
+
+impl fmt::Display for ManagedDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vendor_id, self.product_id)?;
+        if let Some((_, slot)) = self.slot {
+            write!(f, " slot {}", slot)?;
+        }
+        Ok(())
+    }
+}
+
+// How a handle reaches the device: through a receiver slot, a USB cable
+// (Lightspeed mice in cable mode) or straight to its Bluetooth HID node.
+// Without a selected route, units reachable over several use the first of
+// cable, receiver and Bluetooth that's connected.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Route {
+    Cable,
+    #[default]
+    Receiver,
+    Bluetooth,
+}
+
+// One physical unit and the handles that reach it. A device paired to a Bolt
+// receiver and over Bluetooth at the same time shows up twice on the bus with
+// the same unit id.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LogicalDevice {
+    pub unit_id: Option<[u8; 4]>,
+    // handle and route of each connected handle, the one in use first
+    pub routes: Vec<(HandleId, Route)>,
+}
+
+// Handles that appeared and disappeared since the previous rescan. A
+// receiver comes with one handle per paired device.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ScanDiff {
+    pub added: Vec<HandleId>,
+    pub removed: Vec<HandleId>,
+}
+
+// Keeps track of a set of devices that come and go, e.g. as they're turned
+// off or move out of range. With several receivers attached, the devices
+// paired to them share one namespace: each slot gets its own handle, a unit
+// that moves to another receiver keeps its unit id, and its handle on the
+// receiver it left is dropped.
+#[derive(Default)]
+pub struct DeviceManager {
+    devices: Vec<ManagedDevice>,
+    next_id: u32,
+    // HID nodes of the HID++ devices present on the bus at the last rescan
+    present: BTreeSet<CString>,
+    // route picked for units reachable over several
+    selected: HashMap<[u8; 4], Route>,
+}
+
+impl DeviceManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Manages the first device with these ids. Adding a receiver manages the
+    // devices paired to it too, they get their handles as it connects.
+    pub fn add(&mut self, vendor_id: u16, product_id: u16) -> HandleId {
+        let existing = self
+            .devices
+            .iter()
+            .find(|m| m.vendor_id == vendor_id && m.product_id == product_id && m.slot.is_none());
+        if let Some(existing) = existing {
+            return existing.id;
+        }
+        let id = self.next_id();
+        self.devices
+            .push(ManagedDevice::new(id, vendor_id, product_id));
+        id
+    }
+
+    // Stops managing the device, along with the devices paired to it when
+    // it's a receiver
+    pub fn remove(&mut self, id: HandleId) -> Option<Device> {
+        let position = self.position(id)?;
+        let managed = self.devices.remove(position);
+        self.devices
+            .retain(|m| m.slot.is_none_or(|(receiver, _)| receiver != id));
+        managed
+            .device
+            .or(managed.receiver.map(Receiver::into_inner))
+    }
+
+    // Tries to open every device that isn't connected, returning the handles
+    // that just connected
+    pub fn connect(&mut self) -> Vec<HandleId> {
+        let mut connected = vec![];
+        // the devices behind a receiver are opened through it
+        for position in 0..self.devices.len() {
+            if self.devices[position].is_receiver() {
+                connected.extend(self.connect_receiver(position));
+            } else if self.devices[position].slot.is_none() {
+                connected.extend(self.connect_device(position));
+            }
+        }
+        connected
+    }
+
+    fn connect_device(&mut self, position: usize) -> Option<HandleId> {
+        let managed = &self.devices[position];
+        if managed.is_connected() {
+            return None;
+        }
+        // a second handle on a receiver would steal the replies and
+        // notifications meant for the first one
+        if managed.path.is_some() && self.is_open(managed.path.as_deref(), position) {
+            crate::tracing::debug!("{} is already open", managed);
+            return None;
+        }
+        let opened = match &managed.path {
+            Some(path) => Device::builder()
+                .path(path.as_bytes())
+                .and_then(|builder| builder.open()),
+            None => Device::new(managed.vendor_id, managed.product_id),
+        };
+        let device = match opened {
+            Ok(device) => device,
+            Err(err) => {
+                crate::tracing::trace!("{} not available: {}", managed, err);
+                return None;
+            }
+        };
+        let path = device.hid_info().ok().and_then(|info| info.path);
+        if self.devices[position].path.is_none() && self.is_open(path.as_deref(), position) {
+            crate::tracing::debug!("{} is already open", self.devices[position]);
+            return None;
+        }
+        Some(self.attach(position, device, path))
+    }
+
+    // Opens the receiver if it isn't connected, then every paired device
+    // that has no handle
+    fn connect_receiver(&mut self, position: usize) -> Vec<HandleId> {
+        let managed = &self.devices[position];
+        if managed.receiver.is_none() {
+            if managed.path.is_some() && self.is_open(managed.path.as_deref(), position) {
+                crate::tracing::debug!("{} is already open", managed);
+                return vec![];
+            }
+            let opened = match &managed.path {
+                Some(path) => Receiver::open_path(path),
+                None => Receiver::open(managed.vendor_id, managed.product_id),
+            };
+            let mut receiver = match opened {
+                Ok(receiver) => receiver,
+                Err(err) => {
+                    crate::tracing::trace!("{} not available: {}", managed, err);
+                    return vec![];
+                }
+            };
+            let path = receiver.device().hid_info().ok().and_then(|info| info.path);
+            if managed.path.is_none() && self.is_open(path.as_deref(), position) {
+                crate::tracing::debug!("{} is already open", managed);
+                return vec![];
+            }
+            let managed = &mut self.devices[position];
+            managed.path = managed.path.take().or(path);
+            crate::tracing::info!("Connected receiver {}", managed);
+            managed.receiver = Some(receiver);
+        }
+
+        let managed = &mut self.devices[position];
+        let (receiver_id, vendor_id, product_id) =
+            (managed.id, managed.vendor_id, managed.product_id);
+        let path = managed.path.clone();
+        let Some(receiver) = managed.receiver.as_mut() else {
+            return vec![];
+        };
+        let slots = match receiver.paired_slots() {
+            Ok(slots) => slots,
+            Err(err) => {
+                crate::tracing::debug!("Failed to list the slots of {}: {}", managed, err);
+                return vec![];
+            }
+        };
+
+        let mut connected = vec![];
+        for slot in slots {
+            let existing = self
+                .devices
+                .iter()
+                .position(|m| m.slot == Some((receiver_id, slot)));
+            let slot_position = match existing {
+                Some(slot_position) if self.devices[slot_position].is_connected() => continue,
+                Some(slot_position) => slot_position,
+                None => {
+                    let mut managed = ManagedDevice::new(self.next_id(), vendor_id, product_id);
+                    managed.slot = Some((receiver_id, slot));
+                    self.devices.push(managed);
+                    self.devices.len() - 1
+                }
+            };
+            let opened = self.devices[position]
+                .receiver
+                .as_ref()
+                .map(|receiver| receiver.device_in(slot));
+            match opened {
+                Some(Ok(device)) => {
+                    connected.push(self.attach(slot_position, device, path.clone()));
+                }
+                Some(Err(err)) => {
+                    crate::tracing::trace!("{} not available: {}", self.devices[slot_position], err)
+                }
+                None => {}
+            }
+        }
+        connected
+    }
+
+    // Hands the just opened `device` to the entry at `position`
+    fn attach(&mut self, position: usize, mut device: Device, path: Option<CString>) -> HandleId {
+        let info = device.hid_info().ok();
+        let unit_id = device
+            .unit_id()
+            .ok()
+            .filter(|id| *id != [0; 4] && *id != [0xFF; 4]);
+        // hidapi has no interface number for Bluetooth nodes
+        let route = match info {
+            _ if device.is_cable_mode() => Route::Cable,
+            Some(info) if info.interface_number < 0 => Route::Bluetooth,
+            _ => Route::Receiver,
+        };
+        if route == Route::Receiver {
+            self.forget_on_other_receivers(unit_id, position);
+        }
+
+        let managed = &mut self.devices[position];
+        managed.path = managed.path.take().or(path);
+        managed.unit_id = unit_id;
+        managed.route = route;
+        crate::tracing::info!("Connected {}", managed);
+        managed.device = Some(device);
+        managed.id
+    }
+
+    // Enumerates the bus and manages every HID++ device that showed up since
+    // the last call, connecting it, and disconnects the ones that went away.
+    // For platforms without hotplug notifications, call it periodically.
+    pub fn rescan(&mut self) -> anyhow::Result<ScanDiff> {
+        let candidates = discovery::candidates()?;
+        let present = candidates
+            .iter()
+            .map(|c| c.path.clone())
+            .collect::<BTreeSet<_>>();
+
+        let mut diff = ScanDiff::default();
+        for managed in &mut self.devices {
+            let gone = managed
+                .path
+                .as_ref()
+                .is_some_and(|path| self.present.contains(path) && !present.contains(path));
+            if gone {
+                diff.removed.push(managed.id);
+                if managed.device.take().is_some() | managed.receiver.take().is_some() {
+                    crate::tracing::info!("Disconnected {}", managed);
+                }
+            }
+        }
+        let mut added = BTreeSet::new();
+        for candidate in &candidates {
+            if self.present.contains(&candidate.path) {
+                continue;
+            }
+            crate::tracing::debug!(
+                "Found {:04x}:{:04x} {}, HID++ on interface {} of {:?}",
+                candidate.vendor_id,
+                candidate.product_id,
+                candidate.product,
+                candidate.interface_number,
+                candidate.interfaces
+            );
+            self.add_path((candidate.vendor_id, candidate.product_id), &candidate.path);
+            added.insert(&candidate.path);
+        }
+        if !added.is_empty() {
+            self.connect();
+            diff.added = self
+                .devices
+                .iter()
+                .filter(|m| m.path.as_ref().is_some_and(|path| added.contains(path)))
+                .map(|m| m.id)
+                .collect();
+        }
+
+        self.present = present;
+        Ok(diff)
+    }
+
+    // Manages the device at `path`, reusing an entry with its ids that isn't
+    // tied to another node
+    fn add_path(&mut self, (vendor_id, product_id): (u16, u16), path: &CString) {
+        let existing = self.devices.iter_mut().find(|m| {
+            m.vendor_id == vendor_id
+                && m.product_id == product_id
+                && m.slot.is_none()
+                && (m.path.is_none() || m.path.as_ref() == Some(path))
+        });
+        match existing {
+            Some(managed) => managed.path = Some(path.clone()),
+            None => {
+                let mut managed = ManagedDevice::new(self.next_id(), vendor_id, product_id);
+                managed.path = Some(path.clone());
+                self.devices.push(managed);
+            }
+        }
+    }
+
+    // Drops the handle of a device that stopped responding, `connect` will
+    // try to open it again. Disconnecting a receiver drops the handles of the
+    // devices paired to it.
+    pub fn disconnect(&mut self, id: HandleId) {
+        for managed in &mut self.devices {
+            let behind = managed.slot.is_some_and(|(receiver, _)| receiver == id);
+            if (managed.id == id || behind)
+                && (managed.device.take().is_some() | managed.receiver.take().is_some())
+            {
+                crate::tracing::info!("Disconnected {}", managed);
+            }
+        }
+    }
+
+    // The handle of a connected device, or of the receiver itself for a
+    // receiver
+    pub fn get_mut(&mut self, id: HandleId) -> Option<&mut Device> {
+        let position = self.position(id)?;
+        let managed = &mut self.devices[position];
+        match &mut managed.receiver {
+            Some(receiver) => Some(receiver.device()),
+            None => managed.device.as_mut(),
+        }
+    }
+
+    // One handle per physical unit: when a unit is connected over several
+    // routes only the selected one is returned, so it isn't polled or
+    // configured twice. Receivers are left out, the devices paired to them
+    // are listed instead.
+    pub fn connected(&mut self) -> impl Iterator<Item = (HandleId, &mut Device)> {
+        let active = (0..self.devices.len())
+            .map(|position| self.is_active(position))
+            .collect::<Vec<_>>();
+        self.devices
+            .iter_mut()
+            .zip(active)
+            .filter(|(_, active)| *active)
+            .filter_map(|(managed, _)| {
+                let id = managed.id;
+                managed.device.as_mut().map(|device| (id, device))
+            })
+    }
+
+    // Connected devices grouped by unit id, in the order they were added
+    pub fn logical_devices(&self) -> Vec<LogicalDevice> {
+        let mut logical: Vec<LogicalDevice> = vec![];
+        for (position, managed) in self.devices.iter().enumerate() {
+            if managed.device.is_none() {
+                continue;
+            }
+            let route = (managed.id, managed.route);
+            let existing = managed
+                .unit_id
+                .and_then(|id| logical.iter_mut().find(|l| l.unit_id == Some(id)));
+            match existing {
+                Some(existing) if self.is_active(position) => existing.routes.insert(0, route),
+                Some(existing) => existing.routes.push(route),
+                None => logical.push(LogicalDevice {
+                    unit_id: managed.unit_id,
+                    routes: vec![route],
+                }),
+            }
+        }
+        logical
+    }
+
+    // Picks the route `connected` uses for a unit reachable over several.
+    // When that route goes away the other one takes over.
+    pub fn select_route(&mut self, unit_id: [u8; 4], route: Route) {
+        self.selected.insert(unit_id, route);
+    }
+
+    // Reads the battery of every connected device, one thread per device so
+    // a slow or unresponsive one doesn't hold up the others. Results are in
+    // the order devices were added, once per unit like `connected`.
+    pub fn poll_batteries(&mut self) -> Vec<(HandleId, anyhow::Result<BatteryInfo>)> {
+        thread::scope(|scope| {
+            let handles = self
+                .connected()
+                .map(|(id, device)| (id, scope.spawn(move || device.get_battery())))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|(id, handle)| {
+                    let result = handle
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("Battery poll panicked")));
+                    (id, result)
+                })
+                .collect()
+        })
+    }
+
+    pub fn is_connected(&self, id: HandleId) -> bool {
+        self.position(id)
+            .is_some_and(|position| self.devices[position].is_connected())
+    }
+
+    // Whether the device at `position` is connected and is the handle used
+    // for its unit: the one on the selected route, else on the preferred one
+    fn is_active(&self, position: usize) -> bool {
+        let managed = &self.devices[position];
+        if managed.device.is_none() {
+            return false;
+        }
+        let Some(unit_id) = managed.unit_id else {
+            return true;
+        };
+        let selected = self.selected.get(&unit_id).copied();
+        let same_unit = self
+            .devices
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.device.is_some() && m.unit_id == Some(unit_id));
+        let active = same_unit
+            .clone()
+            .find(|(_, m)| Some(m.route) == selected)
+            .or_else(|| same_unit.min_by_key(|(_, m)| m.route))
+            .map_or(position, |(active, _)| active);
+        active == position
+    }
+
+    // Whether a handle other than the one at `position` has `path` open.
+    // Devices behind a receiver go through the receiver's handle.
+    fn is_open(&self, path: Option<&CStr>, position: usize) -> bool {
+        path.is_some_and(|path| {
+            self.devices.iter().enumerate().any(|(other, m)| {
+                other != position
+                    && m.slot.is_none()
+                    && m.is_connected()
+                    && m.path.as_deref() == Some(path)
+            })
+        })
+    }
+
+    // A device is linked to one receiver at a time, even when it's paired to
+    // several: once it connects through another one, the handle on the
+    // receiver it left only times out
+    fn forget_on_other_receivers(&mut self, unit_id: Option<[u8; 4]>, position: usize) {
+        let Some(unit_id) = unit_id else {
+            return;
+        };
+        for (other, managed) in self.devices.iter_mut().enumerate() {
+            if other != position
+                && managed.route == Route::Receiver
+                && managed.unit_id == Some(unit_id)
+                && managed.device.take().is_some()
+            {
+                crate::tracing::info!("{:02x?} moved away from {}", unit_id, managed);
+            }
+        }
+    }
+
+    fn position(&self, id: HandleId) -> Option<usize> {
+        self.devices.iter().position(|m| m.id == id)
+    }
+
+    fn next_id(&mut self) -> HandleId {
+        self.next_id += 1;
+        HandleId(self.next_id)
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{sim::Simulator, Clock, ReportId, Transport};
+
+#[derive(Default)]
+struct MockState {
+    // (request prefix, replies), checked in the order they were added
+    canned: Vec<(Vec<u8>, Vec<Vec<u8>>)>,
+    simulator: Option<Simulator>,
+    // reports waiting to be read
+    pending: VecDeque<Vec<u8>>,
+    written: Vec<Vec<u8>>,
+    report_lengths: Option<Vec<ReportId>>,
+    // reads that time out wait on it, None returns right away
+    clock: Option<Arc<dyn Clock>>,
+}
+
+// A transport answering from canned replies instead of hardware, for testing
+// protocol logic. Requests are matched against the canned ones by prefix, so
+// a test only has to spell out the bytes it cares about, then handed to the
+// simulator if there is one. Requests nothing answers time out.
+//
+//   let mock = MockTransport::new();
+//   mock.expect(&[0x10, 0x01, 0x00, 0x01, 0x10, 0x00], &[...]);
+//   let mut device = Device::builder().transport(mock.clone()).open()?;
+//
+// Clones share their state, keep one to push events or look at what the
+// device sent after handing the other to the device.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Answers what the canned replies don't with a simulated mouse
+    pub fn simulated(simulator: Simulator) -> Self {
+        let mock = Self::new();
+        mock.state().simulator = Some(simulator);
+        mock
+    }
+
+    // Replies with `reply` to every request starting with `request`
+    pub fn expect(&self, request: &[u8], reply: &[u8]) -> &Self {
+        self.expect_many(request, vec![reply.to_vec()])
+    }
+
+    // Replies with several reports, e.g. notifications sent before the reply
+    pub fn expect_many(&self, request: &[u8], replies: Vec<Vec<u8>>) -> &Self {
+        self.state().canned.push((request.to_vec(), replies));
+        self
+    }
+
+    // Queues an unsolicited report, read before any later reply
+    pub fn push_event(&self, report: &[u8]) -> &Self {
+        self.state().pending.push_back(report.to_vec());
+        self
+    }
+
+    // Limits the reports the transport claims to carry
+    pub fn set_report_lengths(&self, report_lengths: Vec<ReportId>) -> &Self {
+        self.state().report_lengths = Some(report_lengths);
+        self
+    }
+
+    // Lets reads that find nothing take their whole timeout on `clock`, so
+    // deadlines measured on a `ManualClock` come around
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) -> &Self {
+        self.state().clock = Some(clock);
+        self
+    }
+
+    // Every report the device wrote, oldest first
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        self.state().written.clone()
+    }
+
+    // Runs `f` on the simulator, e.g. to change the battery between reads
+    pub fn with_simulator<T>(&self, f: impl FnOnce(&mut Simulator) -> T) -> Option<T> {
+        self.state().simulator.as_mut().map(f)
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Transport for MockTransport {
+    fn write_report(&mut self, report: &[u8]) -> anyhow::Result<()> {
+        let mut state = self.state();
+        state.written.push(report.to_vec());
+        let canned = state
+            .canned
+            .iter()
+            .find(|(request, _)| report.starts_with(request))
+            .map(|(_, replies)| replies.clone());
+        let replies = match canned {
+            Some(replies) => replies,
+            None => state
+                .simulator
+                .as_mut()
+                .and_then(|simulator| simulator.handle(report))
+                .into_iter()
+                .collect(),
+        };
+        state.pending.extend(replies);
+        Ok(())
+    }
+
+    // an empty queue is a timeout, waited out on the clock if there is one
+    fn read_report(&mut self, buf: &mut [u8], timeout: Duration) -> anyhow::Result<usize> {
+        let mut state = self.state();
+        let Some(report) = state.pending.pop_front() else {
+            let clock = state.clock.clone();
+            drop(state);
+            if let Some(clock) = clock {
+                clock.sleep(timeout);
+            }
+            return Ok(0);
+        };
+        let len = report.len().min(buf.len());
+        buf[..len].copy_from_slice(&report[..len]);
+        Ok(len)
+    }
+
+    fn report_lengths(&self) -> Vec<ReportId> {
+        self.state()
+            .report_lengths
+            .clone()
+            .unwrap_or_else(|| vec![ReportId::Short, ReportId::Long, ReportId::VeryLong])
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{BatteryInfo, BatteryStatus, CancellationToken, ChargingAlert, Device, Event};
+
+type ThresholdCallback = Box<dyn FnMut(u8, &BatteryInfo) + Send>;
+type ChangeCallback = Box<dyn FnMut(&BatteryInfo) + Send>;
+type ChargingCallback = Box<dyn FnMut(ChargingAlert, &BatteryInfo) + Send>;
+
+struct ThresholdAlert {
+    // sorted from highest to lowest
+    thresholds: Vec<u8>,
+    // whether each threshold may still fire, cleared when it fires and set
+    // again once the battery is back above it
+    armed: Vec<bool>,
+    callback: ThresholdCallback,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatteryReading {
+    pub at: Instant,
+    pub battery: BatteryInfo,
+}
+
+// Watches a device's battery, combining battery events with polling at a
+// fixed interval when the device stays quiet.
+pub struct BatteryMonitor {
+    interval: Duration,
+    alerts: Vec<ThresholdAlert>,
+    on_change: Vec<ChangeCallback>,
+    on_charging_alert: Vec<ChargingCallback>,
+    last: Option<BatteryInfo>,
+    history: VecDeque<BatteryReading>,
+    history_capacity: usize,
+    skip_while_asleep: bool,
+    // the receiver reported the link down, the device is asleep or off
+    asleep: bool,
+}
+
+impl BatteryMonitor {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            alerts: vec![],
+            on_change: vec![],
+            on_charging_alert: vec![],
+            last: None,
+            history: VecDeque::new(),
+            history_capacity: 256,
+            skip_while_asleep: false,
+            asleep: false,
+        }
+    }
+
+    // Number of readings kept by `history()`, the oldest are dropped first
+    pub fn history_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.history_capacity = capacity;
+        while self.history.len() > capacity {
+            self.history.pop_front();
+        }
+        self
+    }
+
+    // Every reading seen, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &BatteryReading> {
+        self.history.iter()
+    }
+
+    // Calls `callback` with the threshold and the reading once every time the
+    // battery drops to or below one of the thresholds while discharging.
+    pub fn on_threshold(
+        &mut self,
+        mut thresholds: Vec<u8>,
+        callback: impl FnMut(u8, &BatteryInfo) + Send + 'static,
+    ) -> &mut Self {
+        thresholds.sort_unstable_by(|a, b| b.cmp(a));
+        thresholds.dedup();
+        self.alerts.push(ThresholdAlert {
+            armed: vec![true; thresholds.len()],
+            thresholds,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    // Calls `callback` whenever a reading differs from the previous one
+    pub fn on_change(&mut self, callback: impl FnMut(&BatteryInfo) + Send + 'static) -> &mut Self {
+        self.on_change.push(Box::new(callback));
+        self
+    }
+
+    // Calls `callback` when the battery starts charging slowly or stops
+    // charging because of its temperature, once per transition
+    pub fn on_charging_alert(
+        &mut self,
+        callback: impl FnMut(ChargingAlert, &BatteryInfo) + Send + 'static,
+    ) -> &mut Self {
+        self.on_charging_alert.push(Box::new(callback));
+        self
+    }
+
+    // Skips polls while the receiver reports the device's link down, since
+    // polling wakes its radio, and polls once it reconnects instead
+    pub fn skip_while_asleep(&mut self, skip: bool) -> &mut Self {
+        self.skip_while_asleep = skip;
+        self
+    }
+
+    pub fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+
+    pub fn last(&self) -> Option<&BatteryInfo> {
+        self.last.as_ref()
+    }
+
+    // Feeds a reading obtained elsewhere, e.g. from `Device::next_event`
+    pub fn update(&mut self, battery: BatteryInfo) {
+        let charging = !matches!(battery.status, BatteryStatus::Discharging);
+        for alert in &mut self.alerts {
+            for (threshold, armed) in alert.thresholds.iter().zip(alert.armed.iter_mut()) {
+                if battery.percentage > *threshold {
+                    *armed = true;
+                } else if *armed && !charging {
+                    *armed = false;
+                    (alert.callback)(*threshold, &battery);
+                }
+            }
+        }
+        let alert = match battery.status {
+            BatteryStatus::SlowRecharge => Some(ChargingAlert::SlowCharge),
+            BatteryStatus::ThermalError => Some(ChargingAlert::Thermal),
+            _ => None,
+        };
+        if let Some(alert) = alert {
+            if self.last.as_ref().map(|last| &last.status) != Some(&battery.status) {
+                for callback in &mut self.on_charging_alert {
+                    callback(alert, &battery);
+                }
+            }
+        }
+        if self.last.as_ref() != Some(&battery) {
+            for callback in &mut self.on_change {
+                callback(&battery);
+            }
+        }
+        if self.history_capacity > 0 {
+            if self.history.len() == self.history_capacity {
+                self.history.pop_front();
+            }
+            self.history.push_back(BatteryReading {
+                at: Instant::now(),
+                battery: battery.clone(),
+            });
+        }
+        self.last = Some(battery);
+    }
+
+    pub fn poll(&mut self, device: &mut Device) -> anyhow::Result<BatteryInfo> {
+        let battery = device.get_battery()?;
+        self.update(battery.clone());
+        Ok(battery)
+    }
+
+    // Monitors the device until an error occurs
+    pub fn run(&mut self, device: &mut Device) -> anyhow::Result<()> {
+        self.run_until(device, &CancellationToken::new())
+    }
+
+    // Monitors the device until an error occurs or `cancel` is cancelled
+    pub fn run_until(
+        &mut self,
+        device: &mut Device,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let clock = device.clock();
+        self.poll(device)?;
+        let mut last_poll = clock.now();
+        // wait in short slices so cancellation doesn't take a whole interval
+        let slice = Duration::from_millis(100);
+        while !cancel.is_cancelled() {
+            let remaining = self
+                .interval
+                .saturating_sub(clock.now().saturating_duration_since(last_poll));
+            match device.next_event(remaining.min(slice))? {
+                Some(Event::Battery(battery) | Event::ChargingAlert { battery, .. }) => {
+                    self.update(battery);
+                    last_poll = clock.now();
+                }
+                Some(event @ (Event::Link { .. } | Event::PoweredOn)) => {
+                    let awake = !matches!(
+                        event,
+                        Event::Link {
+                            established: false,
+                            ..
+                        }
+                    );
+                    let woke_up = self.asleep && awake;
+                    self.asleep = !awake;
+                    if woke_up && self.skip_while_asleep {
+                        self.poll(device)?;
+                        last_poll = clock.now();
+                    }
+                }
+                _ => {}
+            }
+            // a device busy sending other events is polled all the same
+            if clock.now().saturating_duration_since(last_poll) >= self.interval {
+                if !(self.skip_while_asleep && self.asleep) {
+                    self.poll(device)?;
+                }
+                last_poll = clock.now();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::bail;
+
+use crate::{strings::StringAssembler, Device, Feature, Function};
+
+// DeviceNameType (0x0005) device types
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum DeviceType {
+    Keyboard,
+    RemoteControl,
+    Numpad,
+    Mouse,
+    Trackpad,
+    Trackball,
+    Presenter,
+    Receiver,
+    Headset,
+    Webcam,
+    Other(u8),
+}
+
+impl From<u8> for DeviceType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => DeviceType::Keyboard,
+            1 => DeviceType::RemoteControl,
+            2 => DeviceType::Numpad,
+            3 => DeviceType::Mouse,
+            4 => DeviceType::Trackpad,
+            5 => DeviceType::Trackball,
+            6 => DeviceType::Presenter,
+            7 => DeviceType::Receiver,
+            8 => DeviceType::Headset,
+            9 => DeviceType::Webcam,
+            other => DeviceType::Other(other),
+        }
+    }
+}
+
+impl Device {
+    // The best human readable name available: the user-set friendly name,
+    // then the model name, then the USB product string
+    pub fn product_name(&mut self) -> anyhow::Result<String> {
+        if self.feature_index(Feature::DeviceFriendlyName).is_ok() {
+            match self.friendly_name() {
+                Ok(name) if !name.is_empty() => return Ok(name),
+                Ok(_) => {}
+                Err(err) => crate::tracing::debug!("Failed to read friendly name: {}", err),
+            }
+        }
+        if self.feature_index(Feature::DeviceNameType).is_ok() {
+            match self.device_name() {
+                Ok(name) if !name.is_empty() => return Ok(name),
+                Ok(_) => {}
+                Err(err) => crate::tracing::debug!("Failed to read device name: {}", err),
+            }
+        }
+        match self.hid_info()?.product {
+            Some(product) if !product.is_empty() => Ok(product),
+            _ => bail!("Device has no name"),
+        }
+    }
+
+    // DeviceNameType (0x0005) model name, e.g. "MX Master 3"
+    pub fn device_name(&mut self) -> anyhow::Result<String> {
+        let result =
+            self.send_feature(Feature::DeviceNameType, Function::DeviceNameGetCount, &[])?;
+        let mut name = StringAssembler::new(result.data[0] as usize);
+        while !name.is_complete() {
+            let result = self.send_feature(
+                Feature::DeviceNameType,
+                Function::DeviceNameGetName,
+                &[name.offset() as u8],
+            )?;
+            if !name.push(&result.data, 0) {
+                break;
+            }
+        }
+        Ok(name.finish())
+    }
+
+    pub fn device_type(&mut self) -> anyhow::Result<DeviceType> {
+        let result =
+            self.send_feature(Feature::DeviceNameType, Function::DeviceNameGetType, &[])?;
+        Ok(DeviceType::from(result.data[0]))
+    }
+
+    // DeviceFriendlyName (0x0007) name, which users can change
+    pub fn friendly_name(&mut self) -> anyhow::Result<String> {
+        let result = self.send_feature(
+            Feature::DeviceFriendlyName,
+            Function::FriendlyNameGetLen,
+            &[],
+        )?;
+        let mut name = StringAssembler::new(result.data[0] as usize);
+        while !name.is_complete() {
+            let result = self.send_feature(
+                Feature::DeviceFriendlyName,
+                Function::FriendlyNameGetName,
+                &[name.offset() as u8],
+            )?;
+            // replies echo the byte index before the characters
+            if !name.push(&result.data, 1) {
+                break;
+            }
+        }
+        Ok(name.finish())
+    }
+}
@@ -0,0 +1,105 @@
+use notify_rust::{Notification, Urgency};
+
+use crate::{BatteryInfo, BatteryStatus, ChargingAlert, Event};
+
+const APP_NAME: &str = "hidpp";
+
+pub fn notify_battery(device_name: &str, battery: &BatteryInfo) -> anyhow::Result<()> {
+    let (summary, icon, urgency) = match battery.status {
+        BatteryStatus::Full => ("Battery full", "battery-full-charged", Urgency::Low),
+        BatteryStatus::Recharging | BatteryStatus::AlmostFull | BatteryStatus::SlowRecharge => {
+            ("Charging", "battery-good-charging", Urgency::Low)
+        }
+        BatteryStatus::WirelessCharging => {
+            ("Charging wirelessly", "battery-good-charging", Urgency::Low)
+        }
+        BatteryStatus::InvalidBattery | BatteryStatus::ThermalError => {
+            ("Battery error", "battery-missing", Urgency::Critical)
+        }
+        BatteryStatus::Discharging if battery.percentage <= 5 => {
+            ("Battery critical", "battery-empty", Urgency::Critical)
+        }
+        BatteryStatus::Discharging if battery.percentage <= 20 => {
+            ("Battery low", "battery-caution", Urgency::Normal)
+        }
+        BatteryStatus::Discharging => ("Battery", "battery-good", Urgency::Low),
+    };
+
+    let percentage = if battery.estimated {
+        format!("~{}%", battery.percentage)
+    } else {
+        format!("{}%", battery.percentage)
+    };
+    Notification::new()
+        .appname(APP_NAME)
+        .summary(&format!("{}: {}", device_name, summary))
+        .body(&format!("{} ({:?})", percentage, battery.status))
+        .icon(icon)
+        .urgency(urgency)
+        .show()?;
+    Ok(())
+}
+
+pub fn notify_charging_alert(
+    device_name: &str,
+    alert: ChargingAlert,
+    battery: &BatteryInfo,
+) -> anyhow::Result<()> {
+    let (summary, body) = match alert {
+        ChargingAlert::SlowCharge => ("Charging slowly", "Check the cable and charger"),
+        ChargingAlert::Thermal => (
+            "Charging stopped",
+            "The battery is too hot or too cold to charge",
+        ),
+    };
+    let body = match battery.voltage {
+        Some(voltage) => format!("{} ({}%, {} mV)", body, battery.percentage, voltage),
+        None => format!("{} ({}%)", body, battery.percentage),
+    };
+    Notification::new()
+        .appname(APP_NAME)
+        .summary(&format!("{}: {}", device_name, summary))
+        .body(&body)
+        .icon("battery-caution-charging")
+        .urgency(Urgency::Critical)
+        .show()?;
+    Ok(())
+}
+
+pub fn notify_connection(device_name: &str, connected: bool) -> anyhow::Result<()> {
+    let summary = if connected {
+        format!("{} connected", device_name)
+    } else {
+        format!("{} disconnected", device_name)
+    };
+    Notification::new()
+        .appname(APP_NAME)
+        .summary(&summary)
+        .icon("input-mouse")
+        .urgency(Urgency::Low)
+        .show()?;
+    Ok(())
+}
+
+// Shows a notification for the events worth surfacing to the user, others
+// are ignored
+pub fn notify_event(device_name: &str, event: &Event) -> anyhow::Result<()> {
+    match event {
+        Event::Battery(battery) => notify_battery(device_name, battery),
+        Event::ChargingAlert { alert, battery } => {
+            notify_charging_alert(device_name, *alert, battery)
+        }
+        _ => Ok(()),
+    }
+}
+
+// Callback for `BatteryMonitor::on_threshold` showing a notification for
+// every crossed threshold
+pub fn threshold_notifier(device_name: impl Into<String>) -> impl FnMut(u8, &BatteryInfo) + Send {
+    let device_name = device_name.into();
+    move |_, battery| {
+        if let Err(err) = notify_battery(&device_name, battery) {
+            crate::tracing::warn!("Failed to show notification: {}", err);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::bail;
+
+use crate::{Device, Feature, Function};
+
+// Operating systems a MultiPlatform (0x4531) device can be configured for
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum HostOs {
+    Tizen,
+    Windows,
+    // Windows Embedded
+    WinEmb,
+    Android,
+    Linux,
+    Chrome,
+    Ios,
+    MacOs,
+}
+
+impl HostOs {
+    pub fn current() -> Option<HostOs> {
+        if cfg!(target_os = "windows") {
+            Some(HostOs::Windows)
+        } else if cfg!(target_os = "macos") {
+            Some(HostOs::MacOs)
+        } else if cfg!(target_os = "ios") {
+            Some(HostOs::Ios)
+        } else if cfg!(target_os = "android") {
+            Some(HostOs::Android)
+        } else if cfg!(target_os = "linux") {
+            let chrome_os = std::fs::read_to_string("/etc/lsb-release")
+                .map(|release| release.contains("CHROMEOS_RELEASE"))
+                .unwrap_or(false);
+            Some(if chrome_os {
+                HostOs::Chrome
+            } else {
+                HostOs::Linux
+            })
+        } else {
+            None
+        }
+    }
+
+    // bit in a platform descriptor's OS mask
+    fn mask(&self) -> u16 {
+        match self {
+            HostOs::Tizen => 0x0001,
+            HostOs::Windows => 0x0100,
+            HostOs::WinEmb => 0x0200,
+            HostOs::Linux => 0x0400,
+            HostOs::Chrome => 0x0800,
+            HostOs::Android => 0x1000,
+            HostOs::MacOs => 0x2000,
+            HostOs::Ios => 0x4000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PlatformDescriptor {
+    pub platform: u8,
+    pub os_mask: u16,
+}
+
+impl PlatformDescriptor {
+    pub fn supports(&self, os: HostOs) -> bool {
+        self.os_mask & os.mask() != 0
+    }
+}
+
+// What to configure on a keyboard when it connects to a host
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HostDefaults {
+    pub os: HostOs,
+    // Some(true) makes F1-F12 trigger their special function without Fn,
+    // None leaves the device setting alone
+    pub fn_swap: Option<bool>,
+}
+
+impl HostDefaults {
+    // mimics Logi Options: media keys by default on Apple hosts, standard
+    // function keys left as configured elsewhere
+    pub fn for_os(os: HostOs) -> Self {
+        let fn_swap = match os {
+            HostOs::MacOs | HostOs::Ios => Some(true),
+            _ => None,
+        };
+        Self { os, fn_swap }
+    }
+
+    pub fn current() -> Option<Self> {
+        HostOs::current().map(HostDefaults::for_os)
+    }
+}
+
+impl Device {
+    pub fn get_platform_descriptors(&mut self) -> anyhow::Result<Vec<PlatformDescriptor>> {
+        let infos = self.send_feature(
+            Feature::MultiPlatform,
+            Function::MultiPlatformGetFeatureInfos,
+            &[],
+        )?;
+        let count = infos.data[2];
+
+        let mut descriptors = vec![];
+        for index in 0..count {
+            let result = self.send_feature(
+                Feature::MultiPlatform,
+                Function::MultiPlatformGetPlatformDescriptor,
+                &[index],
+            )?;
+            descriptors.push(PlatformDescriptor {
+                platform: result.data[0],
+                os_mask: u16::from_be_bytes([result.data[2], result.data[3]]),
+            });
+        }
+        Ok(descriptors)
+    }
+
+    // Platform (descriptor index) of the host the device is connected to
+    pub fn get_host_platform(&mut self) -> anyhow::Result<u8> {
+        let result = self.send_feature(
+            Feature::MultiPlatform,
+            Function::MultiPlatformGetHostPlatform,
+            &[0xFF],
+        )?;
+        Ok(result.data[2])
+    }
+
+    // Sets the platform of the host the device is currently connected to
+    pub fn set_host_platform(&mut self, platform: u8) -> anyhow::Result<()> {
+        let infos = self.send_feature(
+            Feature::MultiPlatform,
+            Function::MultiPlatformGetFeatureInfos,
+            &[],
+        )?;
+        if infos.data[0] & 0x02 == 0 {
+            bail!("Device does not allow setting the platform");
+        }
+
+        // host index 0xFF is the current host
+        self.send_feature(
+            Feature::MultiPlatform,
+            Function::MultiPlatformSetHostPlatform,
+            &[0xFF, platform],
+        )?;
+        Ok(())
+    }
+
+    // Keyboards implement one of three Fn inversion features
+    fn fn_inversion_feature(&mut self) -> anyhow::Result<Feature> {
+        for feature in [
+            Feature::NewFnInversion,
+            Feature::FnInversion,
+            Feature::K375sFnInversion,
+        ] {
+            if self.feature_index(feature.clone()).is_ok() {
+                return Ok(feature);
+            }
+        }
+        bail!("Device does not support Fn inversion")
+    }
+
+    pub fn get_fn_swap(&mut self) -> anyhow::Result<bool> {
+        let feature = self.fn_inversion_feature()?;
+        let result = match feature {
+            // the K375s variant takes a host index, 0xFF for the current one
+            Feature::K375sFnInversion => {
+                let result = self.send_feature(feature, Function::FnInversionGet, &[0xFF])?;
+                result.data[1]
+            }
+            _ => {
+                self.send_feature(feature, Function::FnInversionGet, &[])?
+                    .data[0]
+            }
+        };
+        Ok(result & 0x01 != 0)
+    }
+
+    pub fn set_fn_swap(&mut self, enabled: bool) -> anyhow::Result<()> {
+        let feature = self.fn_inversion_feature()?;
+        let payload = match feature {
+            Feature::K375sFnInversion => vec![0xFF, enabled as u8],
+            _ => vec![enabled as u8],
+        };
+        self.send_feature(feature, Function::FnInversionSet, &payload)?;
+        Ok(())
+    }
+
+    // Configures the platform and Fn behavior for the given host, skipping
+    // whatever the device doesn't support
+    pub fn apply_host_defaults(&mut self, defaults: &HostDefaults) -> anyhow::Result<()> {
+        if self.feature_index(Feature::MultiPlatform).is_ok() {
+            let descriptors = self.get_platform_descriptors()?;
+            match descriptors.iter().find(|d| d.supports(defaults.os)) {
+                Some(descriptor) => {
+                    crate::tracing::debug!(
+                        "Setting platform {} for {:?}",
+                        descriptor.platform,
+                        defaults.os
+                    );
+                    if let Err(err) = self.set_host_platform(descriptor.platform) {
+                        crate::tracing::debug!("Skipping platform: {}", err);
+                    }
+                }
+                None => crate::tracing::debug!("No platform descriptor for {:?}", defaults.os),
+            }
+        }
+
+        if let Some(fn_swap) = defaults.fn_swap {
+            if self.fn_inversion_feature().is_ok() {
+                self.set_fn_swap(fn_swap)?;
+            }
+        }
+
+        Ok(())
+    }
+}
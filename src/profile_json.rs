@@ -0,0 +1,284 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::{ButtonAction, ButtonFunction, ProfileConfig};
+
+// An onboard profile as JSON, in this crate's own schema, to keep profiles
+// in files and edit them by hand. Only what maps onto a profile sector is
+// kept: polling rate, DPI levels, the color and button assignments. It's not
+// the format G HUB or Logitech Gaming Software export.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonProfile {
+    #[serde(default)]
+    pub name: String,
+    // reports per second
+    pub polling_rate: u16,
+    pub dpi_levels: Vec<JsonDpiLevel>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<JsonColor>,
+    #[serde(default)]
+    pub assignments: Vec<JsonAssignment>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonDpiLevel {
+    pub dpi: u16,
+    #[serde(default)]
+    pub default: bool,
+    // used while the DPI shift button is held
+    #[serde(default)]
+    pub shift: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct JsonColor {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonAssignment {
+    // "g1" for the first button
+    pub slot_id: String,
+    // part of the G-Shift layer
+    #[serde(default)]
+    pub g_shift: bool,
+    pub action: JsonAction,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum JsonAction {
+    Disabled,
+    // 1 for the left button
+    Mouse { button: u8 },
+    Keystroke { key: u8, modifiers: u8 },
+    Media { usage: u16 },
+    Function { function: JsonFunction },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum JsonFunction {
+    TiltLeft,
+    TiltRight,
+    DpiUp,
+    DpiDown,
+    DpiCycle,
+    DpiDefault,
+    DpiShift,
+    ProfileNext,
+    ProfilePrevious,
+    ProfileCycle,
+    GShift,
+    BatteryStatus,
+}
+
+impl JsonProfile {
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    // Overwrites the settings this format carries, keeping the rest of the
+    // profile (lighting effects, timeouts) as it is
+    pub fn apply_to(&self, profile: &mut ProfileConfig) -> anyhow::Result<()> {
+        if self.polling_rate == 0 {
+            bail!("Invalid polling rate: 0");
+        }
+        profile.report_rate = (1000 / self.polling_rate).clamp(1, u8::MAX as u16) as u8;
+
+        // the shift level isn't part of the DPI cycle on the device
+        let levels = self
+            .dpi_levels
+            .iter()
+            .filter(|level| !level.shift)
+            .collect::<Vec<_>>();
+        if levels.is_empty() {
+            bail!("The profile has no DPI levels");
+        }
+        let mut dpi_slots = levels.iter().map(|level| level.dpi).collect::<Vec<_>>();
+        let default_slot = levels.iter().position(|level| level.default).unwrap_or(0);
+        // the shift DPI reuses a slot with the same value, or gets its own
+        let shift_slot = self
+            .dpi_levels
+            .iter()
+            .find(|level| level.shift)
+            .map(
+                |shift| match dpi_slots.iter().position(|dpi| *dpi == shift.dpi) {
+                    Some(slot) => slot,
+                    None => {
+                        dpi_slots.push(shift.dpi);
+                        dpi_slots.len() - 1
+                    }
+                },
+            );
+        profile.dpi_slots = dpi_slots;
+        profile.default_dpi_slot = default_slot as u8;
+        profile.shift_dpi_slot = shift_slot.map(|slot| slot as u8);
+
+        if let Some(color) = self.color {
+            profile.color = [color.red, color.green, color.blue];
+        }
+
+        for assignment in &self.assignments {
+            let Some(slot) = assignment
+                .slot_id
+                .strip_prefix('g')
+                .and_then(|n| n.parse::<usize>().ok())
+                .and_then(|n| n.checked_sub(1))
+            else {
+                bail!("Invalid slot id: {}", assignment.slot_id);
+            };
+            let buttons = if assignment.g_shift {
+                &mut profile.gshift_buttons
+            } else {
+                &mut profile.buttons
+            };
+            if slot >= buttons.len() {
+                bail!("The profile has no button {}", assignment.slot_id);
+            }
+            buttons[slot] = ButtonAction::try_from(assignment.action)?;
+        }
+
+        if !self.name.is_empty() {
+            profile.name = self.name.clone();
+        }
+        // catches anything the device can't store, e.g. too many DPI slots
+        profile.to_bytes()?;
+        Ok(())
+    }
+}
+
+impl From<&ProfileConfig> for JsonProfile {
+    fn from(profile: &ProfileConfig) -> Self {
+        let mut dpi_levels = profile
+            .dpi_slots
+            .iter()
+            .enumerate()
+            .map(|(slot, dpi)| JsonDpiLevel {
+                dpi: *dpi,
+                default: slot == profile.default_dpi_slot as usize,
+                shift: false,
+            })
+            .collect::<Vec<_>>();
+        if let Some(shift) = profile.shift_dpi_slot {
+            if let Some(dpi) = profile.dpi_slots.get(shift as usize) {
+                dpi_levels.push(JsonDpiLevel {
+                    dpi: *dpi,
+                    default: false,
+                    shift: true,
+                });
+            }
+        }
+
+        let mut assignments = vec![];
+        for (g_shift, buttons) in [(false, &profile.buttons), (true, &profile.gshift_buttons)] {
+            for (slot, action) in buttons.iter().enumerate() {
+                match JsonAction::try_from(*action) {
+                    Ok(action) => assignments.push(JsonAssignment {
+                        slot_id: format!("g{}", slot + 1),
+                        g_shift,
+                        action,
+                    }),
+                    Err(err) => {
+                        crate::tracing::debug!("Not exporting button {}: {}", slot + 1, err)
+                    }
+                }
+            }
+        }
+
+        let [red, green, blue] = profile.color;
+        JsonProfile {
+            name: profile.name.clone(),
+            polling_rate: 1000 / profile.report_rate.max(1) as u16,
+            dpi_levels,
+            color: Some(JsonColor { red, green, blue }),
+            assignments,
+        }
+    }
+}
+
+impl TryFrom<ButtonAction> for JsonAction {
+    type Error = anyhow::Error;
+
+    fn try_from(action: ButtonAction) -> anyhow::Result<Self> {
+        Ok(match action {
+            ButtonAction::Disabled => JsonAction::Disabled,
+            ButtonAction::Mouse(buttons) if buttons.count_ones() == 1 => JsonAction::Mouse {
+                button: buttons.trailing_zeros() as u8 + 1,
+            },
+            ButtonAction::Key { modifiers, key } => JsonAction::Keystroke { key, modifiers },
+            ButtonAction::Consumer(usage) => JsonAction::Media { usage },
+            ButtonAction::Function(function) => JsonAction::Function {
+                function: JsonFunction::try_from(function)?,
+            },
+            action => bail!("{:?} can't be written as JSON", action),
+        })
+    }
+}
+
+impl TryFrom<JsonAction> for ButtonAction {
+    type Error = anyhow::Error;
+
+    fn try_from(action: JsonAction) -> anyhow::Result<Self> {
+        Ok(match action {
+            JsonAction::Disabled => ButtonAction::Disabled,
+            JsonAction::Mouse { button } if (1..=16).contains(&button) => {
+                ButtonAction::Mouse(1 << (button - 1))
+            }
+            JsonAction::Mouse { button } => bail!("Invalid mouse button: {}", button),
+            JsonAction::Keystroke { key, modifiers } => ButtonAction::Key { modifiers, key },
+            JsonAction::Media { usage } => ButtonAction::Consumer(usage),
+            JsonAction::Function { function } => ButtonAction::Function(function.into()),
+        })
+    }
+}
+
+impl TryFrom<ButtonFunction> for JsonFunction {
+    type Error = anyhow::Error;
+
+    fn try_from(function: ButtonFunction) -> anyhow::Result<Self> {
+        Ok(match function {
+            ButtonFunction::TiltLeft => JsonFunction::TiltLeft,
+            ButtonFunction::TiltRight => JsonFunction::TiltRight,
+            ButtonFunction::NextDpi => JsonFunction::DpiUp,
+            ButtonFunction::PreviousDpi => JsonFunction::DpiDown,
+            ButtonFunction::CycleDpi => JsonFunction::DpiCycle,
+            ButtonFunction::DefaultDpi => JsonFunction::DpiDefault,
+            ButtonFunction::ShiftDpi => JsonFunction::DpiShift,
+            ButtonFunction::NextProfile => JsonFunction::ProfileNext,
+            ButtonFunction::PreviousProfile => JsonFunction::ProfilePrevious,
+            ButtonFunction::CycleProfile => JsonFunction::ProfileCycle,
+            ButtonFunction::GShift => JsonFunction::GShift,
+            ButtonFunction::BatteryStatus => JsonFunction::BatteryStatus,
+            ButtonFunction::Other(value) => bail!("Unknown button function 0x{:02X}", value),
+        })
+    }
+}
+
+impl From<JsonFunction> for ButtonFunction {
+    fn from(function: JsonFunction) -> Self {
+        match function {
+            JsonFunction::TiltLeft => ButtonFunction::TiltLeft,
+            JsonFunction::TiltRight => ButtonFunction::TiltRight,
+            JsonFunction::DpiUp => ButtonFunction::NextDpi,
+            JsonFunction::DpiDown => ButtonFunction::PreviousDpi,
+            JsonFunction::DpiCycle => ButtonFunction::CycleDpi,
+            JsonFunction::DpiDefault => ButtonFunction::DefaultDpi,
+            JsonFunction::DpiShift => ButtonFunction::ShiftDpi,
+            JsonFunction::ProfileNext => ButtonFunction::NextProfile,
+            JsonFunction::ProfilePrevious => ButtonFunction::PreviousProfile,
+            JsonFunction::ProfileCycle => ButtonFunction::CycleProfile,
+            JsonFunction::GShift => ButtonFunction::GShift,
+            JsonFunction::BatteryStatus => ButtonFunction::BatteryStatus,
+        }
+    }
+}
@@ -0,0 +1,825 @@
+use std::fmt;
+
+use anyhow::bail;
+
+use crate::{CancellationToken, Device, Dpi, Feature, Function};
+
+// bytes transferred by each memoryRead/memoryWrite
+const CHUNK_LEN: usize = 16;
+// sector 0 lists the writable profiles, ROM profiles start at 0x0100
+const DIRECTORY_SECTOR: u16 = 0x0000;
+const ROM_SECTOR: u16 = 0x0100;
+const DPI_SLOTS: usize = 5;
+// a shift DPI slot of 0xFF means the profile has none
+const NO_SLOT: u8 = 0xFF;
+// button actions, 4 bytes each: the normal layer and the G-Shift layer
+const BUTTONS_AT: usize = 32;
+const GSHIFT_BUTTONS_AT: usize = 96;
+const BUTTON_SLOTS: usize = 16;
+// two LED effects of 11 bytes each follow the name: the primary zone, then
+// the logo
+const LIGHTING_AT: usize = 208;
+const LED_ZONES: usize = 2;
+const LED_EFFECT_LEN: usize = 11;
+const LED_STATIC: u8 = 0x01;
+
+// OnboardProfiles (0x8100) getInfo reply
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct ProfilesInfo {
+    pub memory_model: u8,
+    pub profile_format: u8,
+    pub macro_format: u8,
+    pub profile_count: u8,
+    pub profile_count_oob: u8,
+    pub button_count: u8,
+    pub sector_count: u16,
+    pub sector_size: u16,
+    // bits 0-1: G-Shift support, bits 2-3: DPI shift support
+    pub mechanical_layout: u8,
+    pub various_info: u8,
+}
+
+impl ProfilesInfo {
+    pub fn has_gshift(&self) -> bool {
+        self.mechanical_layout & 0x03 == 0x02
+    }
+
+    pub fn has_dpi_shift(&self) -> bool {
+        self.mechanical_layout & 0x0C == 0x08
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum OnboardMode {
+    // the device uses the active profile stored in its memory
+    Onboard,
+    // settings are driven by software on the host
+    Host,
+}
+
+impl TryFrom<u8> for OnboardMode {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> anyhow::Result<Self> {
+        match value {
+            0x01 => Ok(OnboardMode::Onboard),
+            0x02 => Ok(OnboardMode::Host),
+            _ => bail!("Unknown onboard mode: {}", value),
+        }
+    }
+}
+
+impl OnboardMode {
+    fn value(&self) -> u8 {
+        match self {
+            OnboardMode::Onboard => 0x01,
+            OnboardMode::Host => 0x02,
+        }
+    }
+}
+
+// Functions a profile button can perform on the device itself
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ButtonFunction {
+    TiltLeft,
+    TiltRight,
+    NextDpi,
+    PreviousDpi,
+    CycleDpi,
+    DefaultDpi,
+    // DPI shift (sniper), uses the profile's shift DPI slot while held
+    ShiftDpi,
+    NextProfile,
+    PreviousProfile,
+    CycleProfile,
+    // switches the other buttons to the G-Shift layer while held
+    GShift,
+    BatteryStatus,
+    Other(u8),
+}
+
+impl From<u8> for ButtonFunction {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => ButtonFunction::TiltLeft,
+            0x02 => ButtonFunction::TiltRight,
+            0x03 => ButtonFunction::NextDpi,
+            0x04 => ButtonFunction::PreviousDpi,
+            0x05 => ButtonFunction::CycleDpi,
+            0x06 => ButtonFunction::DefaultDpi,
+            0x07 => ButtonFunction::ShiftDpi,
+            0x08 => ButtonFunction::NextProfile,
+            0x09 => ButtonFunction::PreviousProfile,
+            0x0A => ButtonFunction::CycleProfile,
+            0x0B => ButtonFunction::GShift,
+            0x0C => ButtonFunction::BatteryStatus,
+            value => ButtonFunction::Other(value),
+        }
+    }
+}
+
+impl ButtonFunction {
+    fn value(&self) -> u8 {
+        match self {
+            ButtonFunction::TiltLeft => 0x01,
+            ButtonFunction::TiltRight => 0x02,
+            ButtonFunction::NextDpi => 0x03,
+            ButtonFunction::PreviousDpi => 0x04,
+            ButtonFunction::CycleDpi => 0x05,
+            ButtonFunction::DefaultDpi => 0x06,
+            ButtonFunction::ShiftDpi => 0x07,
+            ButtonFunction::NextProfile => 0x08,
+            ButtonFunction::PreviousProfile => 0x09,
+            ButtonFunction::CycleProfile => 0x0A,
+            ButtonFunction::GShift => 0x0B,
+            ButtonFunction::BatteryStatus => 0x0C,
+            ButtonFunction::Other(value) => *value,
+        }
+    }
+}
+
+// What a button does in a profile
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ButtonAction {
+    // not set, the device falls back to the button's default
+    Unassigned,
+    Disabled,
+    // bitmask of mouse buttons, 0x0001 is the left button
+    Mouse(u16),
+    // HID keyboard usage with modifier bits (0x01 left ctrl, 0x02 left shift...)
+    Key { modifiers: u8, key: u8 },
+    // HID consumer control usage, e.g. 0x00E9 volume up
+    Consumer(u16),
+    Function(ButtonFunction),
+    // runs the macro stored at this sector and offset
+    Macro { sector: u16, offset: u16 },
+    Raw([u8; 4]),
+}
+
+impl From<[u8; 4]> for ButtonAction {
+    fn from(bytes: [u8; 4]) -> Self {
+        match bytes {
+            [0xFF, 0xFF, 0xFF, 0xFF] => ButtonAction::Unassigned,
+            [0x80, 0x00, _, _] => ButtonAction::Disabled,
+            [0x80, 0x01, high, low] => ButtonAction::Mouse(u16::from_be_bytes([high, low])),
+            [0x80, 0x02, modifiers, key] => ButtonAction::Key { modifiers, key },
+            [0x80, 0x03, high, low] => ButtonAction::Consumer(u16::from_be_bytes([high, low])),
+            [0x90, function, _, _] => ButtonAction::Function(ButtonFunction::from(function)),
+            [high, low, offset_high, offset_low] if high >> 4 == 0 => ButtonAction::Macro {
+                sector: u16::from_be_bytes([high, low]),
+                offset: u16::from_be_bytes([offset_high, offset_low]),
+            },
+            bytes => ButtonAction::Raw(bytes),
+        }
+    }
+}
+
+impl ButtonAction {
+    fn to_bytes(self) -> [u8; 4] {
+        match self {
+            ButtonAction::Unassigned => [0xFF; 4],
+            ButtonAction::Disabled => [0x80, 0x00, 0x00, 0x00],
+            ButtonAction::Mouse(buttons) => {
+                let [high, low] = buttons.to_be_bytes();
+                [0x80, 0x01, high, low]
+            }
+            ButtonAction::Key { modifiers, key } => [0x80, 0x02, modifiers, key],
+            ButtonAction::Consumer(usage) => {
+                let [high, low] = usage.to_be_bytes();
+                [0x80, 0x03, high, low]
+            }
+            ButtonAction::Function(function) => [0x90, function.value(), 0x00, 0x00],
+            ButtonAction::Macro { sector, offset } => {
+                let [high, low] = (sector & 0x0FFF).to_be_bytes();
+                let [offset_high, offset_low] = offset.to_be_bytes();
+                [high, low, offset_high, offset_low]
+            }
+            ButtonAction::Raw(bytes) => bytes,
+        }
+    }
+}
+
+fn decode_buttons(data: &[u8]) -> Vec<ButtonAction> {
+    data.chunks_exact(4)
+        .take(BUTTON_SLOTS)
+        .map(|chunk| ButtonAction::from([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct ProfileEntry {
+    pub sector: u16,
+    pub enabled: bool,
+}
+
+// Parts of a profile `ProfileConfig::diff` tells apart
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ProfileField {
+    ReportRate,
+    DpiSlots,
+    DefaultDpiSlot,
+    ShiftDpiSlot,
+    Color,
+    PowerMode,
+    AngleSnap,
+    Timeouts,
+    Buttons,
+    GShiftButtons,
+    Name,
+    // bytes this crate doesn't model, e.g. lighting
+    Other,
+}
+
+// A profile sector as stored in the device. Fields not modeled here are kept
+// as read and written back untouched.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ProfileConfig {
+    // milliseconds between reports
+    pub report_rate: u8,
+    // DPI values the DPI button cycles through, at most 5
+    pub dpi_slots: Vec<u16>,
+    pub default_dpi_slot: u8,
+    // slot used while the DPI shift (sniper) button is held
+    pub shift_dpi_slot: Option<u8>,
+    pub color: [u8; 3],
+    pub power_mode: u8,
+    pub angle_snap: bool,
+    // incremented by the host on every write
+    pub write_count: u16,
+    // power save and power off timeouts
+    pub ps_timeout: u16,
+    pub po_timeout: u16,
+    // one action per button, in the device's button order
+    pub buttons: Vec<ButtonAction>,
+    // actions used instead of `buttons` while a G-Shift button is held
+    pub gshift_buttons: Vec<ButtonAction>,
+    pub name: String,
+    raw: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for ProfileConfig {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> anyhow::Result<Self> {
+        // fields end at 208, followed by lighting and the CRC
+        if data.len() < 210 {
+            bail!("Profile sector too short: {} bytes", data.len());
+        }
+
+        let dpi_slots = data[3..3 + DPI_SLOTS * 2]
+            .chunks_exact(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .take_while(|dpi| *dpi != 0 && *dpi != 0xFFFF)
+            .collect();
+        // the name is up to 24 UTF-16LE code units, zero or 0xFFFF terminated
+        let name = data[160..208]
+            .chunks_exact(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .take_while(|unit| *unit != 0 && *unit != 0xFFFF)
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            report_rate: data[0],
+            default_dpi_slot: data[1],
+            shift_dpi_slot: (data[2] != NO_SLOT).then_some(data[2]),
+            dpi_slots,
+            color: [data[13], data[14], data[15]],
+            power_mode: data[16],
+            angle_snap: data[17] != 0,
+            write_count: u16::from_le_bytes([data[18], data[19]]),
+            ps_timeout: u16::from_le_bytes([data[28], data[29]]),
+            po_timeout: u16::from_le_bytes([data[30], data[31]]),
+            buttons: decode_buttons(&data[BUTTONS_AT..GSHIFT_BUTTONS_AT]),
+            gshift_buttons: decode_buttons(&data[GSHIFT_BUTTONS_AT..160]),
+            name: String::from_utf16_lossy(&name),
+            raw: data.to_vec(),
+        })
+    }
+}
+
+impl ProfileConfig {
+    // Replaces the DPI slots, keeping the default and shift slots in range
+    pub fn set_dpi_slots(&mut self, slots: &[Dpi]) -> anyhow::Result<()> {
+        if slots.is_empty() || slots.len() > DPI_SLOTS {
+            bail!(
+                "A profile has 1 to {} DPI slots, got {}",
+                DPI_SLOTS,
+                slots.len()
+            );
+        }
+        self.dpi_slots = slots.iter().map(Dpi::value).collect();
+        let last = slots.len() as u8 - 1;
+        self.default_dpi_slot = self.default_dpi_slot.min(last);
+        self.shift_dpi_slot = self.shift_dpi_slot.map(|slot| slot.min(last));
+        Ok(())
+    }
+
+    // Fields that differ between the two profiles. The write counter isn't
+    // compared since every write bumps it.
+    pub fn diff(&self, other: &ProfileConfig) -> Vec<ProfileField> {
+        let mut fields = vec![];
+        let mut check = |changed: bool, field: ProfileField| {
+            if changed {
+                fields.push(field);
+            }
+        };
+        check(
+            self.report_rate != other.report_rate,
+            ProfileField::ReportRate,
+        );
+        check(self.dpi_slots != other.dpi_slots, ProfileField::DpiSlots);
+        check(
+            self.default_dpi_slot != other.default_dpi_slot,
+            ProfileField::DefaultDpiSlot,
+        );
+        check(
+            self.shift_dpi_slot != other.shift_dpi_slot,
+            ProfileField::ShiftDpiSlot,
+        );
+        check(self.color != other.color, ProfileField::Color);
+        check(self.power_mode != other.power_mode, ProfileField::PowerMode);
+        check(self.angle_snap != other.angle_snap, ProfileField::AngleSnap);
+        check(
+            (self.ps_timeout, self.po_timeout) != (other.ps_timeout, other.po_timeout),
+            ProfileField::Timeouts,
+        );
+        check(self.buttons != other.buttons, ProfileField::Buttons);
+        check(
+            self.gshift_buttons != other.gshift_buttons,
+            ProfileField::GShiftButtons,
+        );
+        check(self.name != other.name, ProfileField::Name);
+        // bytes between the modeled fields and before the CRC
+        let unmodeled = |raw: &[u8]| [raw[20..28].to_vec(), raw[208..raw.len() - 2].to_vec()];
+        check(
+            unmodeled(&self.raw) != unmodeled(&other.raw),
+            ProfileField::Other,
+        );
+        fields
+    }
+
+    // Encodes the profile into a sector, CRC included
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        if self.dpi_slots.is_empty() || self.dpi_slots.len() > DPI_SLOTS {
+            bail!("Invalid number of DPI slots: {}", self.dpi_slots.len());
+        }
+        let slot_count = self.dpi_slots.len() as u8;
+        if self.default_dpi_slot >= slot_count {
+            bail!("Default DPI slot {} out of range", self.default_dpi_slot);
+        }
+        if self.shift_dpi_slot.is_some_and(|slot| slot >= slot_count) {
+            bail!("Shift DPI slot {:?} out of range", self.shift_dpi_slot);
+        }
+        if self.buttons.len() > BUTTON_SLOTS || self.gshift_buttons.len() > BUTTON_SLOTS {
+            bail!("A profile has at most {} buttons per layer", BUTTON_SLOTS);
+        }
+        let name = self.name.encode_utf16().collect::<Vec<_>>();
+        if name.len() > 24 {
+            bail!("Profile name too long: {}", self.name);
+        }
+
+        let mut data = self.raw.clone();
+        data[0] = self.report_rate;
+        data[1] = self.default_dpi_slot;
+        data[2] = self.shift_dpi_slot.unwrap_or(NO_SLOT);
+        for slot in 0..DPI_SLOTS {
+            let dpi = self.dpi_slots.get(slot).copied().unwrap_or(0);
+            data[3 + slot * 2..5 + slot * 2].copy_from_slice(&dpi.to_le_bytes());
+        }
+        data[13..16].copy_from_slice(&self.color);
+        data[16] = self.power_mode;
+        data[17] = self.angle_snap as u8;
+        data[18..20].copy_from_slice(&self.write_count.to_le_bytes());
+        data[28..30].copy_from_slice(&self.ps_timeout.to_le_bytes());
+        data[30..32].copy_from_slice(&self.po_timeout.to_le_bytes());
+        for (at, buttons) in [
+            (BUTTONS_AT, &self.buttons),
+            (GSHIFT_BUTTONS_AT, &self.gshift_buttons),
+        ] {
+            for slot in 0..BUTTON_SLOTS {
+                let action = buttons
+                    .get(slot)
+                    .copied()
+                    .unwrap_or(ButtonAction::Unassigned);
+                data[at + slot * 4..at + slot * 4 + 4].copy_from_slice(&action.to_bytes());
+            }
+        }
+        data[160..208].fill(0xFF);
+        for (i, unit) in name.iter().enumerate() {
+            data[160 + i * 2..162 + i * 2].copy_from_slice(&unit.to_le_bytes());
+        }
+
+        let crc_at = data.len() - 2;
+        let crc = sector_crc(&data[..crc_at]);
+        data[crc_at..].copy_from_slice(&crc.to_be_bytes());
+        Ok(data)
+    }
+}
+
+// Builds a profile from scratch for a device, rather than editing one read
+// back from it. The defaults mirror what factory profiles use: the first
+// buttons click, the next ones change DPI and profile, 1ms reports.
+//
+//   let profile = device.profile_template()?.name("Work").build()?;
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ProfileTemplate {
+    sector_size: usize,
+    button_count: u8,
+    led_zones: u8,
+    report_rate: u8,
+    dpi_slots: Vec<u16>,
+    default_dpi_slot: u8,
+    color: [u8; 3],
+    name: String,
+}
+
+impl ProfileTemplate {
+    pub fn new(info: &ProfilesInfo) -> Self {
+        Self {
+            sector_size: info.sector_size as usize,
+            button_count: info.button_count,
+            led_zones: 0,
+            report_rate: 1,
+            dpi_slots: vec![800, 1600, 3200],
+            default_dpi_slot: 1,
+            color: [0xFF, 0xFF, 0xFF],
+            name: String::new(),
+        }
+    }
+
+    // Number of LED zones lit with `color`, at most 2, the others are off
+    pub fn led_zones(mut self, zones: u8) -> Self {
+        self.led_zones = zones;
+        self
+    }
+
+    pub fn report_rate(mut self, milliseconds: u8) -> Self {
+        self.report_rate = milliseconds;
+        self
+    }
+
+    pub fn dpi_slots(mut self, slots: &[Dpi], default_slot: u8) -> Self {
+        self.dpi_slots = slots.iter().map(Dpi::value).collect();
+        self.default_dpi_slot = default_slot;
+        self
+    }
+
+    pub fn color(mut self, color: [u8; 3]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<ProfileConfig> {
+        let lighting_end = LIGHTING_AT + LED_ZONES * LED_EFFECT_LEN;
+        if self.sector_size < lighting_end + 2 {
+            bail!("Sector size {} too small for a profile", self.sector_size);
+        }
+        if self.led_zones as usize > LED_ZONES {
+            bail!("A profile has at most {} LED zones", LED_ZONES);
+        }
+
+        let mut data = vec![0xFF; self.sector_size];
+        for zone in 0..LED_ZONES {
+            let at = LIGHTING_AT + zone * LED_EFFECT_LEN;
+            let effect = &mut data[at..at + LED_EFFECT_LEN];
+            // an all zero effect is off
+            effect.fill(0x00);
+            if zone < self.led_zones as usize {
+                effect[0] = LED_STATIC;
+                effect[1..4].copy_from_slice(&self.color);
+            }
+        }
+        let mut profile = ProfileConfig::try_from(data.as_slice())?;
+
+        let defaults = [
+            ButtonAction::Mouse(0x0001),
+            ButtonAction::Mouse(0x0002),
+            ButtonAction::Mouse(0x0004),
+            ButtonAction::Mouse(0x0008),
+            ButtonAction::Mouse(0x0010),
+            ButtonAction::Function(ButtonFunction::NextDpi),
+            ButtonAction::Function(ButtonFunction::PreviousDpi),
+            ButtonAction::Function(ButtonFunction::CycleProfile),
+        ];
+        let button_count = (self.button_count as usize).min(BUTTON_SLOTS);
+        profile.buttons = (0..button_count)
+            .map(|button| {
+                defaults
+                    .get(button)
+                    .copied()
+                    .unwrap_or(ButtonAction::Unassigned)
+            })
+            .collect();
+        profile.gshift_buttons = vec![ButtonAction::Unassigned; button_count];
+        profile.report_rate = self.report_rate;
+        profile.dpi_slots = self.dpi_slots;
+        profile.default_dpi_slot = self.default_dpi_slot;
+        profile.shift_dpi_slot = None;
+        profile.color = self.color;
+        profile.power_mode = 0;
+        profile.angle_snap = false;
+        profile.write_count = 0;
+        // one minute to power save, never off
+        profile.ps_timeout = 60_000;
+        profile.po_timeout = 0;
+        profile.name = self.name;
+
+        // validates the fields and fills in the CRC
+        let data = profile.to_bytes()?;
+        ProfileConfig::try_from(data.as_slice())
+    }
+}
+
+// A sector whose stored CRC doesn't match its contents, e.g. one that was
+// never written or a write that was interrupted
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct CorruptProfile {
+    pub sector: u16,
+    pub stored: u16,
+    pub computed: u16,
+}
+
+impl fmt::Display for CorruptProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Sector 0x{:04X} is corrupt: CRC 0x{:04X}, expected 0x{:04X}",
+            self.sector, self.stored, self.computed
+        )
+    }
+}
+
+impl std::error::Error for CorruptProfile {}
+
+// Checks the CRC in the last two bytes of a sector read from the device
+pub fn verify_sector(sector: u16, data: &[u8]) -> Result<(), CorruptProfile> {
+    let crc_at = data.len().saturating_sub(2);
+    let stored = match &data[crc_at..] {
+        [high, low] => u16::from_be_bytes([*high, *low]),
+        _ => 0,
+    };
+    let computed = sector_crc(&data[..crc_at]);
+    if stored != computed {
+        return Err(CorruptProfile {
+            sector,
+            stored,
+            computed,
+        });
+    }
+    Ok(())
+}
+
+// CRC-16/CCITT-FALSE of a sector's contents, stored big endian in its last
+// two bytes
+pub fn sector_crc(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, byte| {
+        let mut crc = crc ^ (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+impl Device {
+    pub fn profiles_info(&mut self) -> anyhow::Result<ProfilesInfo> {
+        let result = self.send_feature(
+            Feature::OnboardProfiles,
+            Function::OnboardProfilesGetInfo,
+            &[],
+        )?;
+        let data = &result.data;
+        Ok(ProfilesInfo {
+            memory_model: data[0],
+            profile_format: data[1],
+            macro_format: data[2],
+            profile_count: data[3],
+            profile_count_oob: data[4],
+            button_count: data[5],
+            sector_count: u16::from_be_bytes([data[6], data[7]]),
+            sector_size: u16::from_be_bytes([data[8], data[9]]),
+            mechanical_layout: data[10],
+            various_info: data[11],
+        })
+    }
+
+    pub fn onboard_mode(&mut self) -> anyhow::Result<OnboardMode> {
+        let result = self.send_feature(
+            Feature::OnboardProfiles,
+            Function::OnboardProfilesGetMode,
+            &[],
+        )?;
+        OnboardMode::try_from(result.data[0])
+    }
+
+    pub fn set_onboard_mode(&mut self, mode: OnboardMode) -> anyhow::Result<()> {
+        self.send_feature(
+            Feature::OnboardProfiles,
+            Function::OnboardProfilesSetMode,
+            &[mode.value()],
+        )?;
+        Ok(())
+    }
+
+    // Sector of the active profile
+    pub fn current_profile(&mut self) -> anyhow::Result<u16> {
+        let result = self.send_feature(
+            Feature::OnboardProfiles,
+            Function::OnboardProfilesGetCurrentProfile,
+            &[],
+        )?;
+        Ok(u16::from_be_bytes([result.data[0], result.data[1]]))
+    }
+
+    pub fn set_current_profile(&mut self, sector: u16) -> anyhow::Result<()> {
+        self.send_feature(
+            Feature::OnboardProfiles,
+            Function::OnboardProfilesSetCurrentProfile,
+            &sector.to_be_bytes(),
+        )?;
+        Ok(())
+    }
+
+    // Writable profiles listed in the directory, ROM profiles excluded
+    pub fn profile_directory(&mut self) -> anyhow::Result<Vec<ProfileEntry>> {
+        let info = self.profiles_info()?;
+        let data = self.read_sector(DIRECTORY_SECTOR)?;
+        verify_sector(DIRECTORY_SECTOR, &data)?;
+        Ok(data
+            .chunks_exact(4)
+            .take(info.profile_count as usize)
+            .map(|entry| ProfileEntry {
+                sector: u16::from_be_bytes([entry[0], entry[1]]),
+                enabled: entry[2] != 0,
+            })
+            .take_while(|entry| entry.sector != 0xFFFF)
+            .collect())
+    }
+
+    // Reads a whole sector. Reads can't go past the end of the sector, so the
+    // last chunk is read from `sector_size - 16` and trimmed.
+    pub fn read_sector(&mut self, sector: u16) -> anyhow::Result<Vec<u8>> {
+        let size = self.profiles_info()?.sector_size as usize;
+        let mut data = Vec::with_capacity(size);
+        while data.len() < size {
+            let offset = data.len().min(size.saturating_sub(CHUNK_LEN));
+            let mut payload = sector.to_be_bytes().to_vec();
+            payload.extend_from_slice(&(offset as u16).to_be_bytes());
+            let result = self.send_feature(
+                Feature::OnboardProfiles,
+                Function::OnboardProfilesMemoryRead,
+                &payload,
+            )?;
+            let skip = data.len() - offset;
+            let end = result.data.len().min(CHUNK_LEN);
+            data.extend_from_slice(&result.data[skip..end]);
+        }
+        data.truncate(size);
+        Ok(data)
+    }
+
+    pub fn write_sector(&mut self, sector: u16, data: &[u8]) -> anyhow::Result<()> {
+        if sector >= ROM_SECTOR {
+            bail!("Sector 0x{:04X} is read only", sector);
+        }
+
+        let mut payload = sector.to_be_bytes().to_vec();
+        payload.extend_from_slice(&0u16.to_be_bytes());
+        payload.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        self.send_feature(
+            Feature::OnboardProfiles,
+            Function::OnboardProfilesMemoryAddrWrite,
+            &payload,
+        )?;
+        for chunk in data.chunks(CHUNK_LEN) {
+            self.send_feature(
+                Feature::OnboardProfiles,
+                Function::OnboardProfilesMemoryWrite,
+                chunk,
+            )?;
+        }
+        self.send_feature(
+            Feature::OnboardProfiles,
+            Function::OnboardProfilesMemoryWriteEnd,
+            &[],
+        )?;
+        Ok(())
+    }
+
+    pub fn read_profile(&mut self, sector: u16) -> anyhow::Result<ProfileConfig> {
+        let data = self.read_sector(sector)?;
+        verify_sector(sector, &data)?;
+        ProfileConfig::try_from(data.as_slice())
+    }
+
+    // Fails without writing anything once `cancel` is cancelled, a sector is
+    // never left half written
+    pub fn write_profile(
+        &mut self,
+        sector: u16,
+        profile: &ProfileConfig,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        cancel.check()?;
+        let mut profile = profile.clone();
+        profile.write_count = profile.write_count.wrapping_add(1);
+        let data = profile.to_bytes()?;
+        self.write_sector(sector, &data)
+    }
+
+    // Template for a new profile laid out for this device, with DPI slots
+    // the sensor supports
+    pub fn profile_template(&mut self) -> anyhow::Result<ProfileTemplate> {
+        let template = ProfileTemplate::new(&self.profiles_info()?);
+        let Ok(capabilities) = self.dpi_capabilities(0) else {
+            return Ok(template);
+        };
+        let mut slots = [800, 1600, 3200]
+            .iter()
+            .map(|dpi| Dpi::new(*dpi, &capabilities))
+            .collect::<Vec<_>>();
+        slots.dedup();
+        let default_slot = (slots.len() as u8 - 1).min(1);
+        Ok(template.dpi_slots(&slots, default_slot))
+    }
+
+    // Writes the profile only if it differs from what the sector holds,
+    // sparing flash wear and the time a write takes. Returns whether it was
+    // written. Corrupt sectors are always rewritten.
+    pub fn update_profile(
+        &mut self,
+        sector: u16,
+        profile: &ProfileConfig,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<bool> {
+        cancel.check()?;
+        match self.read_profile(sector) {
+            Ok(current) if current.diff(profile).is_empty() => {
+                crate::tracing::debug!("Profile sector 0x{:04X} unchanged", sector);
+                return Ok(false);
+            }
+            Ok(current) => {
+                crate::tracing::debug!(
+                    "Profile sector 0x{:04X} changed: {:?}",
+                    sector,
+                    current.diff(profile)
+                );
+            }
+            Err(err) => match err.downcast_ref::<CorruptProfile>() {
+                Some(corrupt) => {
+                    crate::tracing::debug!("Rewriting profile sector: {}", corrupt)
+                }
+                None => return Err(err),
+            },
+        }
+        self.write_profile(sector, profile, cancel)?;
+        Ok(true)
+    }
+
+    // `update_profile` for several sectors, returns the ones written.
+    // Cancelling stops between sectors, those already written stay written.
+    pub fn update_profiles(
+        &mut self,
+        profiles: &[(u16, ProfileConfig)],
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Vec<u16>> {
+        let mut written = vec![];
+        for (sector, profile) in profiles {
+            if self.update_profile(*sector, profile, cancel)? {
+                written.push(*sector);
+            }
+        }
+        Ok(written)
+    }
+
+    // DPI slot of the active profile in use, only available in onboard mode
+    pub fn current_dpi_slot(&mut self) -> anyhow::Result<u8> {
+        let result = self.send_feature(
+            Feature::OnboardProfiles,
+            Function::OnboardProfilesGetCurrentDpiIndex,
+            &[],
+        )?;
+        Ok(result.data[0])
+    }
+
+    pub fn set_current_dpi_slot(&mut self, slot: u8) -> anyhow::Result<()> {
+        if self.onboard_mode()? != OnboardMode::Onboard {
+            bail!("DPI slots can only be selected in onboard mode");
+        }
+        self.send_feature(
+            Feature::OnboardProfiles,
+            Function::OnboardProfilesSetCurrentDpiIndex,
+            &[slot],
+        )?;
+        Ok(())
+    }
+}
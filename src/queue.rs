@@ -0,0 +1,75 @@
+use std::collections::VecDeque;
+
+use crate::Message;
+
+// What to do with a notification that arrives while the event queue is full
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum OverflowPolicy {
+    // make room by dropping the oldest queued notification
+    #[default]
+    DropOldest,
+    // drop the notification that just arrived
+    DropNewest,
+    // refuse to send new requests until the consumer drains the queue, so
+    // nothing is dropped. Notifications arriving while a reply is awaited
+    // are still queued.
+    Block,
+}
+
+// Notifications read while waiting for replies, kept for `next_event`
+pub(crate) struct EventQueue {
+    events: VecDeque<Message>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    // dropped since the last `take_dropped`
+    dropped: u64,
+}
+
+impl EventQueue {
+    pub(crate) fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            events: VecDeque::new(),
+            capacity: capacity.max(1),
+            policy,
+            dropped: 0,
+        }
+    }
+
+    // Returns false if a notification had to be dropped
+    pub(crate) fn push(&mut self, message: Message) -> bool {
+        if self.events.len() < self.capacity {
+            self.events.push_back(message);
+            return true;
+        }
+        match self.policy {
+            OverflowPolicy::DropOldest => {
+                self.events.pop_front();
+                self.events.push_back(message);
+            }
+            OverflowPolicy::DropNewest => {}
+            OverflowPolicy::Block => {
+                self.events.push_back(message);
+                return true;
+            }
+        }
+        self.dropped += 1;
+        false
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Message> {
+        self.events.pop_front()
+    }
+
+    // Whether new requests should wait for the consumer
+    pub(crate) fn is_blocking(&self) -> bool {
+        self.policy == OverflowPolicy::Block && self.events.len() >= self.capacity
+    }
+
+    pub(crate) fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.events.clear();
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::RwLock;
+
+// Oddities of specific products that the generic HID++ 2.0 code path can't
+// discover on its own. Consulted by `Device::new` and overridable at runtime
+// through `register_quirks`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Quirks {
+    // the device has no short (0x10) report, every request goes out as long
+    pub long_reports_only: bool,
+    // the device never answers very long (0x12) reports
+    pub no_very_long_reports: bool,
+    // the battery can only be read through BatteryVoltage (0x1001)
+    pub battery_voltage_only: bool,
+    // the device answers on index 0xFF rather than 1, like headsets with
+    // their own dongle
+    pub direct_index: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct QuirkKey {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    // product id of the device behind a receiver, None for the receiver or a
+    // directly connected device
+    pub wireless_product_id: Option<u16>,
+}
+
+impl QuirkKey {
+    pub fn new(vendor_id: u16, product_id: u16, wireless_product_id: Option<u16>) -> Self {
+        Self {
+            vendor_id,
+            product_id,
+            wireless_product_id,
+        }
+    }
+}
+
+const LONG_REPORTS_ONLY: Quirks = Quirks {
+    long_reports_only: true,
+    no_very_long_reports: false,
+    battery_voltage_only: false,
+    direct_index: false,
+};
+
+const BATTERY_VOLTAGE_ONLY: Quirks = Quirks {
+    long_reports_only: false,
+    no_very_long_reports: false,
+    battery_voltage_only: true,
+    direct_index: false,
+};
+
+const HEADSET: Quirks = Quirks {
+    long_reports_only: true,
+    no_very_long_reports: true,
+    battery_voltage_only: false,
+    direct_index: true,
+};
+
+static BUILTIN_QUIRKS: &[(QuirkKey, Quirks)] = &[
+    // MX Master 3 over Bluetooth
+    (
+        QuirkKey {
+            vendor_id: 0x046d,
+            product_id: 0xb023,
+            wireless_product_id: None,
+        },
+        LONG_REPORTS_ONLY,
+    ),
+    // MX Keys over Bluetooth
+    (
+        QuirkKey {
+            vendor_id: 0x046d,
+            product_id: 0xb35b,
+            wireless_product_id: None,
+        },
+        LONG_REPORTS_ONLY,
+    ),
+    // G903 behind a Lightspeed receiver
+    (
+        QuirkKey {
+            vendor_id: 0x046d,
+            product_id: 0xc539,
+            wireless_product_id: Some(0x4087),
+        },
+        BATTERY_VOLTAGE_ONLY,
+    ),
+    // G Pro Wireless behind a Lightspeed receiver
+    (
+        QuirkKey {
+            vendor_id: 0x046d,
+            product_id: 0xc539,
+            wireless_product_id: Some(0x4079),
+        },
+        BATTERY_VOLTAGE_ONLY,
+    ),
+    // G933 and G935 headsets, through their dongle
+    (
+        QuirkKey {
+            vendor_id: 0x046d,
+            product_id: 0x0a5b,
+            wireless_product_id: None,
+        },
+        HEADSET,
+    ),
+    (
+        QuirkKey {
+            vendor_id: 0x046d,
+            product_id: 0x0a87,
+            wireless_product_id: None,
+        },
+        HEADSET,
+    ),
+];
+
+static USER_QUIRKS: RwLock<Vec<(QuirkKey, Quirks)>> = RwLock::new(Vec::new());
+
+// Registers quirks for a product, taking precedence over the builtin table
+// and over any earlier registration for the same key.
+pub fn register_quirks(key: QuirkKey, quirks: Quirks) {
+    let mut user_quirks = USER_QUIRKS.write().unwrap();
+    user_quirks.retain(|(k, _)| *k != key);
+    user_quirks.push((key, quirks));
+}
+
+// Quirks of a device on the wireless link: those for its receiver's ids if
+// there are any, else those registered for the wireless product id behind
+// any receiver, which also hold when the device is plugged in with a cable
+pub(crate) fn lookup_wireless_quirks(key: QuirkKey) -> Quirks {
+    let quirks = lookup_quirks(key);
+    if quirks != Quirks::default() {
+        return quirks;
+    }
+    let same_device = |k: &QuirkKey| {
+        k.vendor_id == key.vendor_id && k.wireless_product_id == key.wireless_product_id
+    };
+    if let Some((_, quirks)) = USER_QUIRKS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(k, _)| same_device(k))
+    {
+        return *quirks;
+    }
+    BUILTIN_QUIRKS
+        .iter()
+        .find(|(k, _)| same_device(k))
+        .map(|(_, quirks)| *quirks)
+        .unwrap_or_default()
+}
+
+pub fn lookup_quirks(key: QuirkKey) -> Quirks {
+    if let Some((_, quirks)) = USER_QUIRKS.read().unwrap().iter().find(|(k, _)| *k == key) {
+        return *quirks;
+    }
+
+    BUILTIN_QUIRKS
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, quirks)| *quirks)
+        .unwrap_or_default()
+}
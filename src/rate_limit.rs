@@ -0,0 +1,92 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{Clock, SystemClock};
+
+// how often a request waiting for a free slot checks again
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+// Throttles outgoing requests. Clones share the same state, so a limiter can
+// be handed to several devices talking through the same receiver.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    state: Arc<Mutex<State>>,
+    clock: Arc<dyn Clock>,
+    min_interval: Duration,
+    max_in_flight: usize,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    in_flight: usize,
+    last_request: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(min_interval: Duration, max_in_flight: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State::default())),
+            clock: Arc::new(SystemClock),
+            min_interval,
+            max_in_flight: max_in_flight.max(1),
+        }
+    }
+
+    // Measures intervals and waits on `clock`, e.g. a `ManualClock` in tests
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    // Blocks until a request may be sent, the returned permit keeps the
+    // request in flight until it's dropped.
+    pub fn acquire(&self) -> Permit<'_> {
+        loop {
+            let mut state = self.state.lock().unwrap();
+            let wait = if state.in_flight >= self.max_in_flight {
+                POLL_INTERVAL
+            } else {
+                let elapsed = state
+                    .last_request
+                    .map(|last| self.clock.now().saturating_duration_since(last))
+                    .unwrap_or(self.min_interval);
+                if elapsed >= self.min_interval {
+                    state.in_flight += 1;
+                    state.last_request = Some(self.clock.now());
+                    return Permit { limiter: self };
+                }
+                crate::tracing::trace!("Throttling request for {:?}", self.min_interval - elapsed);
+                self.min_interval - elapsed
+            };
+            // other requests may finish while this one sleeps
+            drop(state);
+            self.clock.sleep(wait);
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(Duration::ZERO, 1)
+    }
+}
+
+pub struct Permit<'a> {
+    limiter: &'a RateLimiter,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.limiter.state.lock().unwrap().in_flight -= 1;
+    }
+}
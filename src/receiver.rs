@@ -0,0 +1,611 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::CStr,
+    fmt,
+    ops::BitOr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::bail;
+
+use crate::{
+    consts::{RECEIVER_INDEX, VERY_LONG_REPORT_LEN},
+    hidpp10,
+    strings::decode_string,
+    Backoff, CancellationToken, Clock, Device, Error, Event, HidInfo, HidapiTransport, ReportId,
+    SystemClock, Transport,
+};
+
+const NOTIFICATION_FLAGS: u8 = 0x00;
+const FIRMWARE_INFO: u8 = 0xF1;
+const RECEIVER_INFO: u8 = 0xB5;
+const DEVICE_ACTIVITY: u8 = 0xB3;
+const RECEIVER_PAIRING: u8 = 0xB2;
+
+const OPEN_LOCK: u8 = 0x01;
+const CLOSE_LOCK: u8 = 0x02;
+// receiver info sub page with the name of the device in slot 1, the next
+// slots follow
+const DEVICE_NAME: u8 = 0x40;
+// receiver info sub pages with what's paired in slot 1, the next slots
+// follow. Bolt receivers keep theirs at 0x50 plus the slot.
+const PAIRING_INFO: u8 = 0x20;
+const BOLT_PAIRING_INFO: u8 = 0x50;
+const BOLT_RECEIVERS: [u16; 1] = [0xC548];
+
+// how long a handle waits on the shared transport before letting the
+// others read
+const SHARED_POLL: Duration = Duration::from_millis(10);
+
+// A Unifying, Lightspeed, Nano or Bolt receiver, which talks HID++ 1.0
+// registers. Devices paired to it are opened as `Device`s with their slot
+// as device index, with `device_in` or `paired_devices`. They share the
+// receiver's handle, so several can be driven at once, from other threads
+// too.
+pub struct Receiver {
+    device: Device,
+    shared: Arc<Mutex<SharedTransport>>,
+}
+
+// The receiver's transport and the reports read from it that are waiting
+// for the handle of their device index. Reports for slots without a handle
+// go to the receiver. A queue holds at most `capacity` reports, the oldest
+// are dropped when a handle isn't read.
+struct SharedTransport {
+    transport: Box<dyn Transport>,
+    queues: HashMap<u8, VecDeque<Vec<u8>>>,
+    capacity: usize,
+    // the handles' clock, read deadlines are measured on it
+    clock: Arc<dyn Clock>,
+}
+
+// One device index's view of the shared transport
+struct SlotTransport {
+    shared: Arc<Mutex<SharedTransport>>,
+    device_index: u8,
+    // index of a request sent elsewhere, e.g. a device asking the receiver
+    // about its slot, whose reply belongs to this handle
+    awaiting: Option<u8>,
+}
+
+impl SlotTransport {
+    fn new(shared: &Arc<Mutex<SharedTransport>>, device_index: u8) -> anyhow::Result<Self> {
+        let mut locked = shared.lock().unwrap();
+        if locked.queues.contains_key(&device_index) {
+            bail!("Device index {} is already open", device_index);
+        }
+        locked.queues.insert(device_index, VecDeque::new());
+        Ok(Self {
+            shared: shared.clone(),
+            device_index,
+            awaiting: None,
+        })
+    }
+}
+
+impl Drop for SlotTransport {
+    fn drop(&mut self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.queues.remove(&self.device_index);
+        }
+    }
+}
+
+impl Transport for SlotTransport {
+    fn write_report(&mut self, report: &[u8]) -> anyhow::Result<()> {
+        self.awaiting = report
+            .get(1)
+            .copied()
+            .filter(|index| *index != self.device_index);
+        self.shared.lock().unwrap().transport.write_report(report)
+    }
+
+    fn read_report(&mut self, buf: &mut [u8], timeout: Duration) -> anyhow::Result<usize> {
+        let clock = self.shared.lock().unwrap().clock.clone();
+        let deadline = clock.now() + timeout;
+        loop {
+            let mut shared = self.shared.lock().unwrap();
+            let queued = shared
+                .queues
+                .get_mut(&self.device_index)
+                .and_then(|queue| queue.pop_front());
+            let report = match queued {
+                Some(report) => report,
+                None => {
+                    let remaining = deadline.saturating_duration_since(clock.now());
+                    let mut report = vec![0; VERY_LONG_REPORT_LEN];
+                    let len = shared
+                        .transport
+                        .read_report(&mut report, remaining.min(SHARED_POLL))?;
+                    report.truncate(len);
+                    report
+                }
+            };
+            if report.len() > 1 {
+                let owner = match report[1] {
+                    index if self.awaiting == Some(index) => self.device_index,
+                    index if shared.queues.contains_key(&index) => index,
+                    _ => RECEIVER_INDEX,
+                };
+                if owner == self.device_index {
+                    if self.awaiting == Some(report[1]) {
+                        self.awaiting = None;
+                    }
+                    let len = report.len().min(buf.len());
+                    buf[..len].copy_from_slice(&report[..len]);
+                    return Ok(len);
+                }
+                let capacity = shared.capacity;
+                if let Some(queue) = shared.queues.get_mut(&owner) {
+                    if queue.len() >= capacity {
+                        queue.pop_front();
+                        crate::tracing::trace!("Dropped a report queued for index {}", owner);
+                    }
+                    queue.push_back(report);
+                }
+            }
+            drop(shared);
+            if clock.now() >= deadline {
+                return Ok(0);
+            }
+        }
+    }
+
+    fn report_lengths(&self) -> Vec<ReportId> {
+        self.shared.lock().unwrap().transport.report_lengths()
+    }
+
+    fn hid_info(&self) -> anyhow::Result<HidInfo> {
+        self.shared.lock().unwrap().transport.hid_info()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ReceiverFirmware {
+    // major and minor versions are BCD encoded
+    pub firmware: (u8, u8),
+    pub build: u16,
+    pub bootloader: (u8, u8),
+    // not every receiver reports one
+    pub serial: Option<String>,
+}
+
+impl fmt::Display for ReceiverFirmware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "firmware {:02X}.{:02X}.B{:04X}, bootloader {:02X}.{:02X}",
+            self.firmware.0, self.firmware.1, self.build, self.bootloader.0, self.bootloader.1
+        )?;
+        if let Some(serial) = &self.serial {
+            write!(f, ", serial {}", serial)?;
+        }
+        Ok(())
+    }
+}
+
+// Receiver register 0x00: which kinds of notifications the receiver passes
+// on to the host. Many receivers start with them all off.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct NotificationFlags(pub u32);
+
+impl NotificationFlags {
+    pub const ROLLER_H: Self = Self(0x200000);
+    pub const BATTERY_STATUS: Self = Self(0x100000);
+    pub const ROLLER_V: Self = Self(0x040000);
+    pub const SOFTWARE_PRESENT: Self = Self(0x000800);
+    pub const LINK_QUALITY: Self = Self(0x000400);
+    pub const WIRELESS: Self = Self(0x000100);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for NotificationFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+// Long register 0xB3: a counter per paired slot, bumped as the receiver gets
+// reports from the device. Counters wrap around, compare two readings taken a
+// moment apart to see which device is in use.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ActivityCounters(pub [u8; 6]);
+
+impl ActivityCounters {
+    // slots are 1-6
+    pub fn get(&self, slot: u8) -> Option<u8> {
+        self.0.get(usize::from(slot).checked_sub(1)?).copied()
+    }
+
+    // Slots whose counter moved since `earlier`, busiest first
+    pub fn active_since(&self, earlier: &ActivityCounters) -> Vec<u8> {
+        let mut active = self
+            .0
+            .iter()
+            .zip(earlier.0.iter())
+            .enumerate()
+            .map(|(index, (now, before))| (index as u8 + 1, now.wrapping_sub(*before)))
+            .filter(|(_, delta)| *delta > 0)
+            .collect::<Vec<_>>();
+        active.sort_by_key(|(_, delta)| std::cmp::Reverse(*delta));
+        active.into_iter().map(|(slot, _)| slot).collect()
+    }
+}
+
+// Kind of device announced in the connection notification
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum DeviceKind {
+    Keyboard,
+    Mouse,
+    Numpad,
+    Presenter,
+    Trackball,
+    Touchpad,
+    Other(u8),
+}
+
+impl From<u8> for DeviceKind {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => DeviceKind::Keyboard,
+            0x02 => DeviceKind::Mouse,
+            0x03 => DeviceKind::Numpad,
+            0x04 => DeviceKind::Presenter,
+            0x08 => DeviceKind::Trackball,
+            0x09 => DeviceKind::Touchpad,
+            other => DeviceKind::Other(other),
+        }
+    }
+}
+
+// Why the receiver closed its pairing lock without pairing a device
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum PairingError {
+    Timeout,
+    UnsupportedDevice,
+    TooManyDevices,
+    SequenceTimeout,
+    // stopped through the cancellation token
+    Cancelled,
+    Other(u8),
+}
+
+impl From<u8> for PairingError {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => PairingError::Timeout,
+            0x02 => PairingError::UnsupportedDevice,
+            0x03 => PairingError::TooManyDevices,
+            0x06 => PairingError::SequenceTimeout,
+            other => PairingError::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for PairingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PairingError::Timeout => write!(f, "no device showed up in time"),
+            PairingError::UnsupportedDevice => write!(f, "the device is not supported"),
+            PairingError::TooManyDevices => write!(f, "every slot is taken"),
+            PairingError::SequenceTimeout => write!(f, "the device stopped responding"),
+            PairingError::Cancelled => write!(f, "cancelled"),
+            PairingError::Other(code) => write!(f, "error 0x{:02X}", code),
+        }
+    }
+}
+
+// Progress reported by `Receiver::pair`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PairingEvent {
+    // the receiver is waiting for a device, tell the user to switch it on
+    LockOpened,
+    Discovered {
+        slot: u8,
+        kind: DeviceKind,
+        wireless_pid: u16,
+        // None if the receiver couldn't tell yet
+        name: Option<String>,
+    },
+    Completed {
+        slot: u8,
+    },
+    Failed(PairingError),
+}
+
+impl Receiver {
+    pub fn open(vendor_id: u16, product_id: u16) -> anyhow::Result<Self> {
+        let transport = HidapiTransport::open(
+            vendor_id,
+            product_id,
+            None,
+            &Backoff::default(),
+            &SystemClock,
+        )?;
+        Self::from_transport(transport, vendor_id, product_id)
+    }
+
+    // Opens the receiver at this HID node, e.g. one `enumerate` found
+    pub fn open_path(path: &CStr) -> anyhow::Result<Self> {
+        let transport = HidapiTransport::open(0, 0, Some(path), &Backoff::default(), &SystemClock)?;
+        let (vendor_id, product_id) = transport.ids()?;
+        Self::from_transport(transport, vendor_id, product_id)
+    }
+
+    // Talks to the receiver through `transport`, e.g. a mock in tests
+    pub fn from_transport(
+        transport: impl Transport + 'static,
+        vendor_id: u16,
+        product_id: u16,
+    ) -> anyhow::Result<Self> {
+        Self::from_transport_with_clock(transport, vendor_id, product_id, Arc::new(SystemClock))
+    }
+
+    // Like `from_transport`, with `clock` for the timeouts of the receiver
+    // and its devices, a `ManualClock` in tests
+    pub fn from_transport_with_clock(
+        transport: impl Transport + 'static,
+        vendor_id: u16,
+        product_id: u16,
+        clock: Arc<dyn Clock>,
+    ) -> anyhow::Result<Self> {
+        let shared = Arc::new(Mutex::new(SharedTransport {
+            transport: Box::new(transport),
+            queues: HashMap::new(),
+            capacity: Device::builder().event_capacity,
+            clock: clock.clone(),
+        }));
+        let device = Device::builder()
+            .transport(SlotTransport::new(&shared, RECEIVER_INDEX)?)
+            .clock(clock)
+            .vid(vendor_id)
+            .pid(product_id)
+            .device_index(RECEIVER_INDEX)
+            .open()?;
+        Ok(Self { device, shared })
+    }
+
+    // Opens the device paired in `slot` (1-6). Only one handle per slot can
+    // be open at a time.
+    pub fn device_in(&self, slot: u8) -> anyhow::Result<Device> {
+        if !(1..=6).contains(&slot) {
+            bail!("Invalid receiver slot {}, slots are 1-6", slot);
+        }
+        let clock = self.shared.lock().unwrap().clock.clone();
+        Device::builder()
+            .transport(SlotTransport::new(&self.shared, slot)?)
+            .clock(clock)
+            .vid(self.device.vendor_id())
+            .pid(self.device.product_id())
+            .device_index(slot)
+            .open()
+    }
+
+    // Slots with a device paired, whether it's switched on or not
+    pub fn paired_slots(&mut self) -> anyhow::Result<Vec<u8>> {
+        let bolt = BOLT_RECEIVERS.contains(&self.device.product_id());
+        let mut slots = vec![];
+        for slot in 1..=6 {
+            let page = match bolt {
+                true => BOLT_PAIRING_INFO + slot,
+                false => PAIRING_INFO + slot - 1,
+            };
+            match self.read_long_register(RECEIVER_INFO, &[page]) {
+                Ok(_) => slots.push(slot),
+                // the receiver rejects the pages of empty slots
+                Err(err) if err.downcast_ref::<Error>().is_some() => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(slots)
+    }
+
+    // A handle on every paired device, as (slot, device)
+    pub fn paired_devices(&mut self) -> anyhow::Result<Vec<(u8, Device)>> {
+        self.paired_slots()?
+            .into_iter()
+            .map(|slot| Ok((slot, self.device_in(slot)?)))
+            .collect()
+    }
+
+    pub fn device(&mut self) -> &mut Device {
+        &mut self.device
+    }
+
+    pub fn into_inner(self) -> Device {
+        self.device
+    }
+
+    // Reads a short register, returning its 3 value bytes
+    pub fn read_register(&mut self, register: u8, params: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.device.read_register(RECEIVER_INDEX, register, params)
+    }
+
+    pub fn write_register(&mut self, register: u8, value: &[u8]) -> anyhow::Result<()> {
+        self.device.write_register(RECEIVER_INDEX, register, value)
+    }
+
+    // Reads a long register, returning its 16 value bytes
+    pub fn read_long_register(&mut self, register: u8, params: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.device
+            .read_long_register(RECEIVER_INDEX, register, params)
+    }
+
+    pub fn write_long_register(&mut self, register: u8, value: &[u8]) -> anyhow::Result<()> {
+        self.device
+            .write_long_register(RECEIVER_INDEX, register, value)
+    }
+
+    pub fn notification_flags(&mut self) -> anyhow::Result<NotificationFlags> {
+        let value = self.read_register(NOTIFICATION_FLAGS, &[])?;
+        Ok(NotificationFlags(u32::from_be_bytes([
+            0, value[0], value[1], value[2],
+        ])))
+    }
+
+    pub fn set_notification_flags(&mut self, flags: NotificationFlags) -> anyhow::Result<()> {
+        let [_, high, middle, low] = flags.0.to_be_bytes();
+        self.write_register(NOTIFICATION_FLAGS, &[high, middle, low])
+    }
+
+    // Turns on battery, wireless link and link quality notifications, plus
+    // the software present flag telling devices someone is listening. Flags
+    // already set are kept.
+    pub fn enable_notifications(&mut self) -> anyhow::Result<NotificationFlags> {
+        let flags = self.notification_flags()?
+            | NotificationFlags::BATTERY_STATUS
+            | NotificationFlags::WIRELESS
+            | NotificationFlags::LINK_QUALITY
+            | NotificationFlags::SOFTWARE_PRESENT;
+        self.set_notification_flags(flags)?;
+        Ok(flags)
+    }
+
+    pub fn activity(&mut self) -> anyhow::Result<ActivityCounters> {
+        let value = self.read_long_register(DEVICE_ACTIVITY, &[])?;
+        let mut counters = [0; 6];
+        for (counter, byte) in counters.iter_mut().zip(value.iter()) {
+            *counter = *byte;
+        }
+        Ok(ActivityCounters(counters))
+    }
+
+    // The slot of the device used the most during `window`, None if they
+    // all stayed idle. Ask the user to move the device while this runs.
+    pub fn active_slot(&mut self, window: Duration) -> anyhow::Result<Option<u8>> {
+        let before = self.activity()?;
+        self.device.clock().sleep(window);
+        Ok(self.activity()?.active_since(&before).first().copied())
+    }
+
+    // Name of the device paired in `slot` (1-6), as the receiver knows it
+    pub fn device_name(&mut self, slot: u8) -> anyhow::Result<String> {
+        let page = DEVICE_NAME + slot.saturating_sub(1);
+        let info = self.read_long_register(RECEIVER_INFO, &[page])?;
+        let len = (info[1] as usize).min(info.len() - 2);
+        Ok(decode_string(&info[2..2 + len]))
+    }
+
+    // Opens the pairing lock for up to `timeout` and pairs the first device
+    // switched on, returning its slot. `progress` is called as pairing goes
+    // along so the user can be guided. This is the Unifying and Lightspeed
+    // flow, Bolt receivers pair differently.
+    pub fn pair(
+        &mut self,
+        timeout: Duration,
+        progress: impl FnMut(&PairingEvent),
+    ) -> anyhow::Result<u8> {
+        self.pair_until(timeout, progress, &CancellationToken::new())
+    }
+
+    // Like `pair`, but gives up and closes the pairing lock once `cancel` is
+    // cancelled
+    pub fn pair_until(
+        &mut self,
+        timeout: Duration,
+        mut progress: impl FnMut(&PairingEvent),
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<u8> {
+        let seconds = timeout.as_secs().clamp(1, 255) as u8;
+        self.write_register(RECEIVER_PAIRING, &[OPEN_LOCK, 0x00, seconds])?;
+
+        // the receiver closes the lock itself, give its notification a moment
+        let clock = self.device.clock();
+        let deadline = clock.now() + timeout + Duration::from_secs(2);
+        // wait in short slices so cancellation doesn't take the whole timeout
+        let slice = Duration::from_millis(100);
+        let mut discovered = None;
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(clock.now());
+            if remaining.is_zero() || cancel.is_cancelled() {
+                if let Err(err) = self.write_register(RECEIVER_PAIRING, &[CLOSE_LOCK, 0x00, 0x00]) {
+                    crate::tracing::debug!("Failed to close the pairing lock: {}", err);
+                }
+                break Err(if remaining.is_zero() {
+                    PairingError::Timeout
+                } else {
+                    PairingError::Cancelled
+                });
+            }
+            let Some(message) = self.device.next_message(remaining.min(slice))? else {
+                continue;
+            };
+
+            match hidpp10::decode_notification(&message) {
+                Some(Event::PairingLock { open: true, .. }) => progress(&PairingEvent::LockOpened),
+                Some(Event::PairingLock {
+                    error: Some(error), ..
+                }) => break Err(PairingError::from(error)),
+                Some(Event::PairingLock { .. }) => break discovered.ok_or(PairingError::Timeout),
+                // sent from the slot the new device got
+                Some(Event::Link { .. }) if (1..=6).contains(&message.device_index()) => {
+                    let slot = message.device_index();
+                    let data = message.data();
+                    let name = match self.device_name(slot) {
+                        Ok(name) => Some(name),
+                        Err(err) => {
+                            crate::tracing::debug!(
+                                "Failed to read the name of slot {}: {}",
+                                slot,
+                                err
+                            );
+                            None
+                        }
+                    };
+                    discovered = Some(slot);
+                    progress(&PairingEvent::Discovered {
+                        slot,
+                        kind: DeviceKind::from(data[0] & 0x0F),
+                        wireless_pid: u16::from_le_bytes([data[1], data[2]]),
+                        name,
+                    });
+                }
+                _ => crate::tracing::trace!(
+                    "Ignoring notification while pairing: {}",
+                    message.dump()
+                ),
+            }
+        };
+
+        match result {
+            Ok(slot) => {
+                progress(&PairingEvent::Completed { slot });
+                Ok(slot)
+            }
+            Err(error) => {
+                progress(&PairingEvent::Failed(error));
+                bail!("Pairing failed: {}", error)
+            }
+        }
+    }
+
+    // Firmware and bootloader versions from register 0xF1, plus the serial
+    // number from the receiver info register where available
+    pub fn firmware_info(&mut self) -> anyhow::Result<ReceiverFirmware> {
+        // each reply echoes the requested item before its value
+        let firmware = self.read_register(FIRMWARE_INFO, &[0x01])?;
+        let build = self.read_register(FIRMWARE_INFO, &[0x02])?;
+        let bootloader = self.read_register(FIRMWARE_INFO, &[0x04])?;
+
+        let serial = match self.read_long_register(RECEIVER_INFO, &[0x03]) {
+            Ok(info) => Some(info[1..5].iter().map(|b| format!("{:02X}", b)).collect()),
+            Err(err) => {
+                crate::tracing::debug!("Failed to read receiver serial: {}", err);
+                None
+            }
+        };
+
+        Ok(ReceiverFirmware {
+            firmware: (firmware[1], firmware[2]),
+            build: u16::from_be_bytes([build[1], build[2]]),
+            bootloader: (bootloader[1], bootloader[2]),
+            serial,
+        })
+    }
+}
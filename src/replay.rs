@@ -0,0 +1,130 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    frame_log::{Direction, FrameRecord},
+    CancellationToken, Device,
+};
+
+// A recorded request with the reply that was received for it
+#[derive(Clone, Debug)]
+pub struct RecordedTransaction {
+    // offset from the first request of the session
+    pub offset: Duration,
+    pub request: Vec<u8>,
+    pub expected: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplayResult {
+    pub request: Vec<u8>,
+    pub expected: Option<Vec<u8>>,
+    pub actual: Result<Vec<u8>, String>,
+}
+
+impl ReplayResult {
+    pub fn matches(&self) -> bool {
+        match (&self.expected, &self.actual) {
+            (Some(expected), Ok(actual)) => expected == actual,
+            (None, Err(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+pub fn load_session(path: impl AsRef<Path>) -> anyhow::Result<Vec<RecordedTransaction>> {
+    let contents = fs::read_to_string(path)?;
+    let records = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str::<FrameRecord>)
+        .collect::<Result<Vec<_>, _>>()?;
+    transactions(&records)
+}
+
+// Pairs each outgoing frame with the first reply that follows it, skipping
+// notifications (software id 0) the same way Device::write does.
+pub fn transactions(records: &[FrameRecord]) -> anyhow::Result<Vec<RecordedTransaction>> {
+    let start = records
+        .iter()
+        .find(|r| r.direction == Direction::Out)
+        .map(|r| r.timestamp_us)
+        .unwrap_or_default();
+
+    let mut transactions: Vec<RecordedTransaction> = vec![];
+    for record in records {
+        match record.direction {
+            Direction::Out => transactions.push(RecordedTransaction {
+                offset: Duration::from_micros(record.timestamp_us.saturating_sub(start)),
+                request: record.bytes()?,
+                expected: None,
+            }),
+            Direction::In => {
+                let is_notification = record
+                    .decoded
+                    .as_ref()
+                    .map(|d| d.software_id == 0)
+                    .unwrap_or(false);
+                if is_notification {
+                    continue;
+                }
+                if let Some(last) = transactions.last_mut() {
+                    if last.expected.is_none() {
+                        last.expected = Some(record.bytes()?);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(transactions)
+}
+
+// Re-sends every recorded request, optionally keeping the original spacing
+// between them, and collects the replies for comparison.
+pub fn replay(
+    device: &mut Device,
+    transactions: &[RecordedTransaction],
+    preserve_timing: bool,
+) -> Vec<ReplayResult> {
+    replay_until(
+        device,
+        transactions,
+        preserve_timing,
+        &CancellationToken::new(),
+    )
+}
+
+// Like `replay`, stopping early when `cancel` is cancelled. Only the
+// transactions that were sent are returned.
+pub fn replay_until(
+    device: &mut Device,
+    transactions: &[RecordedTransaction],
+    preserve_timing: bool,
+    cancel: &CancellationToken,
+) -> Vec<ReplayResult> {
+    let start = Instant::now();
+    let mut results = vec![];
+    for transaction in transactions {
+        if preserve_timing {
+            if let Some(wait) = transaction.offset.checked_sub(start.elapsed()) {
+                cancel.sleep(wait);
+            }
+        }
+        if cancel.is_cancelled() {
+            break;
+        }
+
+        results.push(ReplayResult {
+            request: transaction.request.clone(),
+            expected: transaction.expected.clone(),
+            actual: device
+                .write(&transaction.request)
+                .map_err(|e| e.to_string()),
+        });
+    }
+    results
+}
@@ -0,0 +1,38 @@
+use anyhow::bail;
+
+use crate::{Device, Feature, Function};
+
+impl Device {
+    // Report intervals the device supports, in milliseconds
+    pub fn report_rates(&mut self) -> anyhow::Result<Vec<u8>> {
+        let result = self.send_feature(Feature::ReportRate, Function::ReportRateGetList, &[])?;
+        // bit n set means n + 1 ms is supported
+        Ok((0..8)
+            .filter(|bit| result.data[0] & 1 << bit != 0)
+            .map(|bit| bit + 1)
+            .collect())
+    }
+
+    // Milliseconds between reports
+    pub fn get_report_rate(&mut self) -> anyhow::Result<u8> {
+        let result = self.send_feature(Feature::ReportRate, Function::ReportRateGetRate, &[])?;
+        Ok(result.data[0])
+    }
+
+    pub fn set_report_rate(&mut self, milliseconds: u8) -> anyhow::Result<()> {
+        let supported = self.report_rates()?;
+        if !supported.contains(&milliseconds) {
+            bail!(
+                "Report rate of {}ms not supported, pick one of {:?}",
+                milliseconds,
+                supported
+            );
+        }
+        self.send_feature(
+            Feature::ReportRate,
+            Function::ReportRateSetRate,
+            &[milliseconds],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+
+use crate::{BacklightConfig, Cid, Device, DisabledKeys, Dpi, Feature, SmartShift};
+
+// The everyday settings of a mouse in one place, for applications that don't
+// want to deal with features. Settings the device doesn't have are None, and
+// None fields are left alone when applying.
+//
+//   let mut settings = MouseSettings::read_from(&mut device)?;
+//   settings.dpi = Some(1600);
+//   settings.apply_to(&mut device)?;
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MouseSettings {
+    // of the first sensor, rounded to a supported value when applied
+    pub dpi: Option<u16>,
+    // milliseconds between reports
+    pub report_rate: Option<u8>,
+    pub smartshift: Option<SmartShift>,
+    pub hires_wheel: Option<bool>,
+    // what each remappable control performs, read back as the control itself
+    // when it isn't remapped. Map a control to itself to give it its own
+    // action back.
+    pub button_mappings: HashMap<Cid, Cid>,
+}
+
+fn supports(device: &mut Device, feature: Feature) -> bool {
+    device.feature_index(feature).is_ok()
+}
+
+impl MouseSettings {
+    pub fn read_from(device: &mut Device) -> anyhow::Result<Self> {
+        let mut settings = MouseSettings::default();
+        if supports(device, Feature::AdjustableDpi) {
+            settings.dpi = Some(device.get_dpi(0)?.0);
+        }
+        if supports(device, Feature::ReportRate) {
+            settings.report_rate = Some(device.get_report_rate()?);
+        }
+        if supports(device, Feature::SmartShiftEnhanced) || supports(device, Feature::SmartShift) {
+            settings.smartshift = Some(device.get_smart_shift()?);
+        }
+        if supports(device, Feature::HiResWheel) {
+            settings.hires_wheel = Some(device.get_wheel_mode()?.hires);
+        }
+        if supports(device, Feature::ReprogControlsV4) {
+            for cid in device.remappable_controls()? {
+                let target = device.get_remap(cid)?.unwrap_or(cid);
+                settings.button_mappings.insert(cid, target);
+            }
+        }
+        Ok(settings)
+    }
+
+    pub fn apply_to(&self, device: &mut Device) -> anyhow::Result<()> {
+        if let Some(dpi) = self.dpi {
+            if !supports(device, Feature::AdjustableDpi) {
+                bail!("The device has no adjustable DPI");
+            }
+            let capabilities = device.dpi_capabilities(0)?;
+            device.set_dpi(0, Dpi::new(dpi, &capabilities))?;
+        }
+        if let Some(report_rate) = self.report_rate {
+            device.set_report_rate(report_rate)?;
+        }
+        if let Some(smartshift) = self.smartshift {
+            device.set_smart_shift(Some(smartshift.ratchet), Some(smartshift.threshold))?;
+        }
+        if let Some(hires) = self.hires_wheel {
+            let mut mode = device.get_wheel_mode()?;
+            if mode.hires != hires {
+                mode.hires = hires;
+                device.set_wheel_mode(mode)?;
+            }
+        }
+        for (cid, target) in &self.button_mappings {
+            let target = (target != cid).then_some(*target);
+            device.set_remap(*cid, target)?;
+        }
+        Ok(())
+    }
+}
+
+// Same idea for keyboards
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KeyboardSettings {
+    // F-keys trigger their special function without holding Fn, F1-F12 then
+    // need Fn
+    pub fn_inversion: Option<bool>,
+    pub backlight: Option<BacklightConfig>,
+    pub disabled_keys: Option<DisabledKeys>,
+    // platform descriptor in use on the current host
+    pub platform: Option<u8>,
+    // names of the paired hosts by 0 based index, only written back when
+    // they changed
+    pub host_names: HashMap<u8, String>,
+}
+
+impl KeyboardSettings {
+    pub fn read_from(device: &mut Device) -> anyhow::Result<Self> {
+        let mut settings = KeyboardSettings::default();
+        if [
+            Feature::NewFnInversion,
+            Feature::FnInversion,
+            Feature::K375sFnInversion,
+        ]
+        .into_iter()
+        .any(|feature| supports(device, feature))
+        {
+            settings.fn_inversion = Some(device.get_fn_swap()?);
+        }
+        if supports(device, Feature::Backlight2) {
+            settings.backlight = Some(device.get_backlight()?);
+        }
+        if supports(device, Feature::DisableKeys) {
+            settings.disabled_keys = Some(device.get_disabled_keys()?);
+        }
+        if supports(device, Feature::MultiPlatform) {
+            settings.platform = Some(device.get_host_platform()?);
+        }
+        if supports(device, Feature::HostsInfo) {
+            for host in device.hosts()? {
+                if let Some(name) = host.name {
+                    settings.host_names.insert(host.index, name);
+                }
+            }
+        }
+        Ok(settings)
+    }
+
+    pub fn apply_to(&self, device: &mut Device) -> anyhow::Result<()> {
+        if let Some(fn_inversion) = self.fn_inversion {
+            device.set_fn_swap(fn_inversion)?;
+        }
+        if let Some(backlight) = &self.backlight {
+            device.set_backlight(backlight)?;
+        }
+        if let Some(keys) = self.disabled_keys {
+            device.set_disabled_keys(keys)?;
+        }
+        if let Some(platform) = self.platform {
+            device.set_host_platform(platform)?;
+        }
+        if !self.host_names.is_empty() {
+            for host in device.hosts()? {
+                match self.host_names.get(&host.index) {
+                    Some(name) if host.name.as_ref() != Some(name) => {
+                        device.set_host_name(host.index, name)?
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Copies the settings of `src` to `dst`, another unit of the same model, e.g.
+// to provision a batch of identical mice. Settings `dst` doesn't have, say
+// on older firmware, are skipped. Host names and the platform stay as they
+// are, they belong to each unit's own pairings. Returns what was applied.
+pub fn copy_settings(
+    src: &mut Device,
+    dst: &mut Device,
+) -> anyhow::Result<(MouseSettings, KeyboardSettings)> {
+    let (src_model, dst_model) = (model(src)?, model(dst)?);
+    if src_model != dst_model {
+        bail!(
+            "Can't copy settings from {} to {}, they're different models",
+            src_model,
+            dst_model
+        );
+    }
+
+    let mut mouse = MouseSettings::read_from(src)?;
+    let current = MouseSettings::read_from(dst)?;
+    mouse.dpi = mouse.dpi.filter(|_| current.dpi.is_some());
+    mouse.report_rate = mouse.report_rate.filter(|_| current.report_rate.is_some());
+    mouse.smartshift = mouse.smartshift.filter(|_| current.smartshift.is_some());
+    mouse.hires_wheel = mouse.hires_wheel.filter(|_| current.hires_wheel.is_some());
+    if supports(dst, Feature::ReprogControlsV4) {
+        let controls = dst.remappable_controls()?;
+        mouse
+            .button_mappings
+            .retain(|cid, target| controls.contains(cid) && controls.contains(target));
+    } else {
+        mouse.button_mappings.clear();
+    }
+
+    let mut keyboard = KeyboardSettings::read_from(src)?;
+    let current = KeyboardSettings::read_from(dst)?;
+    keyboard.fn_inversion = keyboard
+        .fn_inversion
+        .filter(|_| current.fn_inversion.is_some());
+    keyboard.backlight = keyboard.backlight.filter(|_| current.backlight.is_some());
+    keyboard.disabled_keys = keyboard
+        .disabled_keys
+        .filter(|_| current.disabled_keys.is_some());
+    keyboard.platform = None;
+    keyboard.host_names.clear();
+
+    mouse.apply_to(dst)?;
+    keyboard.apply_to(dst)?;
+    Ok((mouse, keyboard))
+}
+
+// The model name where the device reports one, the ids otherwise
+fn model(device: &mut Device) -> anyhow::Result<String> {
+    if supports(device, Feature::DeviceNameType) {
+        return device.device_name();
+    }
+    Ok(format!(
+        "{:04x}:{:04x}",
+        device.vendor_id(),
+        device.product_id()
+    ))
+}
@@ -0,0 +1,228 @@
+#[cfg(feature = "json")]
+use crate::replay::RecordedTransaction;
+use crate::{
+    consts::{ERR_INVALID_ARGUMENT, ERR_INVALID_FEATURE_INDEX, ERR_INVALID_FUNCTION},
+    Feature, Message,
+};
+
+// Report descriptor with the two vendor collections HID++ devices expose:
+// short (0x10, 6 bytes) and long (0x11, 19 bytes) reports in both directions.
+pub const REPORT_DESCRIPTOR: &[u8] = &[
+    0x06, 0x00, 0xFF, // Usage Page (Vendor Defined 0xFF00)
+    0x09, 0x01, // Usage (0x01)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x10, //   Report ID (0x10)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x06, //   Report Count (6)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x09, 0x01, //   Usage (0x01)
+    0x81, 0x00, //   Input (Data, Array, Absolute)
+    0x09, 0x01, //   Usage (0x01)
+    0x91, 0x00, //   Output (Data, Array, Absolute)
+    0xC0, // End Collection
+    0x06, 0x00, 0xFF, // Usage Page (Vendor Defined 0xFF00)
+    0x09, 0x02, // Usage (0x02)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x11, //   Report ID (0x11)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x13, //   Report Count (19)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x09, 0x02, //   Usage (0x02)
+    0x81, 0x00, //   Input (Data, Array, Absolute)
+    0x09, 0x02, //   Usage (0x02)
+    0x91, 0x00, //   Output (Data, Array, Absolute)
+    0xC0, // End Collection
+];
+
+// Device side of the protocol: takes request frames and produces the frames
+// a HID++ 2.0 mouse with Root, FeatureSet, UnifiedBattery, AdjustableDpi and
+// DeviceNameType would answer with, or replays a transcript recorded from a real device.
+#[derive(Clone, Debug)]
+pub struct Simulator {
+    features: Vec<Feature>,
+    #[cfg(feature = "json")]
+    transcript: Vec<RecordedTransaction>,
+    pub battery_percentage: u8,
+    pub charging: bool,
+    pub dpi: u16,
+    pub dpi_list: Vec<u16>,
+    pub name: String,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self {
+            features: vec![
+                Feature::Root,
+                Feature::FeatureSet,
+                Feature::UnifiedBattery,
+                Feature::AdjustableDpi,
+                Feature::DeviceNameType,
+            ],
+            #[cfg(feature = "json")]
+            transcript: vec![],
+            battery_percentage: 80,
+            charging: false,
+            dpi: 1600,
+            dpi_list: vec![400, 800, 1600, 3200],
+            name: "Simulated Mouse".to_string(),
+        }
+    }
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Answers every request with the reply recorded for the same frame, e.g.
+    // from a `--log-frames` session, so devices we don't model can be tested.
+    // Requests missing from the transcript get an error.
+    #[cfg(feature = "json")]
+    pub fn from_transcript(transcript: Vec<RecordedTransaction>) -> Self {
+        Self {
+            transcript,
+            ..Self::default()
+        }
+    }
+
+    // Returns the reply to a request frame, None if the frame isn't HID++
+    pub fn handle(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        #[cfg(feature = "json")]
+        let raw = request;
+        let request = Message::try_from(request.to_vec()).ok()?;
+        #[cfg(feature = "json")]
+        if !self.transcript.is_empty() {
+            return match self.transcript.iter().find(|t| t.request == raw) {
+                Some(transaction) => transaction.expected.clone(),
+                None => {
+                    crate::tracing::debug!("Request missing from the transcript: {:02x?}", raw);
+                    Some(error_frame(&request, ERR_INVALID_FUNCTION))
+                }
+            };
+        }
+        let mut data = request.data.clone();
+        data.resize(16, 0);
+
+        let Some(feature) = self.features.get(request.feature_index as usize).cloned() else {
+            return Some(error_frame(&request, ERR_INVALID_FEATURE_INDEX));
+        };
+
+        let reply = match (feature, request.function_index) {
+            // getFeature
+            (Feature::Root, 0x00) => {
+                let id = u16::from_be_bytes([data[0], data[1]]);
+                let index = self
+                    .features
+                    .iter()
+                    .position(|f| f.value() == id)
+                    .unwrap_or(0);
+                vec![index as u8, 0x00, 0x00]
+            }
+            // getProtocolVersion, 4.5 echoing the ping byte
+            (Feature::Root, 0x01) => vec![0x04, 0x05, data[2]],
+            // getCount, not including Root
+            (Feature::FeatureSet, 0x00) => vec![(self.features.len() - 1) as u8],
+            // getFeatureId
+            (Feature::FeatureSet, 0x01) => match self.features.get(data[0] as usize) {
+                Some(feature) => {
+                    let [hi, lo] = feature.value().to_be_bytes();
+                    vec![hi, lo, 0x00, 0x00]
+                }
+                None => return Some(error_frame(&request, ERR_INVALID_ARGUMENT)),
+            },
+            // getCapabilities: all four levels, rechargeable with state of charge
+            (Feature::UnifiedBattery, 0x00) => vec![0x0F, 0x03],
+            // getStatus
+            (Feature::UnifiedBattery, 0x01) => self.battery_status(),
+            // getSensorCount
+            (Feature::AdjustableDpi, 0x00) => vec![0x01],
+            // getSensorDpiList, zero terminated
+            (Feature::AdjustableDpi, 0x01) => {
+                let mut reply = vec![0x00];
+                for dpi in &self.dpi_list {
+                    reply.extend_from_slice(&dpi.to_be_bytes());
+                }
+                reply.extend_from_slice(&[0x00, 0x00]);
+                reply
+            }
+            // getSensorDpi: current and default
+            (Feature::AdjustableDpi, 0x02) => {
+                let mut reply = vec![0x00];
+                reply.extend_from_slice(&self.dpi.to_be_bytes());
+                reply.extend_from_slice(&self.dpi_list[0].to_be_bytes());
+                reply
+            }
+            // setSensorDpi
+            (Feature::AdjustableDpi, 0x03) => {
+                let dpi = u16::from_be_bytes([data[1], data[2]]);
+                if !self.dpi_list.contains(&dpi) {
+                    return Some(error_frame(&request, ERR_INVALID_ARGUMENT));
+                }
+                self.dpi = dpi;
+                vec![0x00, data[1], data[2]]
+            }
+            // getDeviceNameCount
+            (Feature::DeviceNameType, 0x00) => vec![self.name.len() as u8],
+            // getDeviceName, 16 bytes from the offset
+            (Feature::DeviceNameType, 0x01) => {
+                self.name.bytes().skip(data[0] as usize).take(16).collect()
+            }
+            // getDeviceType: mouse
+            (Feature::DeviceNameType, 0x02) => vec![0x03],
+            _ => return Some(error_frame(&request, ERR_INVALID_FUNCTION)),
+        };
+
+        Some(long_frame(
+            request.device_index,
+            request.feature_index,
+            request.function_index << 4 | request.software_id,
+            &reply,
+        ))
+    }
+
+    // Updates the battery and returns the battery_status_event announcing it
+    pub fn set_battery(&mut self, percentage: u8, charging: bool) -> Vec<u8> {
+        self.battery_percentage = percentage.min(100);
+        self.charging = charging;
+
+        let feature_index = self
+            .features
+            .iter()
+            .position(|f| *f == Feature::UnifiedBattery)
+            .unwrap_or(0);
+        long_frame(0x01, feature_index as u8, 0x00, &self.battery_status())
+    }
+
+    fn battery_status(&self) -> Vec<u8> {
+        let level = match self.battery_percentage {
+            0..=5 => 0x01,
+            6..=20 => 0x02,
+            21..=80 => 0x04,
+            _ => 0x08,
+        };
+        let (status, external_power) = match (self.charging, self.battery_percentage) {
+            (false, _) => (0x00, 0x00),
+            (true, 100) => (0x03, 0x01),
+            (true, _) => (0x01, 0x01),
+        };
+        vec![self.battery_percentage, level, status, external_power]
+    }
+}
+
+fn long_frame(device_index: u8, feature_index: u8, function_byte: u8, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x11, device_index, feature_index, function_byte];
+    frame.extend(data.iter().copied().chain(std::iter::repeat(0)).take(16));
+    frame
+}
+
+fn error_frame(request: &Message, code: u8) -> Vec<u8> {
+    long_frame(
+        request.device_index,
+        0xFF,
+        request.feature_index,
+        &[request.function_index << 4 | request.software_id, code],
+    )
+}
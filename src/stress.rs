@@ -0,0 +1,134 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Device, Feature, Function, MessageBuilder};
+
+#[derive(Clone, Debug)]
+pub struct StressConfig {
+    pub threads: usize,
+    pub iterations: usize,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        Self {
+            threads: 4,
+            iterations: 100,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StressReport {
+    pub requests: u64,
+    pub succeeded: u64,
+    pub events: u64,
+    pub retries: u64,
+    pub mismatched: u64,
+    pub timeouts: u64,
+    pub errors: u64,
+    pub elapsed: Duration,
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: u64,
+    succeeded: u64,
+    events: u64,
+    mismatched: u64,
+    errors: u64,
+}
+
+// Hammers the device from several threads with interleaved pings, feature
+// calls and event reads. Every ping carries a unique value so replies
+// delivered to the wrong request show up as mismatches.
+pub fn run(device: Arc<Mutex<Device>>, config: StressConfig) -> StressReport {
+    let start = Instant::now();
+    let stats_before = device.lock().unwrap().stats();
+
+    let handles: Vec<_> = (0..config.threads)
+        .map(|thread_id| {
+            let device = Arc::clone(&device);
+            let iterations = config.iterations;
+            thread::spawn(move || {
+                let mut counters = Counters::default();
+                for i in 0..iterations {
+                    let mut device = device.lock().unwrap();
+                    match i % 3 {
+                        0 => ping(
+                            &mut device,
+                            (thread_id * iterations + i) as u8,
+                            &mut counters,
+                        ),
+                        1 => feature_call(&mut device, &mut counters),
+                        _ => match device.next_event(Duration::from_millis(1)) {
+                            Ok(Some(_)) => counters.events += 1,
+                            Ok(None) => {}
+                            Err(err) => {
+                                crate::tracing::debug!("Event read failed: {}", err);
+                                counters.errors += 1;
+                            }
+                        },
+                    }
+                }
+                counters
+            })
+        })
+        .collect();
+
+    let mut report = StressReport::default();
+    for handle in handles {
+        let counters = handle.join().expect("stress thread panicked");
+        report.requests += counters.requests;
+        report.succeeded += counters.succeeded;
+        report.events += counters.events;
+        report.mismatched += counters.mismatched;
+        report.errors += counters.errors;
+    }
+
+    let stats_after = device.lock().unwrap().stats();
+    report.retries = stats_after.retries - stats_before.retries;
+    report.timeouts = stats_after.timeouts - stats_before.timeouts;
+    report.elapsed = start.elapsed();
+    report
+}
+
+fn ping(device: &mut Device, value: u8, counters: &mut Counters) {
+    counters.requests += 1;
+    match device.send_feature(
+        Feature::Root,
+        Function::RootGetProtocolVersion,
+        &[0x00, 0x00, value],
+    ) {
+        Ok(response) if response.data[2] == value => counters.succeeded += 1,
+        Ok(_) => counters.mismatched += 1,
+        Err(err) => {
+            crate::tracing::debug!("Ping failed: {}", err);
+            counters.errors += 1;
+        }
+    }
+}
+
+fn feature_call(device: &mut Device, counters: &mut Counters) {
+    counters.requests += 1;
+    let request = MessageBuilder::new_short(0x00, Function::RootGetFeature)
+        .device_index(0x01)
+        .add_u16(Feature::FeatureSet.value())
+        .build();
+    match request.send(device) {
+        Ok(response)
+            if response.feature_index == request.feature_index
+                && response.function_index == request.function_index =>
+        {
+            counters.succeeded += 1
+        }
+        Ok(_) => counters.mismatched += 1,
+        Err(err) => {
+            crate::tracing::debug!("Feature call failed: {}", err);
+            counters.errors += 1;
+        }
+    }
+}
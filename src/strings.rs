@@ -0,0 +1,96 @@
+// Collects a string the device sends in chunks (device, friendly and host
+// names) and decodes it once complete. Chunks are kept as bytes until then so
+// multi-byte characters split across replies come out whole.
+pub(crate) struct StringAssembler {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl StringAssembler {
+    pub(crate) fn new(len: usize) -> Self {
+        Self {
+            bytes: Vec::with_capacity(len),
+            len,
+        }
+    }
+
+    // byte index to ask the device for next
+    pub(crate) fn offset(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        self.bytes.len() >= self.len
+    }
+
+    // Appends the characters of a reply, after the `header` bytes it echoes
+    // back (indexes). Returns false if the reply had none, so callers stop
+    // asking instead of looping forever.
+    pub(crate) fn push(&mut self, data: &[u8], header: usize) -> bool {
+        let wanted = self.len - self.bytes.len().min(self.len);
+        let chunk = data.get(header..).unwrap_or_default();
+        let chunk = &chunk[..chunk.len().min(wanted)];
+        if chunk.is_empty() {
+            return false;
+        }
+        self.bytes.extend_from_slice(chunk);
+        true
+    }
+
+    pub(crate) fn finish(self) -> String {
+        decode_string(&self.bytes)
+    }
+}
+
+// Decodes a device provided string: UTF-16LE when it looks like it, UTF-8
+// otherwise. It ends at the first NUL, 0xFF (erased flash) padding is
+// stripped, and a character cut off by the length limit is dropped rather
+// than turned into garbage.
+pub(crate) fn decode_string(bytes: &[u8]) -> String {
+    let string = if looks_like_utf16(bytes) {
+        let units = bytes
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .take_while(|unit| *unit != 0x0000 && *unit != 0xFFFF);
+        let string = char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect::<String>();
+        // a surrogate pair cut in half
+        string
+            .trim_end_matches(char::REPLACEMENT_CHARACTER)
+            .to_string()
+    } else {
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        let bytes = &bytes[..end];
+        let end = bytes
+            .iter()
+            .rposition(|b| *b != 0xFF)
+            .map_or(0, |last| last + 1);
+        let bytes = &bytes[..end];
+        match std::str::from_utf8(bytes) {
+            Ok(string) => string.to_string(),
+            // the string ends in the middle of a character
+            Err(err) if err.error_len().is_none() => {
+                String::from_utf8_lossy(&bytes[..err.valid_up_to()]).into_owned()
+            }
+            Err(err) => {
+                crate::tracing::debug!("Invalid UTF-8 in device string: {}", err);
+                String::from_utf8_lossy(bytes).into_owned()
+            }
+        }
+    };
+    string.trim().to_string()
+}
+
+// UTF-8 text never has a NUL right after its first character, while ASCII
+// text in UTF-16LE does
+fn looks_like_utf16(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && bytes[0] != 0 && bytes[1] == 0 && bytes[2] != 0
+}
+
+pub fn to_hex(buf: &[u8]) -> String {
+    buf.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,18 @@
+use crate::{Device, Feature, Function};
+
+impl Device {
+    // Internal temperature in degrees Celsius, from the TemperatureMeasurement
+    // (0x1F30) engineering feature some gaming devices keep enabled. None when
+    // the device doesn't expose it.
+    pub fn temperature(&mut self) -> anyhow::Result<Option<i8>> {
+        if self.feature_index(Feature::TemperatureMeasurement).is_err() {
+            return Ok(None);
+        }
+        let result = self.send_feature(
+            Feature::TemperatureMeasurement,
+            Function::TemperatureMeasurementGet,
+            &[],
+        )?;
+        Ok(Some(result.data[0] as i8))
+    }
+}
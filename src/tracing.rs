@@ -0,0 +1,34 @@
+// Stand-ins for the tracing macros used in the crate, for builds without the
+// tracing feature. They log nothing but still type check their arguments.
+
+macro_rules! event {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = ::std::format!($($arg)*);
+        }
+    };
+}
+
+macro_rules! debug_span {
+    ($name:expr $(, $field:ident = $value:expr)* $(,)?) => {{
+        $(let _ = &$value;)*
+        $crate::tracing::Span
+    }};
+}
+
+pub(crate) use debug_span;
+pub(crate) use event as debug;
+// only logged by the uhid virtual devices
+#[allow(unused_imports)]
+pub(crate) use event as error;
+pub(crate) use event as info;
+pub(crate) use event as trace;
+pub(crate) use event as warn;
+
+pub(crate) struct Span;
+
+impl Span {
+    pub(crate) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+}
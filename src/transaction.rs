@@ -0,0 +1,28 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
+
+static NEXT: AtomicU64 = AtomicU64::new(1);
+
+// Identifies a request and everything done for it: resends, discarded
+// frames, the reply. Ids increase across all devices and threads, and show
+// up in logs as the `transaction{id=..}` span and in frame logs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(transparent))]
+pub struct TransactionId(pub u64);
+
+impl TransactionId {
+    pub(crate) fn next() -> Self {
+        TransactionId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
@@ -0,0 +1,120 @@
+use std::{ffi::CStr, time::Duration};
+
+use crate::{discovery, Backoff, Clock, HidInfo, ReportId};
+
+// Moves reports between a `Device` and the hardware, so the protocol logic
+// doesn't depend on hidapi. Other backends, e.g. a mock for tests or a bridge
+// to a device on another machine, are plugged in with
+// `DeviceBuilder::transport`.
+pub trait Transport: Send {
+    // Sends one report, report id first
+    fn write_report(&mut self, report: &[u8]) -> anyhow::Result<()>;
+
+    // Waits up to `timeout` for a report, returns its length or 0 if none
+    // arrived
+    fn read_report(&mut self, buf: &mut [u8], timeout: Duration) -> anyhow::Result<usize>;
+
+    // Reports the device accepts
+    fn report_lengths(&self) -> Vec<ReportId> {
+        vec![ReportId::Short, ReportId::Long, ReportId::VeryLong]
+    }
+
+    // Strings and numbers from the HID layer, where there's one
+    fn hid_info(&self) -> anyhow::Result<HidInfo> {
+        anyhow::bail!("The transport has no HID information")
+    }
+
+    // Descriptor the caller can poll for readability, where there's one
+    #[cfg(all(target_os = "linux", feature = "hidraw"))]
+    fn raw_fd(&self) -> Option<std::os::unix::io::RawFd> {
+        None
+    }
+}
+
+// The default transport: a hidapi handle on the device's HID++ interface.
+// With the `hidraw` feature reads and writes go straight to the hidraw node
+// instead, hidapi is only asked for the device info.
+pub struct HidapiTransport {
+    device: hidapi::HidDevice,
+    #[cfg(all(target_os = "linux", feature = "hidraw"))]
+    hidraw: crate::hidraw::Hidraw,
+}
+
+impl HidapiTransport {
+    // Opens the HID++ interface at `path`, or of the first device with the
+    // ids, retrying as `backoff` says
+    pub fn open(
+        vendor_id: u16,
+        product_id: u16,
+        path: Option<&CStr>,
+        backoff: &Backoff,
+        clock: &dyn Clock,
+    ) -> anyhow::Result<Self> {
+        let device = backoff
+            .retry(clock, |_| {
+                let api = hidapi::HidApi::new()?;
+                let path = match path {
+                    Some(path) => {
+                        discovery::check_path(&api, path)?;
+                        path.to_owned()
+                    }
+                    None => discovery::hidpp_path(&api, vendor_id, product_id)?,
+                };
+                anyhow::Ok(api.open_path(&path)?)
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to open device: Error opening device: {}", e))?;
+        Ok(Self {
+            #[cfg(all(target_os = "linux", feature = "hidraw"))]
+            hidraw: crate::hidraw::Hidraw::open(device.get_device_info()?.path())?,
+            device,
+        })
+    }
+
+    pub(crate) fn ids(&self) -> anyhow::Result<(u16, u16)> {
+        let info = self.device.get_device_info()?;
+        Ok((info.vendor_id(), info.product_id()))
+    }
+}
+
+impl Transport for HidapiTransport {
+    #[cfg(not(all(target_os = "linux", feature = "hidraw")))]
+    fn write_report(&mut self, report: &[u8]) -> anyhow::Result<()> {
+        self.device.write(report)?;
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", feature = "hidraw"))]
+    fn write_report(&mut self, report: &[u8]) -> anyhow::Result<()> {
+        self.hidraw.write(report)?;
+        Ok(())
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "hidraw")))]
+    fn read_report(&mut self, buf: &mut [u8], timeout: Duration) -> anyhow::Result<usize> {
+        Ok(self.device.read_timeout(buf, timeout.as_millis() as i32)?)
+    }
+
+    #[cfg(all(target_os = "linux", feature = "hidraw"))]
+    fn read_report(&mut self, buf: &mut [u8], timeout: Duration) -> anyhow::Result<usize> {
+        self.hidraw.read_timeout(buf, timeout)
+    }
+
+    fn hid_info(&self) -> anyhow::Result<HidInfo> {
+        let info = self.device.get_device_info()?;
+        Ok(HidInfo {
+            manufacturer: info.manufacturer_string().map(str::to_string),
+            product: info.product_string().map(str::to_string),
+            serial_number: info.serial_number().map(str::to_string),
+            release_number: info.release_number(),
+            interface_number: info.interface_number(),
+            path: Some(info.path().to_owned()),
+        })
+    }
+
+    #[cfg(all(target_os = "linux", feature = "hidraw"))]
+    fn raw_fd(&self) -> Option<std::os::unix::io::RawFd> {
+        use std::os::unix::io::AsRawFd;
+
+        Some(self.hidraw.as_raw_fd())
+    }
+}
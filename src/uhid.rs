@@ -0,0 +1,226 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    faults::{FaultInjector, FaultStats},
+    sim::{Simulator, REPORT_DESCRIPTOR},
+};
+
+// uhid event types, from linux/uhid.h
+const UHID_DESTROY: u32 = 1;
+const UHID_START: u32 = 2;
+const UHID_STOP: u32 = 3;
+const UHID_OPEN: u32 = 4;
+const UHID_CLOSE: u32 = 5;
+const UHID_OUTPUT: u32 = 6;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+
+// sizeof(struct uhid_event)
+const UHID_EVENT_SIZE: usize = 4380;
+const UHID_DATA_MAX: usize = 4096;
+const BUS_USB: u16 = 0x03;
+const EIO: u16 = 5;
+
+// A kernel-visible HID device backed by a `Simulator`. hidapi opens it like
+// any other hidraw node, so the real discovery and transport code is
+// exercised end to end. The device is destroyed when dropped.
+pub struct VirtualDevice {
+    uhid: Arc<Mutex<File>>,
+    simulator: Arc<Mutex<Simulator>>,
+    faults: Arc<Mutex<Option<FaultInjector>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl VirtualDevice {
+    pub fn create(vendor_id: u16, product_id: u16, simulator: Simulator) -> anyhow::Result<Self> {
+        let mut uhid = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/uhid")?;
+        create(&mut uhid, vendor_id, product_id)?;
+        crate::tracing::debug!(
+            "Created virtual device {:04x}:{:04x}",
+            vendor_id,
+            product_id
+        );
+
+        let uhid = Arc::new(Mutex::new(uhid));
+        let simulator = Arc::new(Mutex::new(simulator));
+        let faults = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let uhid = Arc::clone(&uhid);
+            let simulator = Arc::clone(&simulator);
+            let faults = Arc::clone(&faults);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                if let Err(err) = serve(&uhid, &simulator, &faults, &stop) {
+                    crate::tracing::error!("Virtual device stopped: {}", err);
+                }
+            })
+        };
+
+        Ok(Self {
+            uhid,
+            simulator,
+            faults,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn simulator(&self) -> Arc<Mutex<Simulator>> {
+        Arc::clone(&self.simulator)
+    }
+
+    // Passes the replies to requests through `faults` from now on, None to
+    // deliver them untouched again
+    pub fn set_faults(&self, faults: Option<FaultInjector>) {
+        *self.faults.lock().unwrap() = faults;
+    }
+
+    pub fn fault_stats(&self) -> Option<FaultStats> {
+        self.faults
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(FaultInjector::stats)
+    }
+
+    // Sends an unsolicited input report, e.g. an event from `Simulator::set_battery`
+    pub fn send_input(&self, report: &[u8]) -> anyhow::Result<()> {
+        input(&mut self.uhid.lock().unwrap(), report)
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let destroy = UHID_DESTROY.to_le_bytes();
+        if let Err(err) = self.uhid.lock().unwrap().write_all(&destroy) {
+            crate::tracing::warn!("Failed to destroy virtual device: {}", err);
+        }
+    }
+}
+
+fn serve(
+    uhid: &Mutex<File>,
+    simulator: &Mutex<Simulator>,
+    faults: &Mutex<Option<FaultInjector>>,
+    stop: &AtomicBool,
+) -> anyhow::Result<()> {
+    let mut buf = vec![0u8; UHID_EVENT_SIZE];
+    while !stop.load(Ordering::Relaxed) {
+        let mut uhid = uhid.lock().unwrap();
+        let len = match uhid.read(&mut buf) {
+            Ok(len) => len,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                drop(uhid);
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if len < 4 {
+            continue;
+        }
+
+        match u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) {
+            UHID_START => crate::tracing::debug!("Virtual device started"),
+            UHID_STOP => crate::tracing::debug!("Virtual device stopped"),
+            UHID_OPEN => crate::tracing::debug!("Virtual device opened"),
+            UHID_CLOSE => crate::tracing::debug!("Virtual device closed"),
+            UHID_OUTPUT => {
+                // struct uhid_output_req { data[4096], size: u16, rtype: u8 }
+                let size = u16::from_le_bytes([buf[4 + UHID_DATA_MAX], buf[5 + UHID_DATA_MAX]]);
+                let request = &buf[4..4 + size as usize];
+                crate::tracing::trace!("REQ {:02x?}", request);
+                let reply = simulator.lock().unwrap().handle(request);
+                let Some(reply) = reply else {
+                    continue;
+                };
+                let (delay, replies) = match faults.lock().unwrap().as_mut() {
+                    Some(faults) => faults.apply(reply),
+                    None => (Duration::ZERO, vec![reply]),
+                };
+                thread::sleep(delay);
+                for reply in replies {
+                    crate::tracing::trace!("RES {:02x?}", reply);
+                    input(&mut uhid, &reply)?;
+                }
+            }
+            UHID_GET_REPORT => {
+                // feature reports aren't part of HID++, fail them
+                let mut reply = UHID_GET_REPORT_REPLY.to_le_bytes().to_vec();
+                reply.extend_from_slice(&buf[4..8]);
+                reply.extend_from_slice(&EIO.to_le_bytes());
+                reply.extend_from_slice(&0u16.to_le_bytes());
+                uhid.write_all(&reply)?;
+            }
+            UHID_SET_REPORT => {
+                let mut reply = UHID_SET_REPORT_REPLY.to_le_bytes().to_vec();
+                reply.extend_from_slice(&buf[4..8]);
+                reply.extend_from_slice(&EIO.to_le_bytes());
+                uhid.write_all(&reply)?;
+            }
+            other => crate::tracing::trace!("Ignoring uhid event {}", other),
+        }
+    }
+
+    Ok(())
+}
+
+// struct uhid_create2_req { name[128], phys[64], uniq[64], rd_size: u16,
+// bus: u16, vendor: u32, product: u32, version: u32, country: u32, rd_data[4096] }
+fn create(uhid: &mut File, vendor_id: u16, product_id: u16) -> anyhow::Result<()> {
+    let mut event = UHID_CREATE2.to_le_bytes().to_vec();
+    event.extend(fixed(b"hidpp simulated mouse", 128));
+    event.extend(fixed(b"hidpp-sim", 64));
+    event.extend(fixed(b"", 64));
+    event.extend_from_slice(&(REPORT_DESCRIPTOR.len() as u16).to_le_bytes());
+    event.extend_from_slice(&BUS_USB.to_le_bytes());
+    event.extend_from_slice(&(vendor_id as u32).to_le_bytes());
+    event.extend_from_slice(&(product_id as u32).to_le_bytes());
+    event.extend_from_slice(&0u32.to_le_bytes());
+    event.extend_from_slice(&0u32.to_le_bytes());
+    event.extend(fixed(REPORT_DESCRIPTOR, UHID_DATA_MAX));
+    uhid.write_all(&event)?;
+    Ok(())
+}
+
+// struct uhid_input2_req { size: u16, data[4096] }
+fn input(uhid: &mut File, report: &[u8]) -> anyhow::Result<()> {
+    let mut event = UHID_INPUT2.to_le_bytes().to_vec();
+    event.extend_from_slice(&(report.len() as u16).to_le_bytes());
+    event.extend_from_slice(report);
+    uhid.write_all(&event)?;
+    Ok(())
+}
+
+fn fixed(bytes: &[u8], len: usize) -> Vec<u8> {
+    bytes
+        .iter()
+        .copied()
+        .chain(std::iter::repeat(0))
+        .take(len)
+        .collect()
+}
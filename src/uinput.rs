@@ -0,0 +1,171 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    os::unix::io::AsRawFd,
+};
+
+use anyhow::bail;
+
+// event types and codes, from linux/input-event-codes.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const SYN_REPORT: u16 = 0x00;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+
+pub const KEY_ESC: u16 = 1;
+pub const KEY_TAB: u16 = 15;
+pub const KEY_LEFTCTRL: u16 = 29;
+pub const KEY_LEFTSHIFT: u16 = 42;
+pub const KEY_LEFTALT: u16 = 56;
+pub const KEY_UP: u16 = 103;
+pub const KEY_LEFT: u16 = 105;
+pub const KEY_RIGHT: u16 = 106;
+pub const KEY_DOWN: u16 = 108;
+pub const KEY_MUTE: u16 = 113;
+pub const KEY_VOLUMEDOWN: u16 = 114;
+pub const KEY_VOLUMEUP: u16 = 115;
+pub const KEY_LEFTMETA: u16 = 125;
+pub const KEY_BACK: u16 = 158;
+pub const KEY_FORWARD: u16 = 159;
+pub const KEY_NEXTSONG: u16 = 163;
+pub const KEY_PLAYPAUSE: u16 = 164;
+pub const KEY_PREVIOUSSONG: u16 = 165;
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_RIGHT: u16 = 0x111;
+pub const BTN_MIDDLE: u16 = 0x112;
+pub const BTN_SIDE: u16 = 0x113;
+pub const BTN_EXTRA: u16 = 0x114;
+
+// ioctls, from linux/uinput.h
+const UI_DEV_CREATE: u64 = 0x5501;
+const UI_DEV_DESTROY: u64 = 0x5502;
+const UI_SET_EVBIT: u64 = 0x40045564;
+const UI_SET_KEYBIT: u64 = 0x40045565;
+const UI_SET_RELBIT: u64 = 0x40045566;
+
+const KEY_MAX: u16 = 0x2ff;
+const BUS_VIRTUAL: u16 = 0x06;
+
+// A virtual keyboard and mouse the kernel treats like real hardware, used to
+// re-emit diverted buttons and gestures as shortcuts, clicks or scrolling.
+// The device is destroyed when dropped.
+pub struct VirtualInput {
+    uinput: File,
+}
+
+impl VirtualInput {
+    pub fn create(name: &str) -> anyhow::Result<Self> {
+        let mut uinput = OpenOptions::new().write(true).open("/dev/uinput")?;
+
+        ioctl(&uinput, UI_SET_EVBIT, EV_KEY)?;
+        for key in 1..=KEY_MAX {
+            ioctl(&uinput, UI_SET_KEYBIT, key)?;
+        }
+        ioctl(&uinput, UI_SET_EVBIT, EV_REL)?;
+        for rel in [REL_X, REL_Y, REL_HWHEEL, REL_WHEEL] {
+            ioctl(&uinput, UI_SET_RELBIT, rel)?;
+        }
+
+        // struct uinput_user_dev { name[80], id { bustype, vendor, product,
+        // version: u16 }, ff_effects_max: u32, absmax/absmin/absfuzz/absflat[64]: i32 }
+        let mut setup = name.as_bytes().iter().copied().take(79).collect::<Vec<_>>();
+        setup.resize(80, 0);
+        setup.extend_from_slice(&BUS_VIRTUAL.to_ne_bytes());
+        setup.extend_from_slice(&0x046du16.to_ne_bytes());
+        setup.extend_from_slice(&0u16.to_ne_bytes());
+        setup.extend_from_slice(&0u16.to_ne_bytes());
+        setup.extend_from_slice(&0u32.to_ne_bytes());
+        setup.resize(setup.len() + 4 * 64 * 4, 0);
+        uinput.write_all(&setup)?;
+        ioctl(&uinput, UI_DEV_CREATE, 0)?;
+        crate::tracing::debug!("Created virtual input device {}", name);
+
+        Ok(Self { uinput })
+    }
+
+    pub fn press(&mut self, key: u16) -> anyhow::Result<()> {
+        self.emit(EV_KEY, key, 1)?;
+        self.sync()
+    }
+
+    pub fn release(&mut self, key: u16) -> anyhow::Result<()> {
+        self.emit(EV_KEY, key, 0)?;
+        self.sync()
+    }
+
+    pub fn click(&mut self, key: u16) -> anyhow::Result<()> {
+        self.press(key)?;
+        self.release(key)
+    }
+
+    // Presses the keys in order and releases them in reverse, e.g.
+    // `[KEY_LEFTCTRL, KEY_TAB]`
+    pub fn shortcut(&mut self, keys: &[u16]) -> anyhow::Result<()> {
+        for key in keys {
+            self.emit(EV_KEY, *key, 1)?;
+        }
+        self.sync()?;
+        for key in keys.iter().rev() {
+            self.emit(EV_KEY, *key, 0)?;
+        }
+        self.sync()
+    }
+
+    // Scrolls by whole wheel notches, positive is up
+    pub fn scroll(&mut self, notches: i32) -> anyhow::Result<()> {
+        self.emit(EV_REL, REL_WHEEL, notches)?;
+        self.sync()
+    }
+
+    // Scrolls horizontally by whole notches, positive is right
+    pub fn hscroll(&mut self, notches: i32) -> anyhow::Result<()> {
+        self.emit(EV_REL, REL_HWHEEL, notches)?;
+        self.sync()
+    }
+
+    pub fn move_pointer(&mut self, dx: i32, dy: i32) -> anyhow::Result<()> {
+        self.emit(EV_REL, REL_X, dx)?;
+        self.emit(EV_REL, REL_Y, dy)?;
+        self.sync()
+    }
+
+    fn sync(&mut self) -> anyhow::Result<()> {
+        self.emit(EV_SYN, SYN_REPORT, 0)
+    }
+
+    // struct input_event { time: timeval, type: u16, code: u16, value: i32 },
+    // the kernel fills in the time
+    fn emit(&mut self, kind: u16, code: u16, value: i32) -> anyhow::Result<()> {
+        let mut event = vec![0u8; std::mem::size_of::<libc::timeval>()];
+        event.extend_from_slice(&kind.to_ne_bytes());
+        event.extend_from_slice(&code.to_ne_bytes());
+        event.extend_from_slice(&value.to_ne_bytes());
+        self.uinput.write_all(&event)?;
+        Ok(())
+    }
+}
+
+impl Drop for VirtualInput {
+    fn drop(&mut self) {
+        if let Err(err) = ioctl(&self.uinput, UI_DEV_DESTROY, 0) {
+            crate::tracing::warn!("Failed to destroy virtual input device: {}", err);
+        }
+    }
+}
+
+fn ioctl(uinput: &File, request: u64, value: u16) -> anyhow::Result<()> {
+    // SAFETY: the uinput ioctls used here take an int argument by value
+    let result = unsafe { libc::ioctl(uinput.as_raw_fd(), request as _, value as libc::c_int) };
+    if result < 0 {
+        bail!(
+            "ioctl {:#x} failed: {}",
+            request,
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use std::{ffi::CStr, time::Duration};
+
+use anyhow::bail;
+
+use crate::{CancellationToken, Device, FirmwareVersion};
+
+impl Device {
+    // After a firmware update the device reboots and re-enumerates, possibly
+    // with different report ids and a different feature table. Closes this
+    // handle, waits up to `timeout` for a device with a new firmware version
+    // to show up and returns it initialized. When `expected` is given the new
+    // firmware must match it. Gives up between attempts once `cancel` is
+    // cancelled.
+    pub fn reopen_after_update(
+        mut self,
+        expected: Option<&FirmwareVersion>,
+        timeout: Duration,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Device> {
+        let vendor_id = self.vendor_id();
+        let product_id = self.product_id();
+        let path = self.path().map(CStr::to_owned);
+        let previous = self.firmware_version().ok();
+        let rate_limiter = self.rate_limiter().clone();
+        let clock = self.clock();
+        // reopen the same unit the same way, not whichever matches the ids
+        let builder = {
+            let clock = clock.clone();
+            let device_index = self.device_index();
+            let timeout = self.timeout();
+            let quirks = self.quirks();
+            let backoff = self.backoff();
+            move || {
+                Device::builder()
+                    .device_index(device_index)
+                    .timeout(timeout)
+                    .quirks(quirks)
+                    .retry(backoff)
+                    .clock(clock.clone())
+            }
+        };
+        // restoring diverted controls is expected to fail while the device
+        // reboots
+        if let Err(err) = self.close() {
+            crate::tracing::trace!("Failed to close the device for the update: {}", err);
+        }
+
+        let deadline = clock.now() + timeout;
+        loop {
+            cancel.check()?;
+            if clock.now() >= deadline {
+                bail!(
+                    "{:04x}:{:04x} did not come back with new firmware within {:?}",
+                    vendor_id,
+                    product_id,
+                    timeout
+                );
+            }
+            clock.sleep(Duration::from_millis(250));
+
+            // the node may be gone or reused by another device after
+            // re-enumeration, fall back to the ids then
+            let by_path = path
+                .as_ref()
+                .and_then(|path| builder().path(path.as_bytes()).ok())
+                .and_then(|builder| builder.open().ok())
+                .filter(|device| {
+                    device.vendor_id() == vendor_id && device.product_id() == product_id
+                });
+            let opened = match by_path {
+                Some(device) => Ok(device),
+                None => builder().vid(vendor_id).pid(product_id).open(),
+            };
+            let mut device = match opened {
+                Ok(device) => device,
+                Err(err) => {
+                    crate::tracing::trace!("Waiting for re-enumeration: {}", err);
+                    continue;
+                }
+            };
+            // the old firmware may still be answering while it shuts down
+            let firmware = match device.firmware_version() {
+                Ok(firmware) => firmware,
+                Err(err) => {
+                    crate::tracing::trace!("Device not ready: {}", err);
+                    continue;
+                }
+            };
+            if previous.as_ref() == Some(&firmware) && expected != Some(&firmware) {
+                crate::tracing::trace!("Still running {}", firmware);
+                continue;
+            }
+            if let Some(expected) = expected {
+                if *expected != firmware {
+                    bail!(
+                        "Device came back with firmware {}, expected {}",
+                        firmware,
+                        expected
+                    );
+                }
+            }
+
+            crate::tracing::debug!("Device came back with firmware {}", firmware);
+            device.set_rate_limiter(rate_limiter);
+            device.init()?;
+            return Ok(device);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{Device, Feature, Function};
+
+// HiResWheel (0x2121) getWheelCapability reply
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct WheelCapabilities {
+    // high resolution events per ratchet notch
+    pub multiplier: u8,
+    pub has_invert: bool,
+    // whether the wheel has a ratchet/free spin switch
+    pub has_ratchet_switch: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct WheelMode {
+    // wheel movement is reported as HID++ notifications instead of HID
+    pub diverted: bool,
+    pub hires: bool,
+    pub inverted: bool,
+}
+
+impl From<u8> for WheelMode {
+    fn from(value: u8) -> Self {
+        Self {
+            diverted: value & 0x01 != 0,
+            hires: value & 0x02 != 0,
+            inverted: value & 0x04 != 0,
+        }
+    }
+}
+
+impl WheelMode {
+    fn value(&self) -> u8 {
+        self.diverted as u8 | (self.hires as u8) << 1 | (self.inverted as u8) << 2
+    }
+}
+
+// SmartShift ratchet control: the wheel ratchets, and with a threshold it
+// switches to free spin on its own when flicked faster than it
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct SmartShift {
+    pub ratchet: bool,
+    // 1-254, 255 keeps the wheel in ratchet mode
+    pub threshold: u8,
+}
+
+impl Device {
+    pub fn wheel_capabilities(&mut self) -> anyhow::Result<WheelCapabilities> {
+        let result =
+            self.send_feature(Feature::HiResWheel, Function::HiResWheelGetCapability, &[])?;
+        Ok(WheelCapabilities {
+            multiplier: result.data[0],
+            has_invert: result.data[1] & 0x08 != 0,
+            has_ratchet_switch: result.data[1] & 0x04 != 0,
+        })
+    }
+
+    pub fn get_wheel_mode(&mut self) -> anyhow::Result<WheelMode> {
+        let result = self.send_feature(Feature::HiResWheel, Function::HiResWheelGetMode, &[])?;
+        Ok(WheelMode::from(result.data[0]))
+    }
+
+    pub fn set_wheel_mode(&mut self, mode: WheelMode) -> anyhow::Result<()> {
+        self.send_feature(
+            Feature::HiResWheel,
+            Function::HiResWheelSetMode,
+            &[mode.value()],
+        )?;
+        Ok(())
+    }
+
+    // Position of the ratchet switch, true when ratcheting
+    pub fn wheel_ratchet(&mut self) -> anyhow::Result<bool> {
+        let result = self.send_feature(
+            Feature::HiResWheel,
+            Function::HiResWheelGetRatchetSwitch,
+            &[],
+        )?;
+        Ok(result.data[0] & 0x01 != 0)
+    }
+
+    pub fn get_smart_shift(&mut self) -> anyhow::Result<SmartShift> {
+        let (feature, function) = self.smart_shift_feature(false)?;
+        let result = self.send_feature(feature, function, &[])?;
+        Ok(SmartShift {
+            ratchet: result.data[0] == 0x02,
+            threshold: result.data[1],
+        })
+    }
+
+    // Changes the wheel mode and/or the SmartShift threshold, None keeps the
+    // current value
+    pub fn set_smart_shift(
+        &mut self,
+        ratchet: Option<bool>,
+        threshold: Option<u8>,
+    ) -> anyhow::Result<()> {
+        let mode = match ratchet {
+            Some(true) => 0x02,
+            Some(false) => 0x01,
+            None => 0x00,
+        };
+        let (feature, function) = self.smart_shift_feature(true)?;
+        self.send_feature(feature, function, &[mode, threshold.unwrap_or(0)])?;
+        Ok(())
+    }
+
+    // SmartShift enhanced (0x2111) has the same ratchet control functions
+    // as SmartShift (0x2110), shifted by one
+    fn smart_shift_feature(&mut self, set: bool) -> anyhow::Result<(Feature, Function)> {
+        if self.feature_index(Feature::SmartShiftEnhanced).is_ok() {
+            let function = if set {
+                Function::SmartShiftEnhancedSetRatchetControl
+            } else {
+                Function::SmartShiftEnhancedGetRatchetControl
+            };
+            return Ok((Feature::SmartShiftEnhanced, function));
+        }
+        let function = if set {
+            Function::SmartShiftSetRatchetControl
+        } else {
+            Function::SmartShiftGetRatchetControl
+        };
+        Ok((Feature::SmartShift, function))
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use hidpp::{Backoff, ManualClock};
+
+fn backoff() -> Backoff {
+    Backoff {
+        initial: Duration::from_millis(10),
+        factor: 2.0,
+        max_delay: Duration::from_millis(250),
+        budget: Duration::from_millis(500),
+        jitter: false,
+    }
+}
+
+#[test]
+fn gives_up_once_the_budget_has_elapsed() {
+    let clock = ManualClock::new();
+    let mut attempts = 0;
+    let result: Result<(), String> = backoff().retry(&clock, |_| {
+        attempts += 1;
+        Err("not yet".to_string())
+    });
+
+    assert!(result.is_err());
+    // 10 + 20 + 40 + 80 + 160 ms of delays, then the 190 ms left of the
+    // budget, between 7 attempts
+    assert_eq!(attempts, 7);
+    assert!(clock.elapsed() <= backoff().budget);
+    assert_eq!(clock.elapsed(), Duration::from_millis(500));
+}
+
+#[test]
+fn stops_retrying_on_success() {
+    let clock = ManualClock::new();
+    let result = backoff().retry(&clock, |attempt| match attempt {
+        3 => Ok(attempt),
+        _ => Err("not yet"),
+    });
+
+    assert_eq!(result, Ok(3));
+    assert_eq!(clock.elapsed(), Duration::from_millis(30));
+}
+
+#[test]
+fn never_sleeps_past_the_budget() {
+    let clock = ManualClock::new();
+    let budget = Duration::from_millis(25);
+    let mut attempts = 0;
+    let result: Result<(), &str> = Backoff {
+        budget,
+        ..backoff()
+    }
+    .retry(&clock, |_| {
+        attempts += 1;
+        Err("not yet")
+    });
+
+    assert!(result.is_err());
+    // at 0, 10 and 25 ms
+    assert_eq!(attempts, 3);
+    assert_eq!(clock.elapsed(), budget);
+}
@@ -0,0 +1,140 @@
+use hidpp::{
+    decode_notification, encode_notification, BatteryInfo, BatteryLevel, BatteryStatus, Error,
+    Event, Function, MalformedFrame, Message, MessageBuilder, ReportId,
+};
+
+fn malformed(buf: Vec<u8>) -> MalformedFrame {
+    Message::try_from(buf)
+        .unwrap_err()
+        .downcast::<MalformedFrame>()
+        .unwrap()
+}
+
+#[test]
+fn rejects_truncated_and_unknown_frames() {
+    assert_eq!(malformed(vec![]).expected_len, None);
+    assert_eq!(malformed(vec![0x20, 0x01, 0x00, 0x11]).expected_len, None);
+    assert_eq!(malformed(vec![0x10, 0x01, 0x00]).expected_len, Some(7));
+
+    let truncated = malformed(vec![0x11, 0x01, 0x02, 0x11, 0x00]);
+    assert_eq!(truncated.expected_len, Some(20));
+    assert_eq!(truncated.bytes, vec![0x11, 0x01, 0x02, 0x11, 0x00]);
+
+    let message = Message::try_from(vec![0x10, 0x01, 0x02, 0x11, 0xAA, 0xBB, 0xCC]).unwrap();
+    assert_eq!(message.data(), &[0xAA, 0xBB, 0xCC]);
+}
+
+#[test]
+fn encodes_payloads_to_the_report_length() {
+    let payload = (1..=16).collect::<Vec<u8>>();
+    let message = MessageBuilder::new_short(0x05, Function::HostsInfoSetHostFriendlyName)
+        .report_id(ReportId::Long)
+        .device_index(0x01)
+        .data(payload.clone())
+        .build();
+    let bytes = message.to_bytes();
+    assert_eq!(bytes.len(), 20);
+    assert_eq!(bytes[..4], [0x11, 0x01, 0x05, 0x41]);
+    assert_eq!(bytes[4..], payload[..]);
+
+    let message = MessageBuilder::new_short(0x05, Function::HostsInfoSetHostFriendlyName)
+        .report_id(ReportId::VeryLong)
+        .data(payload.clone())
+        .build();
+    let bytes = message.to_bytes();
+    assert_eq!(bytes.len(), 64);
+    assert_eq!(bytes[4..20], payload[..]);
+    assert!(bytes[20..].iter().all(|b| *b == 0));
+}
+
+#[test]
+fn decodes_error_frames() {
+    // HID++ 2.0: feature 0x05 function 1 rejected with invalid argument
+    let mut frame = vec![0x11, 0x01, 0xFF, 0x05, 0x1A, 0x02];
+    frame.resize(20, 0);
+    let message = Message::try_from(frame).unwrap();
+    assert_eq!(Error::from_message(&message), Some(Error::InvalidArgument));
+
+    // HID++ 1.0: the same code means an invalid register address
+    let message = Message::try_from(vec![0x10, 0xFF, 0x8F, 0x81, 0xF1, 0x02, 0x00]).unwrap();
+    assert_eq!(Error::from_message(&message), Some(Error::InvalidAddress));
+
+    let message = Message::try_from(vec![0x10, 0x01, 0x8F, 0x81, 0x00, 0x42, 0x00]).unwrap();
+    assert_eq!(
+        Error::from_message(&message),
+        Some(Error::Other(0x8F, 0x42))
+    );
+
+    let message = Message::try_from(vec![0x10, 0x01, 0x02, 0x11, 0xAA, 0xBB, 0xCC]).unwrap();
+    assert_eq!(Error::from_message(&message), None);
+}
+
+#[cfg(feature = "enum-iterator")]
+#[test]
+fn lists_every_feature() {
+    let all = enum_iterator::all::<hidpp::Feature>().collect::<Vec<_>>();
+    assert_eq!(all, hidpp::Feature::ALL);
+}
+
+#[test]
+fn appends_typed_payload_fields() {
+    let message = MessageBuilder::new_short(0x03, Function::AdjustableDpiSetSensorDpi)
+        .report_id(ReportId::Long)
+        .add_u8(0x00)
+        .add_u16(1600)
+        .add_i16(-2)
+        .add_u16_le(0x4079)
+        .add_bytes(&[0xAA, 0xBB])
+        .add_u32_le(0x01020304)
+        .build();
+    assert_eq!(
+        message.to_bytes()[4..17],
+        [0x00, 0x06, 0x40, 0xFF, 0xFE, 0x79, 0x40, 0xAA, 0xBB, 0x04, 0x03, 0x02, 0x01]
+    );
+
+    let too_long = MessageBuilder::new_short(0x03, Function::AdjustableDpiSetSensorDpi)
+        .add_u16(1600)
+        .add_u16(1600);
+    assert!(too_long.try_build().is_err());
+}
+
+#[test]
+fn round_trips_hidpp10_notifications() {
+    let events = [
+        Event::Link {
+            established: false,
+            encrypted: true,
+        },
+        Event::LinkQuality(73),
+        Event::PairingLock {
+            open: true,
+            error: None,
+        },
+        Event::PairingLock {
+            open: false,
+            error: Some(0x02),
+        },
+        Event::PoweredOn,
+        Event::Unpaired,
+        Event::Battery(BatteryInfo {
+            percentage: 64,
+            estimated: false,
+            level: BatteryLevel::Good,
+            status: BatteryStatus::Recharging,
+            voltage: None,
+        }),
+        Event::Battery(BatteryInfo {
+            percentage: BatteryLevel::Low.approximate_percentage(),
+            estimated: true,
+            level: BatteryLevel::Low,
+            status: BatteryStatus::Discharging,
+            voltage: None,
+        }),
+    ];
+    for event in &events {
+        let message = encode_notification(0x02, event).unwrap();
+        assert_eq!(message.device_index(), 0x02);
+        let decoded = decode_notification(&Message::try_from(message.to_bytes()).unwrap());
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", Some(event)));
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use hidpp::{Cid, Event, Gesture, GestureDirection, GestureEngine, GestureMode};
+
+fn counter(engine: &mut GestureEngine, gesture: Gesture) -> Arc<AtomicUsize> {
+    let count = Arc::new(AtomicUsize::new(0));
+    let fired = count.clone();
+    engine.on(Cid::GestureButton, gesture, move || {
+        fired.fetch_add(1, Ordering::SeqCst);
+    });
+    count
+}
+
+fn press() -> Event {
+    Event::Buttons(vec![Cid::GestureButton])
+}
+
+fn release() -> Event {
+    Event::Buttons(vec![])
+}
+
+#[test]
+fn fires_on_release() {
+    let mut engine = GestureEngine::new();
+    let up = counter(&mut engine, Gesture::new(GestureDirection::Up));
+    let click = counter(&mut engine, Gesture::new(GestureDirection::None));
+
+    engine.handle(&press());
+    engine.handle(&Event::RawXY { dx: 5, dy: -40 });
+    engine.handle(&Event::RawXY { dx: 0, dy: -40 });
+    assert_eq!(up.load(Ordering::SeqCst), 0);
+    engine.handle(&release());
+    assert_eq!(up.load(Ordering::SeqCst), 1);
+    assert_eq!(click.load(Ordering::SeqCst), 0);
+
+    // pressed and released without moving past the threshold
+    engine.handle(&press());
+    engine.handle(&Event::RawXY { dx: 10, dy: 10 });
+    engine.handle(&release());
+    assert_eq!(up.load(Ordering::SeqCst), 1);
+    assert_eq!(click.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn fires_once_on_threshold() {
+    let mut engine = GestureEngine::new();
+    let right = counter(
+        &mut engine,
+        Gesture::new(GestureDirection::Right)
+            .threshold(30)
+            .mode(GestureMode::OnThreshold),
+    );
+
+    engine.handle(&press());
+    engine.handle(&Event::RawXY { dx: 20, dy: 0 });
+    assert_eq!(right.load(Ordering::SeqCst), 0);
+    engine.handle(&Event::RawXY { dx: 20, dy: 0 });
+    assert_eq!(right.load(Ordering::SeqCst), 1);
+    engine.handle(&Event::RawXY { dx: 50, dy: 0 });
+    engine.handle(&release());
+    assert_eq!(right.load(Ordering::SeqCst), 1);
+
+    // movement without the button held is ignored
+    engine.handle(&Event::RawXY { dx: 100, dy: 0 });
+    assert_eq!(right.load(Ordering::SeqCst), 1);
+}
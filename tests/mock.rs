@@ -0,0 +1,754 @@
+use std::{sync::Arc, time::Duration};
+
+use hidpp::{
+    copy_settings, encode_notification, mock::MockTransport, sim::Simulator, BatteryStatus,
+    CancellationToken, Cid, Device, Error, Event, Feature, HostDefaults, HostOs, LedEffect,
+    LedPersistence, LedZone, LedZoneLocation, ManualClock, PairingError, PairingEvent,
+    ProfileField, ProfileTemplate, ProfilesInfo, Receiver, ReportId,
+};
+
+fn open(mock: &MockTransport) -> Device {
+    Device::builder().transport(mock.clone()).open().unwrap()
+}
+
+fn long(header: [u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut frame = header.to_vec();
+    frame.extend_from_slice(data);
+    frame.resize(20, 0);
+    frame
+}
+
+// Root getFeature answering index 5 for UnifiedBattery, and that the
+// device has no DeviceNameType to tell whether it's a headset
+fn with_battery_at_index_5(mock: &MockTransport) {
+    mock.expect(
+        &[0x10, 0x01, 0x00, 0x01, 0x00, 0x05],
+        &long([0x11, 0x01, 0x00, 0x01], &[0x00]),
+    );
+    mock.expect(
+        &[0x10, 0x01, 0x00, 0x01, 0x10, 0x04],
+        &long([0x11, 0x01, 0x00, 0x01], &[0x05]),
+    );
+}
+
+#[test]
+fn discovers_feature_indexes() {
+    let mock = MockTransport::simulated(Simulator::new());
+    let mut device = open(&mock);
+    device.init().unwrap();
+
+    assert_eq!(device.index_for(Feature::Root).unwrap(), 0);
+    assert_eq!(device.index_for(Feature::FeatureSet).unwrap(), 1);
+    assert_eq!(device.index_for(Feature::UnifiedBattery).unwrap(), 2);
+    assert_eq!(device.index_for(Feature::AdjustableDpi).unwrap(), 3);
+    assert!(device.index_for(Feature::Backlight2).is_err());
+}
+
+#[test]
+fn reads_battery_from_canned_replies() {
+    let mock = MockTransport::new();
+    with_battery_at_index_5(&mock);
+    // getCapabilities: all levels, rechargeable with state of charge
+    mock.expect(
+        &[0x10, 0x01, 0x05, 0x01],
+        &long([0x11, 0x01, 0x05, 0x01], &[0x0F, 0x03]),
+    );
+    // getStatus: 55%, good, recharging
+    mock.expect(
+        &[0x10, 0x01, 0x05, 0x11],
+        &long([0x11, 0x01, 0x05, 0x11], &[55, 0x04, 0x01, 0x01]),
+    );
+
+    let mut device = open(&mock);
+    let battery = device.get_battery().unwrap();
+    assert_eq!(battery.percentage, 55);
+    assert!(!battery.estimated);
+    assert_eq!(battery.status, BatteryStatus::Recharging);
+    assert_eq!(mock.written().len(), 4);
+}
+
+#[test]
+fn converts_error_frames() {
+    let mock = MockTransport::new();
+    with_battery_at_index_5(&mock);
+    mock.expect(
+        &[0x10, 0x01, 0x05, 0x01],
+        &long([0x11, 0x01, 0xFF, 0x05], &[0x01, 0x08]),
+    );
+
+    let mut device = open(&mock);
+    let err = device.get_battery().unwrap_err();
+    assert_eq!(err.downcast_ref::<Error>(), Some(&Error::Busy));
+}
+
+#[test]
+fn queues_notifications_that_arrive_before_the_reply() {
+    let mock = MockTransport::simulated(Simulator::new());
+    let mut device = open(&mock);
+    device.init().unwrap();
+
+    let event = mock
+        .with_simulator(|simulator| simulator.set_battery(20, false))
+        .unwrap();
+    let reply = mock
+        .with_simulator(|simulator| simulator.handle(&[0x10, 0x01, 0x03, 0x21, 0x00, 0x00, 0x00]))
+        .flatten()
+        .unwrap();
+    mock.expect_many(&[0x10, 0x01, 0x03, 0x21], vec![event, reply]);
+
+    assert_eq!(device.get_dpi(0).unwrap().0, 1600);
+    match device.next_event(Duration::ZERO).unwrap() {
+        Some(Event::Battery(battery)) => assert_eq!(battery.percentage, 20),
+        other => panic!("expected a battery event, got {:?}", other),
+    }
+}
+
+#[test]
+fn delivers_unsolicited_events() {
+    let mock = MockTransport::simulated(Simulator::new());
+    let mut device = open(&mock);
+    device.init().unwrap();
+
+    let event = mock
+        .with_simulator(|simulator| simulator.set_battery(90, true))
+        .unwrap();
+    mock.push_event(&event);
+    match device.next_event(Duration::ZERO).unwrap() {
+        Some(Event::Battery(battery)) => {
+            assert_eq!(battery.percentage, 90);
+            assert_eq!(battery.status, BatteryStatus::Recharging);
+        }
+        other => panic!("expected a battery event, got {:?}", other),
+    }
+    assert!(device.next_event(Duration::ZERO).unwrap().is_none());
+}
+
+#[test]
+fn keeps_streaming_after_a_malformed_notification() {
+    let mock = MockTransport::simulated(Simulator::new());
+    let mut device = open(&mock);
+    device.init().unwrap();
+
+    let mut malformed = mock
+        .with_simulator(|simulator| simulator.set_battery(50, false))
+        .unwrap();
+    // no such battery status
+    malformed[6] = 0x7F;
+    let event = mock
+        .with_simulator(|simulator| simulator.set_battery(40, false))
+        .unwrap();
+    mock.push_event(&malformed).push_event(&event);
+
+    let events = device.events();
+    match events.recv_timeout(Duration::from_secs(1)) {
+        Some(Ok(Event::Unknown(message))) => assert_eq!(message.data()[2], 0x7F),
+        other => panic!("expected an unknown event, got {:?}", other),
+    }
+    match events.recv_timeout(Duration::from_secs(1)) {
+        Some(Ok(Event::Battery(battery))) => assert_eq!(battery.percentage, 40),
+        other => panic!("expected a battery event, got {:?}", other),
+    }
+    events.stop().unwrap();
+}
+
+#[test]
+fn resends_unanswered_requests() {
+    let mock = MockTransport::new();
+    let mut device = open(&mock);
+
+    assert!(device.ping(0x5A).is_err());
+    // the request and two resends
+    assert_eq!(mock.written().len(), 3);
+    assert_eq!(device.stats().timeouts, 1);
+}
+
+#[test]
+fn sends_long_reports_over_long_only_transports() {
+    let mock = MockTransport::simulated(Simulator::new());
+    mock.set_report_lengths(vec![ReportId::Long]);
+    let mut device = open(&mock);
+
+    device.ping(0x5A).unwrap();
+    assert!(mock.written().iter().all(|frame| frame[0] == 0x11));
+}
+
+#[test]
+fn maps_cable_mode_to_the_wireless_product_id() {
+    let mock = MockTransport::new();
+    let mut device = Device::builder()
+        .transport(mock.clone())
+        .vid(0x046d)
+        .pid(0xC088)
+        .open()
+        .unwrap();
+
+    assert!(device.is_cable_mode());
+    assert_eq!(device.wireless_product_id(), Some(0x4079));
+    assert_eq!(device.cable_product_id(), Some(0xC088));
+}
+
+#[test]
+fn rejects_reserved_bits_in_strict_mode() {
+    let mock = MockTransport::new();
+    with_battery_at_index_5(&mock);
+    // getCapabilities with reserved bit 3 of the flags set
+    mock.expect(
+        &[0x10, 0x01, 0x05, 0x01],
+        &long([0x11, 0x01, 0x05, 0x01], &[0x0F, 0x0B]),
+    );
+    mock.expect(
+        &[0x10, 0x01, 0x05, 0x11],
+        &long([0x11, 0x01, 0x05, 0x11], &[55, 0x04, 0x00, 0x00]),
+    );
+
+    let mut device = open(&mock);
+    assert_eq!(device.get_battery().unwrap().percentage, 55);
+
+    let mut device = Device::builder()
+        .transport(mock.clone())
+        .strict(true)
+        .open()
+        .unwrap();
+    assert!(device.get_battery().is_err());
+}
+
+#[test]
+fn drives_the_devices_paired_to_a_receiver() {
+    let mock = MockTransport::simulated(Simulator::new());
+    // receiver info pages of slots 1 and 2, the simulator rejects the others
+    for page in [0x20, 0x21] {
+        mock.expect(
+            &[0x10, 0xFF, 0x83, 0xB5, page],
+            &long([0x11, 0xFF, 0x83, 0xB5], &[page, 0x08, 0x40, 0x8A]),
+        );
+    }
+    let mut receiver = Receiver::from_transport(mock.clone(), 0x046d, 0xc52b).unwrap();
+    let mut devices = receiver.paired_devices().unwrap();
+    assert_eq!(
+        devices.iter().map(|(slot, _)| *slot).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    assert!(receiver.device_in(1).is_err());
+
+    // a notification from slot 1 read by slot 2 waits for slot 1
+    let event = mock
+        .with_simulator(|simulator| simulator.set_battery(30, false))
+        .unwrap();
+    devices[0].1.init().unwrap();
+    mock.push_event(&event);
+    devices[1].1.ping(0x5A).unwrap();
+    match devices[0].1.next_event(Duration::ZERO).unwrap() {
+        Some(Event::Battery(battery)) => assert_eq!(battery.percentage, 30),
+        other => panic!("expected a battery event, got {:?}", other),
+    }
+}
+
+#[test]
+fn bounds_the_reports_queued_for_a_slot_nobody_reads() {
+    let mock = MockTransport::new();
+    for page in [0x20, 0x21] {
+        mock.expect(
+            &[0x10, 0xFF, 0x83, 0xB5, page],
+            &long([0x11, 0xFF, 0x83, 0xB5], &[page, 0x08, 0x40, 0x8A]),
+        );
+    }
+    let receiver = Receiver::from_transport(mock.clone(), 0x046d, 0xc52b).unwrap();
+    let mut first = receiver.device_in(1).unwrap();
+    let mut second = receiver.device_in(2).unwrap();
+
+    // slot 2 keeps reporting its link while only slot 1 is read
+    for _ in 0..1100 {
+        mock.push_event(&[0x10, 0x02, 0x41, 0x04, 0x61, 0x8A, 0x40]);
+    }
+    for _ in 0..1100 {
+        assert!(first.next_event(Duration::ZERO).unwrap().is_none());
+    }
+
+    let mut queued = 0;
+    while second.next_event(Duration::ZERO).unwrap().is_some() {
+        queued += 1;
+    }
+    assert_eq!(queued, 1024);
+}
+
+#[test]
+fn closes_the_pairing_lock_when_cancelled() {
+    let mock = MockTransport::new();
+    for action in [0x01, 0x02] {
+        mock.expect(
+            &[0x10, 0xFF, 0x80, 0xB2, action],
+            &[0x10, 0xFF, 0x80, 0xB2, 0x00, 0x00, 0x00],
+        );
+    }
+    let mut receiver = Receiver::from_transport(mock.clone(), 0x046d, 0xc52b).unwrap();
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+
+    let mut events = Vec::new();
+    let result = receiver.pair_until(
+        Duration::from_secs(30),
+        |event| events.push(event.clone()),
+        &cancel,
+    );
+    assert!(result.is_err());
+    assert_eq!(events, [PairingEvent::Failed(PairingError::Cancelled)]);
+    let written = mock.written();
+    assert_eq!(written.last().unwrap()[..5], [0x10, 0xFF, 0x80, 0xB2, 0x02]);
+}
+
+#[test]
+fn times_out_events_and_pairing_on_the_device_clock() {
+    let clock = ManualClock::new();
+    let mock = MockTransport::new();
+    mock.set_clock(Arc::new(clock.clone()));
+    let mut device = Device::builder()
+        .transport(mock.clone())
+        .clock(Arc::new(clock.clone()))
+        .open()
+        .unwrap();
+    assert!(device.next_event(Duration::from_secs(5)).unwrap().is_none());
+    assert_eq!(clock.elapsed(), Duration::from_secs(5));
+
+    for action in [0x01, 0x02] {
+        mock.expect(
+            &[0x10, 0xFF, 0x80, 0xB2, action],
+            &[0x10, 0xFF, 0x80, 0xB2, 0x00, 0x00, 0x00],
+        );
+    }
+    let mut receiver =
+        Receiver::from_transport_with_clock(mock, 0x046d, 0xc52b, Arc::new(clock.clone())).unwrap();
+    let start = clock.elapsed();
+    let mut events = Vec::new();
+    let result = receiver.pair(Duration::from_secs(30), |event| events.push(event.clone()));
+    assert!(result.is_err());
+    assert_eq!(events, [PairingEvent::Failed(PairingError::Timeout)]);
+    // the lock's timeout and a moment for the receiver's notification
+    assert_eq!(clock.elapsed() - start, Duration::from_secs(32));
+}
+
+#[test]
+fn accesses_hidpp10_registers() {
+    let mock = MockTransport::new();
+    // notification flags: battery status and wireless notifications on
+    mock.expect(
+        &[0x10, 0xFF, 0x81, 0x00],
+        &[0x10, 0xFF, 0x81, 0x00, 0x10, 0x01, 0x00],
+    );
+    mock.expect(
+        &[0x11, 0xFF, 0x82, 0xB5],
+        &long([0x11, 0xFF, 0x82, 0xB5], &[]),
+    );
+    let mut device = open(&mock);
+
+    assert_eq!(
+        device.read_register(0xFF, 0x00, &[]).unwrap(),
+        [0x10, 0x01, 0x00]
+    );
+    device.write_long_register(0xFF, 0xB5, &[0x42; 16]).unwrap();
+    assert_eq!(mock.written()[1][..5], [0x11, 0xFF, 0x82, 0xB5, 0x42]);
+    assert!(device.write_register(0xFF, 0x00, &[0; 4]).is_err());
+}
+
+#[test]
+fn decodes_hidpp10_frames_only_from_receivers_and_hidpp10_devices() {
+    // a HID++ 2.0 notification on index 0x07, which isn't resolved yet
+    let notification = long([0x11, 0x01, 0x07, 0x00], &[0x05, 0x21]);
+
+    let mock = MockTransport::simulated(Simulator::new());
+    let mut device = open(&mock);
+    mock.push_event(&notification);
+    assert!(matches!(
+        device.next_event(Duration::ZERO).unwrap(),
+        Some(Event::Unknown(_))
+    ));
+
+    // a HID++ 1.0 device rejects getProtocolVersion with an invalid sub id
+    let mock = MockTransport::new();
+    mock.expect(
+        &[0x10, 0x01, 0x00, 0x11],
+        &[0x10, 0x01, 0x8F, 0x00, 0x11, 0x01, 0x00],
+    );
+    let mut device = open(&mock);
+    mock.push_event(&[0x10, 0x01, 0x07, 0x00, 0x05, 0x21, 0x00]);
+    match device.next_event(Duration::ZERO).unwrap() {
+        Some(Event::Battery(battery)) => assert_eq!(battery.status, BatteryStatus::Recharging),
+        other => panic!("expected a battery event, got {:?}", other),
+    }
+
+    // link notifications from a receiver, whatever the device speaks
+    let mock = MockTransport::simulated(Simulator::new());
+    let mut device = Device::builder()
+        .transport(mock.clone())
+        .vid(0x046d)
+        .pid(0xC52B)
+        .open()
+        .unwrap();
+    mock.push_event(
+        &encode_notification(0x01, &Event::LinkQuality(40))
+            .unwrap()
+            .to_bytes(),
+    );
+    assert!(matches!(
+        device.next_event(Duration::ZERO).unwrap(),
+        Some(Event::LinkQuality(40))
+    ));
+}
+
+#[test]
+fn ignores_truncated_raw_xy_reports() {
+    let mock = MockTransport::new();
+    mock.expect(
+        &[0x10, 0x01, 0x00, 0x01, 0x1B, 0x04],
+        &long([0x11, 0x01, 0x00, 0x01], &[0x05]),
+    );
+    let mut device = open(&mock);
+    device.feature_index(Feature::ReprogControlsV4).unwrap();
+
+    // diverted raw XY needs 4 bytes, a short report only carries 3
+    mock.push_event(&[0x10, 0x01, 0x05, 0x10, 0x00, 0x10, 0xFF]);
+    assert!(matches!(
+        device.next_event(Duration::ZERO).unwrap(),
+        Some(Event::Unknown(_))
+    ));
+    mock.push_event(&long([0x11, 0x01, 0x05, 0x10], &[0x00, 0x10, 0xFF, 0xF0]));
+    assert!(matches!(
+        device.next_event(Duration::ZERO).unwrap(),
+        Some(Event::RawXY { dx: 16, dy: -16 })
+    ));
+}
+
+#[test]
+fn applies_quirks_of_the_device_behind_a_receiver() {
+    let mock = MockTransport::new();
+    // receiver info for slot 1: a G903 Hero
+    mock.expect(
+        &[0x10, 0xFF, 0x83, 0xB5, 0x20],
+        &long([0x11, 0xFF, 0x83, 0xB5], &[0x20, 0x00, 0x00, 0x40, 0x87]),
+    );
+    let device = Device::builder()
+        .transport(mock.clone())
+        .vid(0x046d)
+        .pid(0xc539)
+        .device_index(0x01)
+        .open()
+        .unwrap();
+    assert!(device.quirks().battery_voltage_only);
+
+    // the same mouse plugged in with its cable
+    let device = Device::builder()
+        .transport(MockTransport::new())
+        .vid(0x046d)
+        .pid(0xC091)
+        .open()
+        .unwrap();
+    assert!(device.quirks().battery_voltage_only);
+}
+
+fn profiles_info() -> ProfilesInfo {
+    ProfilesInfo {
+        memory_model: 1,
+        profile_format: 3,
+        macro_format: 1,
+        profile_count: 3,
+        profile_count_oob: 0,
+        button_count: 8,
+        sector_count: 16,
+        sector_size: 255,
+        mechanical_layout: 0,
+        various_info: 0,
+    }
+}
+
+#[test]
+fn stops_writing_profiles_once_cancelled() {
+    let profile = ProfileTemplate::new(&profiles_info())
+        .name("Work")
+        .build()
+        .unwrap();
+    let mock = MockTransport::new();
+    let mut device = open(&mock);
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+
+    let profiles = [(1, profile.clone()), (2, profile.clone())];
+    assert!(device.update_profiles(&profiles, &cancel).is_err());
+    assert!(device.write_profile(1, &profile, &cancel).is_err());
+    assert!(mock.written().is_empty());
+}
+
+#[test]
+fn asks_for_led_persistence_once() {
+    let mock = MockTransport::new();
+    // RgbEffects at index 7, able to keep effects in RAM
+    mock.expect(
+        &[0x10, 0x01, 0x00, 0x01, 0x80, 0x71],
+        &long([0x11, 0x01, 0x00, 0x01], &[0x07]),
+    );
+    mock.expect(
+        &[0x10, 0x01, 0x07, 0x01],
+        &long([0x11, 0x01, 0x07, 0x01], &[0xFF, 0xFF, 0x00, 0x00, 0x01]),
+    );
+    mock.expect(
+        &[0x11, 0x01, 0x07, 0x11],
+        &long([0x11, 0x01, 0x07, 0x11], &[]),
+    );
+    let zone = LedZone {
+        index: 0,
+        location: LedZoneLocation::Primary,
+        effects: vec![LedEffect::Off, LedEffect::Static { color: [0; 3] }],
+    };
+
+    let mut device = open(&mock);
+    for color in [[0xFF, 0, 0], [0, 0xFF, 0], [0, 0, 0xFF]] {
+        device
+            .set_led_effect(&zone, LedEffect::Static { color }, LedPersistence::Volatile)
+            .unwrap();
+    }
+    let written = mock.written();
+    let infos = written.iter().filter(|frame| frame[2..4] == [0x07, 0x01]);
+    assert_eq!(infos.count(), 1);
+    // kept in RAM
+    let effects = written
+        .iter()
+        .filter(|frame| frame[2..4] == [0x07, 0x11])
+        .collect::<Vec<_>>();
+    assert_eq!(effects.len(), 3);
+    assert!(effects.iter().all(|frame| frame[4 + 2 + 10] == 0x00));
+}
+
+// OnboardProfiles at index 6 with 255 byte sectors, each read answered
+// from `sector`
+fn with_profile_sector(mock: &MockTransport, sector: &[u8]) {
+    mock.expect(
+        &[0x10, 0x01, 0x00, 0x01, 0x81, 0x00],
+        &long([0x11, 0x01, 0x00, 0x01], &[0x06]),
+    );
+    mock.expect(
+        &[0x10, 0x01, 0x06, 0x01],
+        &long(
+            [0x11, 0x01, 0x06, 0x01],
+            &[1, 3, 1, 3, 0, 8, 0, 16, 0, 255, 0, 0],
+        ),
+    );
+    for offset in (0..sector.len() - 16)
+        .step_by(16)
+        .chain([sector.len() - 16])
+    {
+        let [high, low] = (offset as u16).to_be_bytes();
+        mock.expect(
+            &[0x11, 0x01, 0x06, 0x51, 0x00, 0x01, high, low],
+            &long([0x11, 0x01, 0x06, 0x51], &sector[offset..offset + 16]),
+        );
+    }
+    for (report_id, function) in [(0x11, 0x61), (0x11, 0x71), (0x10, 0x81)] {
+        mock.expect(
+            &[report_id, 0x01, 0x06, function],
+            &long([0x11, 0x01, 0x06, function], &[]),
+        );
+    }
+}
+
+fn profile_writes(mock: &MockTransport) -> usize {
+    mock.written()
+        .iter()
+        .filter(|frame| frame[2] == 0x06 && (0x61..=0x81).contains(&frame[3]))
+        .count()
+}
+
+#[test]
+fn diffs_profiles_field_by_field() {
+    let profile = ProfileTemplate::new(&profiles_info()).build().unwrap();
+    let mut other = profile.clone();
+    assert!(profile.diff(&other).is_empty());
+
+    other.write_count += 1;
+    assert!(profile.diff(&other).is_empty());
+
+    other.name = "Play".to_string();
+    other.dpi_slots = vec![400, 800];
+    assert_eq!(
+        profile.diff(&other),
+        vec![ProfileField::DpiSlots, ProfileField::Name]
+    );
+}
+
+#[test]
+fn only_rewrites_profiles_that_changed_or_are_corrupt() {
+    let profile = ProfileTemplate::new(&profiles_info())
+        .name("Work")
+        .build()
+        .unwrap();
+    let sector = profile.to_bytes().unwrap();
+    let cancel = CancellationToken::new();
+
+    let mock = MockTransport::new();
+    with_profile_sector(&mock, &sector);
+    let mut device = open(&mock);
+    assert!(!device.update_profile(1, &profile, &cancel).unwrap());
+    assert_eq!(profile_writes(&mock), 0);
+
+    let mut renamed = profile.clone();
+    renamed.name = "Play".to_string();
+    assert!(device.update_profile(1, &renamed, &cancel).unwrap());
+    assert!(profile_writes(&mock) > 0);
+
+    let mut corrupt = sector.clone();
+    corrupt[0] ^= 0xFF;
+    let mock = MockTransport::new();
+    with_profile_sector(&mock, &corrupt);
+    let mut device = open(&mock);
+    assert!(device.update_profile(1, &profile, &cancel).unwrap());
+    assert!(profile_writes(&mock) > 0);
+}
+
+#[test]
+fn doesnt_rewrite_profiles_it_failed_to_read() {
+    let profile = ProfileTemplate::new(&profiles_info()).build().unwrap();
+    let mock = MockTransport::new();
+    with_profile_sector(&mock, &profile.to_bytes().unwrap());
+    // sector reads go unanswered
+    let mut device = Device::builder()
+        .transport(mock.clone())
+        .timeout(Duration::from_millis(1))
+        .open()
+        .unwrap();
+    assert!(device
+        .update_profile(2, &profile, &CancellationToken::new())
+        .is_err());
+    assert_eq!(profile_writes(&mock), 0);
+}
+
+#[test]
+fn waits_for_the_updated_device_on_the_device_clock() {
+    let clock = ManualClock::new();
+    let open = || {
+        Device::builder()
+            .transport(MockTransport::new())
+            .clock(Arc::new(clock.clone()))
+            .open()
+            .unwrap()
+    };
+
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    assert!(open()
+        .reopen_after_update(None, Duration::from_secs(5), &cancel)
+        .is_err());
+    assert_eq!(clock.elapsed(), Duration::ZERO);
+
+    let start = clock.elapsed();
+    let result =
+        open().reopen_after_update(None, Duration::from_secs(1), &CancellationToken::new());
+    assert!(result.is_err());
+    assert!(clock.elapsed() - start >= Duration::from_secs(1));
+}
+
+// ReprogControlsV4 at index 5 with a single remappable control, Back,
+// performing the action of `target`
+fn with_back_button_remapped_to(mock: &MockTransport, target: u16) {
+    mock.expect(
+        &[0x10, 0x01, 0x00, 0x01, 0x1B, 0x04],
+        &long([0x11, 0x01, 0x00, 0x01], &[0x05]),
+    );
+    mock.expect(
+        &[0x10, 0x01, 0x05, 0x01],
+        &long([0x11, 0x01, 0x05, 0x01], &[0x01]),
+    );
+    mock.expect(
+        &[0x10, 0x01, 0x05, 0x11, 0x00],
+        &long([0x11, 0x01, 0x05, 0x11], &[0x00, 0x53, 0x00, 0x00, 0x10]),
+    );
+    let [high, low] = target.to_be_bytes();
+    mock.expect(
+        &[0x10, 0x01, 0x05, 0x21, 0x00, 0x53],
+        &long([0x11, 0x01, 0x05, 0x21], &[0x00, 0x53, 0x00, high, low]),
+    );
+    mock.expect(
+        &[0x11, 0x01, 0x05, 0x31],
+        &long([0x11, 0x01, 0x05, 0x31], &[0x00, 0x53, 0x00, 0x00, 0x53]),
+    );
+}
+
+#[test]
+fn copying_settings_resets_remaps_the_source_doesnt_have() {
+    let (src_mock, dst_mock) = (MockTransport::new(), MockTransport::new());
+    with_back_button_remapped_to(&src_mock, 0x0000);
+    with_back_button_remapped_to(&dst_mock, 0x0056);
+    let (mut src, mut dst) = (open(&src_mock), open(&dst_mock));
+
+    let (mouse, _) = copy_settings(&mut src, &mut dst).unwrap();
+    let back = Cid::from(0x0053);
+    assert_eq!(mouse.button_mappings.get(&back), Some(&back));
+    let reset = long([0x11, 0x01, 0x05, 0x31], &[0x00, 0x53, 0x00, 0x00, 0x53]);
+    assert!(dst_mock.written().contains(&reset));
+}
+
+#[test]
+fn picks_the_platform_descriptor_of_the_host_os() {
+    let mock = MockTransport::new();
+    mock.expect(
+        &[0x10, 0x01, 0x00, 0x01, 0x45, 0x31],
+        &long([0x11, 0x01, 0x00, 0x01], &[0x06]),
+    );
+    // setting the platform allowed, three descriptors
+    mock.expect(
+        &[0x10, 0x01, 0x06, 0x01],
+        &long([0x11, 0x01, 0x06, 0x01], &[0x02, 0x00, 0x03]),
+    );
+    // Windows and Windows Embedded, Android, then macOS and iOS
+    for (index, mask) in [0x0300u16, 0x1000, 0x6000].into_iter().enumerate() {
+        let [high, low] = mask.to_be_bytes();
+        mock.expect(
+            &[0x10, 0x01, 0x06, 0x11, index as u8],
+            &long(
+                [0x11, 0x01, 0x06, 0x11],
+                &[index as u8, index as u8, high, low],
+            ),
+        );
+    }
+    mock.expect(
+        &[0x10, 0x01, 0x06, 0x31],
+        &long([0x11, 0x01, 0x06, 0x31], &[]),
+    );
+    let mut device = open(&mock);
+
+    device
+        .apply_host_defaults(&HostDefaults::for_os(HostOs::Android))
+        .unwrap();
+    let descriptors = device.get_platform_descriptors().unwrap();
+    assert!(descriptors[0].supports(HostOs::WinEmb));
+    assert!(!descriptors[1].supports(HostOs::Ios));
+    assert!(descriptors[2].supports(HostOs::Ios));
+    let set = mock
+        .written()
+        .into_iter()
+        .find(|report| report.starts_with(&[0x10, 0x01, 0x06, 0x31]))
+        .unwrap();
+    assert_eq!(set[4..6], [0xFF, 0x01]);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn lets_requests_through_while_events_stream() {
+    let mock = MockTransport::simulated(Simulator::new());
+    let mut device = open(&mock);
+    device.init().unwrap();
+    for percentage in [30, 20, 10] {
+        let event = mock
+            .with_simulator(|simulator| simulator.set_battery(percentage, false))
+            .unwrap();
+        mock.push_event(&event);
+    }
+    let device = hidpp::AsyncDevice::new(device);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let mut events = device.events().await;
+        match events.recv().await {
+            Some(Ok(Event::Battery(battery))) => assert_eq!(battery.percentage, 30),
+            other => panic!("expected a battery event, got {:?}", other),
+        }
+        assert_eq!(device.get_battery().await.unwrap().percentage, 10);
+    });
+}
@@ -0,0 +1,78 @@
+#![cfg(feature = "json")]
+
+use hidpp::{
+    ButtonAction, ButtonFunction, JsonProfile, ProfileConfig, ProfileTemplate, ProfilesInfo,
+};
+
+const G502: &str = include_str!("profiles/g502.json");
+
+fn template() -> ProfileConfig {
+    let info = ProfilesInfo {
+        memory_model: 1,
+        profile_format: 3,
+        macro_format: 1,
+        profile_count: 5,
+        profile_count_oob: 3,
+        button_count: 8,
+        sector_count: 16,
+        sector_size: 255,
+        mechanical_layout: 0x0A,
+        various_info: 0,
+    };
+    ProfileTemplate::new(&info).led_zones(1).build().unwrap()
+}
+
+#[test]
+fn imports_a_json_profile() {
+    let json = JsonProfile::from_json(G502).unwrap();
+    let mut profile = template();
+    json.apply_to(&mut profile).unwrap();
+
+    assert_eq!(profile.name, "FPS");
+    assert_eq!(profile.report_rate, 1);
+    // the shift level reuses the 800 DPI slot instead of taking a fourth
+    assert_eq!(profile.dpi_slots, [800, 1600, 3200]);
+    assert_eq!(profile.default_dpi_slot, 1);
+    assert_eq!(profile.shift_dpi_slot, Some(0));
+    assert_eq!(profile.color, [0, 160, 255]);
+    assert_eq!(
+        profile.buttons[3],
+        ButtonAction::Key {
+            modifiers: 0x01,
+            key: 0x06
+        }
+    );
+    assert_eq!(profile.buttons[4], ButtonAction::Consumer(0x00E9));
+    assert_eq!(
+        profile.buttons[5],
+        ButtonAction::Function(ButtonFunction::ShiftDpi)
+    );
+    assert_eq!(profile.buttons[6], ButtonAction::Disabled);
+    assert_eq!(
+        profile.gshift_buttons[3],
+        ButtonAction::Key {
+            modifiers: 0x01,
+            key: 0x19
+        }
+    );
+    assert_eq!(profile.gshift_buttons[0], ButtonAction::Unassigned);
+}
+
+#[test]
+fn round_trips_through_json() {
+    let mut profile = template();
+    JsonProfile::from_json(G502)
+        .unwrap()
+        .apply_to(&mut profile)
+        .unwrap();
+
+    let json = JsonProfile::from(&profile).to_json().unwrap();
+    let exported = JsonProfile::from_json(&json).unwrap();
+    // the shift level comes back as its own level with the slot's DPI
+    assert_eq!(exported.dpi_levels.len(), 4);
+    assert!(exported.dpi_levels[3].shift && exported.dpi_levels[3].dpi == 800);
+
+    let mut reimported = template();
+    exported.apply_to(&mut reimported).unwrap();
+    assert_eq!(reimported, profile);
+}
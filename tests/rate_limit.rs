@@ -0,0 +1,20 @@
+use std::{sync::Arc, time::Duration};
+
+use hidpp::{ManualClock, RateLimiter};
+
+#[test]
+fn spaces_requests_on_its_clock() {
+    let clock = ManualClock::new();
+    let limiter =
+        RateLimiter::new(Duration::from_millis(100), 1).with_clock(Arc::new(clock.clone()));
+
+    drop(limiter.acquire());
+    assert_eq!(clock.elapsed(), Duration::ZERO);
+    drop(limiter.acquire());
+    assert_eq!(clock.elapsed(), Duration::from_millis(100));
+
+    // time spent elsewhere counts towards the interval
+    clock.advance(Duration::from_millis(60));
+    drop(limiter.acquire());
+    assert_eq!(clock.elapsed(), Duration::from_millis(200));
+}
@@ -0,0 +1,332 @@
+#![cfg(all(target_os = "linux", feature = "uhid"))]
+
+use std::{path::Path, thread, time::Duration};
+
+use hidpp::{
+    copy_settings, encode_notification,
+    faults::{FaultConfig, FaultInjector},
+    sim::Simulator,
+    uhid::VirtualDevice,
+    BatteryInfo, BatteryLevel, BatteryStatus, Device, Dpi, Event,
+};
+
+const VENDOR_ID: u16 = 0x046d;
+
+// creating uhid devices needs /dev/uhid and write access to it, which CI
+// runners don't always grant
+fn uhid_available() -> bool {
+    let available = Path::new("/dev/uhid").exists()
+        && std::fs::OpenOptions::new()
+            .write(true)
+            .open("/dev/uhid")
+            .is_ok();
+    if !available {
+        eprintln!("skipping: /dev/uhid is not available");
+    }
+    available
+}
+
+fn open(product_id: u16) -> Device {
+    // the hidraw node shows up asynchronously after creation
+    for _ in 0..50 {
+        if let Ok(device) = Device::new(VENDOR_ID, product_id) {
+            return device;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!(
+        "virtual device {:04x}:{:04x} never appeared",
+        VENDOR_ID, product_id
+    );
+}
+
+#[test]
+fn discovers_features_and_reads_battery() {
+    if !uhid_available() {
+        return;
+    }
+
+    let mut simulator = Simulator::new();
+    simulator.battery_percentage = 42;
+    let _virtual_device = VirtualDevice::create(VENDOR_ID, 0xc5f0, simulator).unwrap();
+
+    let mut device = open(0xc5f0);
+    device.init().unwrap();
+    let battery = device.get_battery().unwrap();
+
+    assert_eq!(battery.percentage, 42);
+    assert!(!battery.estimated);
+    assert_eq!(battery.status, BatteryStatus::Discharging);
+}
+
+#[test]
+fn delivers_battery_events() {
+    if !uhid_available() {
+        return;
+    }
+
+    let virtual_device = VirtualDevice::create(VENDOR_ID, 0xc5f1, Simulator::new()).unwrap();
+    let mut device = open(0xc5f1);
+    device.init().unwrap();
+
+    let event = virtual_device
+        .simulator()
+        .lock()
+        .unwrap()
+        .set_battery(55, true);
+    virtual_device.send_input(&event).unwrap();
+
+    match device.next_event(Duration::from_secs(1)).unwrap() {
+        Some(Event::Battery(battery)) => {
+            assert_eq!(battery.percentage, 55);
+            assert_eq!(battery.status, BatteryStatus::Recharging);
+        }
+        other => panic!("expected a battery event, got {:?}", other),
+    }
+}
+
+#[test]
+fn streams_events_from_a_background_thread() {
+    if !uhid_available() {
+        return;
+    }
+
+    let virtual_device = VirtualDevice::create(VENDOR_ID, 0xc5f5, Simulator::new()).unwrap();
+    let mut device = open(0xc5f5);
+    device.init().unwrap();
+    let events = device.events();
+
+    let event = virtual_device
+        .simulator()
+        .lock()
+        .unwrap()
+        .set_battery(30, false);
+    virtual_device.send_input(&event).unwrap();
+
+    match events.recv_timeout(Duration::from_secs(1)) {
+        Some(Ok(Event::Battery(battery))) => assert_eq!(battery.percentage, 30),
+        other => panic!("expected a battery event, got {:?}", other),
+    }
+    assert!(events.try_recv().is_none());
+
+    // the device is usable again once the reader stops
+    let mut device = events.stop().unwrap();
+    assert_eq!(device.get_battery().unwrap().percentage, 30);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn reads_battery_and_events_asynchronously() {
+    if !uhid_available() {
+        return;
+    }
+
+    let virtual_device = VirtualDevice::create(VENDOR_ID, 0xc5f6, Simulator::new()).unwrap();
+    let device = hidpp::AsyncDevice::new(open(0xc5f6));
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        device.init().await.unwrap();
+        let mut events = device.events().await;
+
+        let event = virtual_device
+            .simulator()
+            .lock()
+            .unwrap()
+            .set_battery(64, false);
+        virtual_device.send_input(&event).unwrap();
+        match events.recv().await {
+            Some(Ok(Event::Battery(battery))) => assert_eq!(battery.percentage, 64),
+            other => panic!("expected a battery event, got {:?}", other),
+        }
+
+        // requests get through while the event reader runs
+        assert_eq!(device.get_battery().await.unwrap().percentage, 64);
+    });
+}
+
+#[test]
+fn copies_settings_between_units_of_a_model() {
+    if !uhid_available() {
+        return;
+    }
+
+    let mut simulator = Simulator::new();
+    simulator.dpi = 3200;
+    let _src = VirtualDevice::create(VENDOR_ID, 0xc5f7, simulator).unwrap();
+    let _dst = VirtualDevice::create(VENDOR_ID, 0xc5f8, Simulator::new()).unwrap();
+    let mut src = open(0xc5f7);
+    let mut dst = open(0xc5f8);
+    src.init().unwrap();
+    dst.init().unwrap();
+
+    let (mouse, _) = copy_settings(&mut src, &mut dst).unwrap();
+    assert_eq!(mouse.dpi, Some(3200));
+    assert_eq!(dst.get_dpi(0).unwrap().0, 3200);
+
+    let mut simulator = Simulator::new();
+    simulator.name = "Another Mouse".to_string();
+    let _other = VirtualDevice::create(VENDOR_ID, 0xc5f9, simulator).unwrap();
+    let mut other = open(0xc5f9);
+    other.init().unwrap();
+    assert!(copy_settings(&mut src, &mut other).is_err());
+}
+
+#[test]
+fn rounds_dpi_to_supported_values() {
+    if !uhid_available() {
+        return;
+    }
+
+    let _virtual_device = VirtualDevice::create(VENDOR_ID, 0xc5f2, Simulator::new()).unwrap();
+    let mut device = open(0xc5f2);
+    device.init().unwrap();
+
+    let capabilities = device.dpi_capabilities(0).unwrap();
+    assert_eq!(capabilities.values(), vec![400, 800, 1600, 3200]);
+    assert!(Dpi::exact(1700, &capabilities).is_err());
+
+    device.set_dpi(0, Dpi::new(1700, &capabilities)).unwrap();
+    assert_eq!(device.get_dpi(0).unwrap().0, 1600);
+    device.set_dpi(0, Dpi::new(9000, &capabilities)).unwrap();
+    assert_eq!(device.get_dpi(0).unwrap().0, 3200);
+}
+
+#[test]
+fn ignores_hidpp10_notifications_from_hidpp20_devices() {
+    if !uhid_available() {
+        return;
+    }
+
+    let virtual_device = VirtualDevice::create(VENDOR_ID, 0xc5f3, Simulator::new()).unwrap();
+    let mut device = open(0xc5f3);
+
+    // the simulated mouse speaks HID++ 2.0 and isn't behind a receiver, so
+    // these are notifications on feature indexes it hasn't resolved
+    let events = [
+        Event::LinkQuality(73),
+        Event::Battery(BatteryInfo {
+            percentage: 64,
+            estimated: false,
+            level: BatteryLevel::Good,
+            status: BatteryStatus::Recharging,
+            voltage: None,
+        }),
+    ];
+    for event in &events {
+        let message = encode_notification(0x01, event).unwrap();
+        virtual_device.send_input(&message.to_bytes()).unwrap();
+    }
+
+    for _ in &events {
+        match device.next_event(Duration::from_secs(1)).unwrap() {
+            Some(Event::Unknown(_)) => {}
+            other => panic!("expected an unknown event, got {:?}", other),
+        }
+    }
+    assert!(device.link_quality().estimated);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn reads_headset_battery_from_transcript() {
+    if !uhid_available() {
+        return;
+    }
+
+    // a G935 on its dongle: answers on index 0xFF with long reports only,
+    // and reports the battery through AdcMeasurement
+    let transcript = hidpp::replay::load_session(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/transcripts/g935_battery.jsonl"
+    ))
+    .unwrap();
+    let _virtual_device =
+        VirtualDevice::create(VENDOR_ID, 0x0a87, Simulator::from_transcript(transcript)).unwrap();
+    let mut device = open(0x0a87);
+    assert_eq!(device.device_index(), 0xFF);
+
+    let battery = device.get_battery().unwrap();
+    assert_eq!(battery.voltage, Some(4000));
+    assert_eq!(battery.percentage, 81);
+    assert!(battery.estimated);
+    assert_eq!(battery.status, BatteryStatus::Recharging);
+}
+
+#[test]
+fn recovers_from_link_faults() {
+    if !uhid_available() {
+        return;
+    }
+
+    let virtual_device = VirtualDevice::create(VENDOR_ID, 0xc5f4, Simulator::new()).unwrap();
+    let mut device = open(0xc5f4);
+    device.init().unwrap();
+    let capabilities = device.dpi_capabilities(0).unwrap();
+
+    virtual_device.set_faults(Some(FaultInjector::new(FaultConfig {
+        drop: 0.05,
+        duplicate: 0.05,
+        reorder: 0.05,
+        corrupt: 0.05,
+        max_delay: Duration::from_millis(5),
+        seed: 0x5eed,
+    })));
+    for dpi in [400, 3200, 800, 1600].repeat(10) {
+        device
+            .set_dpi(0, Dpi::exact(dpi, &capabilities).unwrap())
+            .unwrap();
+        assert_eq!(device.get_dpi(0).unwrap().0, dpi);
+        assert_eq!(device.get_battery().unwrap().percentage, 80);
+    }
+
+    let faults = virtual_device.fault_stats().unwrap();
+    assert!(faults.dropped > 0 && faults.duplicated > 0);
+    assert!(faults.reordered > 0 && faults.corrupted > 0);
+    let stats = device.stats();
+    assert!(stats.retries > 0);
+    assert!(stats.discarded > 0);
+}
+
+#[test]
+fn enumerates_hidpp_devices() {
+    if !uhid_available() {
+        return;
+    }
+
+    let _virtual_device = VirtualDevice::create(VENDOR_ID, 0xc5fa, Simulator::new()).unwrap();
+    let mut found = None;
+    for _ in 0..50 {
+        found = hidpp::enumerate()
+            .unwrap()
+            .into_iter()
+            .find(|info| info.product_id == 0xc5fa);
+        if found.is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let info = found.expect("virtual device was never enumerated");
+    assert!(!info.receiver);
+    assert_eq!(info.name, "Simulated Mouse");
+    let mut device = info.open().unwrap();
+    device.ping(0x42).unwrap();
+}
+
+#[test]
+fn opens_the_device_a_predicate_picks() {
+    if !uhid_available() {
+        return;
+    }
+
+    let _first = VirtualDevice::create(VENDOR_ID, 0xc5fb, Simulator::new()).unwrap();
+    let _second = VirtualDevice::create(VENDOR_ID, 0xc5fc, Simulator::new()).unwrap();
+    open(0xc5fb);
+    open(0xc5fc);
+
+    let device = Device::open_where(|info| info.product_id == 0xc5fc).unwrap();
+    assert_eq!(device.product_id(), 0xc5fc);
+    assert!(Device::open_where(|info| matches!(info.product_id, 0xc5fb | 0xc5fc)).is_err());
+}